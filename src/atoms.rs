@@ -0,0 +1,519 @@
+// Tag and attribute names known at compile time, the dom interns these
+// up front so the parser can match names by id instead of by string.
+// The table must stay sorted, lookups are a binary search over it.
+macro_rules! atoms {
+    ($($atom:ident: $name:literal,)*) => {
+        #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+        #[repr(usize)]
+        enum Atom {
+            $($atom,)*
+        }
+
+        $(
+            #[allow(dead_code)]
+            pub const $atom: usize = Atom::$atom as usize + FIRST_ATOM;
+        )*
+
+        pub static NAMES: &[&str] = &[$($name,)*];
+    };
+}
+
+/// The id of the first atom, id 0 is the empty string
+pub const FIRST_ATOM: usize = 1;
+
+atoms! {
+    A: "a",
+    ABBR: "abbr",
+    ACCEPT: "accept",
+    ACCEPT_CHARSET: "accept-charset",
+    ACCESSKEY: "accesskey",
+    ACRONYM: "acronym",
+    ACTION: "action",
+    ADDRESS: "address",
+    ALIGN: "align",
+    ALT: "alt",
+    ALT_GLYPH: "altGlyph",
+    ALT_GLYPH_DEF: "altGlyphDef",
+    ALT_GLYPH_ITEM: "altGlyphItem",
+    ALTGLYPH: "altglyph",
+    ALTGLYPHDEF: "altglyphdef",
+    ALTGLYPHITEM: "altglyphitem",
+    ANIMATE_COLOR: "animateColor",
+    ANIMATE_MOTION: "animateMotion",
+    ANIMATE_TRANSFORM: "animateTransform",
+    ANIMATECOLOR: "animatecolor",
+    ANIMATEMOTION: "animatemotion",
+    ANIMATETRANSFORM: "animatetransform",
+    ANNOTATION_XML: "annotation-xml",
+    APPLET: "applet",
+    AREA: "area",
+    ARTICLE: "article",
+    ASIDE: "aside",
+    ASYNC: "async",
+    ATTRIBUTE_NAME: "attributeName",
+    ATTRIBUTE_TYPE: "attributeType",
+    ATTRIBUTENAME: "attributename",
+    ATTRIBUTETYPE: "attributetype",
+    AUDIO: "audio",
+    AUTOCOMPLETE: "autocomplete",
+    AUTOFOCUS: "autofocus",
+    B: "b",
+    BASE: "base",
+    BASE_FREQUENCY: "baseFrequency",
+    BASE_PROFILE: "baseProfile",
+    BASEFONT: "basefont",
+    BASEFREQUENCY: "basefrequency",
+    BASEPROFILE: "baseprofile",
+    BDI: "bdi",
+    BDO: "bdo",
+    BGCOLOR: "bgcolor",
+    BGSOUND: "bgsound",
+    BIG: "big",
+    BLINK: "blink",
+    BLOCKQUOTE: "blockquote",
+    BODY: "body",
+    BORDER: "border",
+    BR: "br",
+    BUTTON: "button",
+    CALC_MODE: "calcMode",
+    CALCMODE: "calcmode",
+    CANVAS: "canvas",
+    CAPTION: "caption",
+    CENTER: "center",
+    CHARSET: "charset",
+    CHECKED: "checked",
+    CIRCLE: "circle",
+    CITE: "cite",
+    CLASS: "class",
+    CLIP_PATH: "clipPath",
+    CLIP_PATH_UNITS: "clipPathUnits",
+    CLIPPATH: "clippath",
+    CLIPPATHUNITS: "clippathunits",
+    CODE: "code",
+    COL: "col",
+    COLGROUP: "colgroup",
+    COLOR: "color",
+    COLS: "cols",
+    COLSPAN: "colspan",
+    CONTENT: "content",
+    CONTENTEDITABLE: "contenteditable",
+    COORDS: "coords",
+    CROSSORIGIN: "crossorigin",
+    CX: "cx",
+    CY: "cy",
+    D: "d",
+    DATA: "data",
+    DATALIST: "datalist",
+    DATETIME: "datetime",
+    DD: "dd",
+    DEFER: "defer",
+    DEFINITION_URL: "definitionURL",
+    DEFINITIONURL: "definitionurl",
+    DEFS: "defs",
+    DEL: "del",
+    DESC: "desc",
+    DETAILS: "details",
+    DFN: "dfn",
+    DIALOG: "dialog",
+    DIFFUSE_CONSTANT: "diffuseConstant",
+    DIFFUSECONSTANT: "diffuseconstant",
+    DIR: "dir",
+    DIRNAME: "dirname",
+    DISABLED: "disabled",
+    DIV: "div",
+    DL: "dl",
+    DOWNLOAD: "download",
+    DRAGGABLE: "draggable",
+    DT: "dt",
+    EDGE_MODE: "edgeMode",
+    EDGEMODE: "edgemode",
+    ELLIPSE: "ellipse",
+    EM: "em",
+    EMBED: "embed",
+    ENCODING: "encoding",
+    ENCTYPE: "enctype",
+    FACE: "face",
+    FE_BLEND: "feBlend",
+    FE_COLOR_MATRIX: "feColorMatrix",
+    FE_COMPONENT_TRANSFER: "feComponentTransfer",
+    FE_COMPOSITE: "feComposite",
+    FE_CONVOLVE_MATRIX: "feConvolveMatrix",
+    FE_DIFFUSE_LIGHTING: "feDiffuseLighting",
+    FE_DISPLACEMENT_MAP: "feDisplacementMap",
+    FE_DISTANT_LIGHT: "feDistantLight",
+    FE_DROP_SHADOW: "feDropShadow",
+    FE_FLOOD: "feFlood",
+    FE_FUNC_A: "feFuncA",
+    FE_FUNC_B: "feFuncB",
+    FE_FUNC_G: "feFuncG",
+    FE_FUNC_R: "feFuncR",
+    FE_GAUSSIAN_BLUR: "feGaussianBlur",
+    FE_IMAGE: "feImage",
+    FE_MERGE: "feMerge",
+    FE_MERGE_NODE: "feMergeNode",
+    FE_MORPHOLOGY: "feMorphology",
+    FE_OFFSET: "feOffset",
+    FE_POINT_LIGHT: "fePointLight",
+    FE_SPECULAR_LIGHTING: "feSpecularLighting",
+    FE_SPOT_LIGHT: "feSpotLight",
+    FE_TILE: "feTile",
+    FE_TURBULENCE: "feTurbulence",
+    FEBLEND: "feblend",
+    FECOLORMATRIX: "fecolormatrix",
+    FECOMPONENTTRANSFER: "fecomponenttransfer",
+    FECOMPOSITE: "fecomposite",
+    FECONVOLVEMATRIX: "feconvolvematrix",
+    FEDIFFUSELIGHTING: "fediffuselighting",
+    FEDISPLACEMENTMAP: "fedisplacementmap",
+    FEDISTANTLIGHT: "fedistantlight",
+    FEDROPSHADOW: "fedropshadow",
+    FEFLOOD: "feflood",
+    FEFUNCA: "fefunca",
+    FEFUNCB: "fefuncb",
+    FEFUNCG: "fefuncg",
+    FEFUNCR: "fefuncr",
+    FEGAUSSIANBLUR: "fegaussianblur",
+    FEIMAGE: "feimage",
+    FEMERGE: "femerge",
+    FEMERGENODE: "femergenode",
+    FEMORPHOLOGY: "femorphology",
+    FEOFFSET: "feoffset",
+    FEPOINTLIGHT: "fepointlight",
+    FESPECULARLIGHTING: "fespecularlighting",
+    FESPOTLIGHT: "fespotlight",
+    FETILE: "fetile",
+    FETURBULENCE: "feturbulence",
+    FIELDSET: "fieldset",
+    FIGCAPTION: "figcaption",
+    FIGURE: "figure",
+    FILL: "fill",
+    FILTER: "filter",
+    FILTER_UNITS: "filterUnits",
+    FILTERUNITS: "filterunits",
+    FONT: "font",
+    FOOTER: "footer",
+    FOR: "for",
+    FOREIGN_OBJECT: "foreignObject",
+    FOREIGNOBJECT: "foreignobject",
+    FORM: "form",
+    FORMACTION: "formaction",
+    FRAME: "frame",
+    FRAMESET: "frameset",
+    G: "g",
+    GLYPH_REF: "glyphRef",
+    GLYPHREF: "glyphref",
+    GRADIENT_TRANSFORM: "gradientTransform",
+    GRADIENT_UNITS: "gradientUnits",
+    GRADIENTTRANSFORM: "gradienttransform",
+    GRADIENTUNITS: "gradientunits",
+    H1: "h1",
+    H2: "h2",
+    H3: "h3",
+    H4: "h4",
+    H5: "h5",
+    H6: "h6",
+    HEAD: "head",
+    HEADER: "header",
+    HEADERS: "headers",
+    HEIGHT: "height",
+    HGROUP: "hgroup",
+    HR: "hr",
+    HREF: "href",
+    HREFLANG: "hreflang",
+    HTML: "html",
+    HTTP_EQUIV: "http-equiv",
+    I: "i",
+    ID: "id",
+    IFRAME: "iframe",
+    IMAGE: "image",
+    IMG: "img",
+    INPUT: "input",
+    INS: "ins",
+    INTEGRITY: "integrity",
+    ISINDEX: "isindex",
+    KBD: "kbd",
+    KERNEL_MATRIX: "kernelMatrix",
+    KERNEL_UNIT_LENGTH: "kernelUnitLength",
+    KERNELMATRIX: "kernelmatrix",
+    KERNELUNITLENGTH: "kernelunitlength",
+    KEY_POINTS: "keyPoints",
+    KEY_SPLINES: "keySplines",
+    KEY_TIMES: "keyTimes",
+    KEYGEN: "keygen",
+    KEYPOINTS: "keypoints",
+    KEYSPLINES: "keysplines",
+    KEYTIMES: "keytimes",
+    LABEL: "label",
+    LANG: "lang",
+    LEGEND: "legend",
+    LENGTH_ADJUST: "lengthAdjust",
+    LENGTHADJUST: "lengthadjust",
+    LI: "li",
+    LIMITING_CONE_ANGLE: "limitingConeAngle",
+    LIMITINGCONEANGLE: "limitingconeangle",
+    LINE: "line",
+    LINEAR_GRADIENT: "linearGradient",
+    LINEARGRADIENT: "lineargradient",
+    LINK: "link",
+    LIST: "list",
+    LISTING: "listing",
+    LOOP: "loop",
+    MAIN: "main",
+    MALIGNMARK: "malignmark",
+    MAP: "map",
+    MARK: "mark",
+    MARKER: "marker",
+    MARKER_HEIGHT: "markerHeight",
+    MARKER_UNITS: "markerUnits",
+    MARKER_WIDTH: "markerWidth",
+    MARKERHEIGHT: "markerheight",
+    MARKERUNITS: "markerunits",
+    MARKERWIDTH: "markerwidth",
+    MARQUEE: "marquee",
+    MASK: "mask",
+    MASK_CONTENT_UNITS: "maskContentUnits",
+    MASK_UNITS: "maskUnits",
+    MASKCONTENTUNITS: "maskcontentunits",
+    MASKUNITS: "maskunits",
+    MATH: "math",
+    MAX: "max",
+    MAXLENGTH: "maxlength",
+    MEDIA: "media",
+    MENU: "menu",
+    MENUITEM: "menuitem",
+    META: "meta",
+    METER: "meter",
+    METHOD: "method",
+    MGLYPH: "mglyph",
+    MI: "mi",
+    MIN: "min",
+    MN: "mn",
+    MO: "mo",
+    MS: "ms",
+    MTEXT: "mtext",
+    MULTIPLE: "multiple",
+    MUTED: "muted",
+    NAME: "name",
+    NAV: "nav",
+    NOBR: "nobr",
+    NOEMBED: "noembed",
+    NOFRAMES: "noframes",
+    NONCE: "nonce",
+    NOSCRIPT: "noscript",
+    NOVALIDATE: "novalidate",
+    NUM_OCTAVES: "numOctaves",
+    NUMOCTAVES: "numoctaves",
+    OBJECT: "object",
+    OL: "ol",
+    OPEN: "open",
+    OPTGROUP: "optgroup",
+    OPTION: "option",
+    OUTPUT: "output",
+    P: "p",
+    PARAM: "param",
+    PATH: "path",
+    PATH_LENGTH: "pathLength",
+    PATHLENGTH: "pathlength",
+    PATTERN: "pattern",
+    PATTERN_CONTENT_UNITS: "patternContentUnits",
+    PATTERN_TRANSFORM: "patternTransform",
+    PATTERN_UNITS: "patternUnits",
+    PATTERNCONTENTUNITS: "patterncontentunits",
+    PATTERNTRANSFORM: "patterntransform",
+    PATTERNUNITS: "patternunits",
+    PICTURE: "picture",
+    PLACEHOLDER: "placeholder",
+    PLAINTEXT: "plaintext",
+    POINTS: "points",
+    POINTS_AT_X: "pointsAtX",
+    POINTS_AT_Y: "pointsAtY",
+    POINTS_AT_Z: "pointsAtZ",
+    POINTSATX: "pointsatx",
+    POINTSATY: "pointsaty",
+    POINTSATZ: "pointsatz",
+    POLYGON: "polygon",
+    POLYLINE: "polyline",
+    POSTER: "poster",
+    PRE: "pre",
+    PRELOAD: "preload",
+    PRESERVE_ALPHA: "preserveAlpha",
+    PRESERVE_ASPECT_RATIO: "preserveAspectRatio",
+    PRESERVEALPHA: "preservealpha",
+    PRESERVEASPECTRATIO: "preserveaspectratio",
+    PRIMITIVE_UNITS: "primitiveUnits",
+    PRIMITIVEUNITS: "primitiveunits",
+    PROGRESS: "progress",
+    Q: "q",
+    R: "r",
+    RADIAL_GRADIENT: "radialGradient",
+    RADIALGRADIENT: "radialgradient",
+    RB: "rb",
+    READONLY: "readonly",
+    RECT: "rect",
+    REF_X: "refX",
+    REF_Y: "refY",
+    REFX: "refx",
+    REFY: "refy",
+    REL: "rel",
+    REPEAT_COUNT: "repeatCount",
+    REPEAT_DUR: "repeatDur",
+    REPEATCOUNT: "repeatcount",
+    REPEATDUR: "repeatdur",
+    REQUIRED: "required",
+    REQUIRED_EXTENSIONS: "requiredExtensions",
+    REQUIRED_FEATURES: "requiredFeatures",
+    REQUIREDEXTENSIONS: "requiredextensions",
+    REQUIREDFEATURES: "requiredfeatures",
+    ROWS: "rows",
+    ROWSPAN: "rowspan",
+    RP: "rp",
+    RT: "rt",
+    RTC: "rtc",
+    RUBY: "ruby",
+    RX: "rx",
+    RY: "ry",
+    S: "s",
+    SAMP: "samp",
+    SANDBOX: "sandbox",
+    SCOPE: "scope",
+    SCRIPT: "script",
+    SEARCH: "search",
+    SECTION: "section",
+    SELECT: "select",
+    SELECTED: "selected",
+    SHAPE: "shape",
+    SIZE: "size",
+    SIZES: "sizes",
+    SLOT: "slot",
+    SMALL: "small",
+    SOURCE: "source",
+    SPAN: "span",
+    SPECULAR_CONSTANT: "specularConstant",
+    SPECULAR_EXPONENT: "specularExponent",
+    SPECULARCONSTANT: "specularconstant",
+    SPECULAREXPONENT: "specularexponent",
+    SPELLCHECK: "spellcheck",
+    SPREAD_METHOD: "spreadMethod",
+    SPREADMETHOD: "spreadmethod",
+    SRC: "src",
+    SRCDOC: "srcdoc",
+    SRCLANG: "srclang",
+    SRCSET: "srcset",
+    START: "start",
+    START_OFFSET: "startOffset",
+    STARTOFFSET: "startoffset",
+    STD_DEVIATION: "stdDeviation",
+    STDDEVIATION: "stddeviation",
+    STEP: "step",
+    STITCH_TILES: "stitchTiles",
+    STITCHTILES: "stitchtiles",
+    STOP: "stop",
+    STRIKE: "strike",
+    STROKE: "stroke",
+    STRONG: "strong",
+    STYLE: "style",
+    SUB: "sub",
+    SUMMARY: "summary",
+    SUP: "sup",
+    SURFACE_SCALE: "surfaceScale",
+    SURFACESCALE: "surfacescale",
+    SVG: "svg",
+    SYMBOL: "symbol",
+    SYSTEM_LANGUAGE: "systemLanguage",
+    SYSTEMLANGUAGE: "systemlanguage",
+    TABINDEX: "tabindex",
+    TABLE: "table",
+    TABLE_VALUES: "tableValues",
+    TABLEVALUES: "tablevalues",
+    TARGET: "target",
+    TARGET_X: "targetX",
+    TARGET_Y: "targetY",
+    TARGETX: "targetx",
+    TARGETY: "targety",
+    TBODY: "tbody",
+    TD: "td",
+    TEMPLATE: "template",
+    TEXT: "text",
+    TEXT_LENGTH: "textLength",
+    TEXT_PATH: "textPath",
+    TEXTAREA: "textarea",
+    TEXTLENGTH: "textlength",
+    TEXTPATH: "textpath",
+    TFOOT: "tfoot",
+    TH: "th",
+    THEAD: "thead",
+    TIME: "time",
+    TITLE: "title",
+    TR: "tr",
+    TRACK: "track",
+    TRANSFORM: "transform",
+    TRANSLATE: "translate",
+    TSPAN: "tspan",
+    TT: "tt",
+    TYPE: "type",
+    U: "u",
+    UL: "ul",
+    USE: "use",
+    USEMAP: "usemap",
+    VALUE: "value",
+    VAR: "var",
+    VIDEO: "video",
+    VIEW_BOX: "viewBox",
+    VIEW_TARGET: "viewTarget",
+    VIEWBOX: "viewbox",
+    VIEWTARGET: "viewtarget",
+    WBR: "wbr",
+    WIDTH: "width",
+    WRAP: "wrap",
+    X: "x",
+    X1: "x1",
+    X2: "x2",
+    X_CHANNEL_SELECTOR: "xChannelSelector",
+    XCHANNELSELECTOR: "xchannelselector",
+    XLINK_ACTUATE: "xlink:actuate",
+    XLINK_ARCROLE: "xlink:arcrole",
+    XLINK_HREF: "xlink:href",
+    XLINK_ROLE: "xlink:role",
+    XLINK_SHOW: "xlink:show",
+    XLINK_TITLE: "xlink:title",
+    XLINK_TYPE: "xlink:type",
+    XML_LANG: "xml:lang",
+    XML_SPACE: "xml:space",
+    XMLNS: "xmlns",
+    XMLNS_XLINK: "xmlns:xlink",
+    XMP: "xmp",
+    Y: "y",
+    Y1: "y1",
+    Y2: "y2",
+    Y_CHANNEL_SELECTOR: "yChannelSelector",
+    YCHANNELSELECTOR: "ychannelselector",
+    ZOOM_AND_PAN: "zoomAndPan",
+    ZOOMANDPAN: "zoomandpan",
+}
+
+/// The atom for `name`, if it's in the table
+pub fn lookup(name: &str) -> Option<usize> {
+    NAMES
+        .binary_search(&name)
+        .ok()
+        .map(|index| index + FIRST_ATOM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorted() {
+        assert!(NAMES.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn lookup_atoms() {
+        assert_eq!(Some(DIV), lookup("div"));
+        assert_eq!("div", NAMES[DIV - FIRST_ATOM]);
+        assert_eq!(Some(FOREIGN_OBJECT), lookup("foreignObject"));
+        assert_eq!(Some(FOREIGNOBJECT), lookup("foreignobject"));
+        assert_eq!(None, lookup("custom-element"));
+    }
+}
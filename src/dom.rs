@@ -1,14 +1,24 @@
 use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    hash::{BuildHasher, RandomState},
     io::{self, Write},
     ops::Range,
     str,
 };
 
+use crate::atoms;
+
 const EMPTY_RANGE: Range<usize> = 0..0;
 pub const EMPTY_RANGE_INDEX: usize = 0;
 const ROOT_NODE_INDEX: usize = 0;
 const INVALID_NODE_ID: usize = usize::MAX;
+const INVALID_NODE_INDEX: usize = usize::MAX;
 pub const ROOT_NODE_ID: usize = 0;
+// fills the unused slots of nodes
+const INVALID_NODE: Node = Node::Text(TextNode {
+    id: INVALID_NODE_ID,
+    range: EMPTY_RANGE,
+});
 
 #[derive(Clone)]
 struct TextNode {
@@ -16,39 +26,122 @@ struct TextNode {
     range: Range<usize>,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Namespace {
+    Html,
+    MathMl,
+    Svg,
+    XLink,
+    Xml,
+    XmlNs,
+}
+
+/// The document mode, which older content relies on for legacy
+/// parsing, CSS and layout behaviour
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum QuirksMode {
+    #[default]
+    NoQuirks,
+    LimitedQuirks,
+    Quirks,
+}
+
 #[derive(Copy, Clone)]
 struct ElementNode {
     id: usize,
+    namespace: Namespace,
     name: usize,
     attrs: usize,
     kids: usize,
 }
 
+#[derive(Copy, Clone)]
+struct CommentNode {
+    id: usize,
+    data: usize,
+}
+
+#[derive(Copy, Clone)]
+struct DocTypeNode {
+    id: usize,
+    name: usize,
+    public_id: Option<usize>,
+    system_id: Option<usize>,
+}
+
+#[derive(Copy, Clone)]
+struct ProcessingInstructionNode {
+    id: usize,
+    target: usize,
+    data: usize,
+}
+
 #[derive(Clone)]
 enum Node {
-    Text(TextNode),
+    Document(ElementNode),         // like an element without a name or attrs
+    DocumentFragment(ElementNode), // template contents, like the document
+    DocType(DocTypeNode),
     Element(ElementNode),
+    Text(TextNode),
+    Comment(CommentNode),
+    ProcessingInstruction(ProcessingInstructionNode),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum NodeKind {
+    Document,
+    DocumentFragment,
+    DocType,
+    Element,
+    Text,
+    Comment,
+    ProcessingInstruction,
 }
 
 impl Node {
     fn id(&self) -> usize {
         match self {
+            Node::Document(node) | Node::DocumentFragment(node) | Node::Element(node) => node.id,
+            Node::DocType(node) => node.id,
             Node::Text(node) => node.id,
-            Node::Element(node) => node.id,
+            Node::Comment(node) => node.id,
+            Node::ProcessingInstruction(node) => node.id,
         }
     }
 
-    fn is_valid(&self) -> bool {
+    fn kind(&self) -> NodeKind {
         match self {
-            Node::Text(node) => node.id != INVALID_NODE_ID,
-            Node::Element(node) => node.id != INVALID_NODE_ID,
+            Node::Document(_) => NodeKind::Document,
+            Node::DocumentFragment(_) => NodeKind::DocumentFragment,
+            Node::DocType(_) => NodeKind::DocType,
+            Node::Element(_) => NodeKind::Element,
+            Node::Text(_) => NodeKind::Text,
+            Node::Comment(_) => NodeKind::Comment,
+            Node::ProcessingInstruction(_) => NodeKind::ProcessingInstruction,
         }
     }
 
+    fn is_valid(&self) -> bool {
+        self.id() != INVALID_NODE_ID
+    }
+
     fn invalidate(&mut self) {
         match self {
+            Node::Document(node) | Node::DocumentFragment(node) | Node::Element(node) => {
+                node.id = INVALID_NODE_ID
+            }
+            Node::DocType(node) => node.id = INVALID_NODE_ID,
             Node::Text(node) => node.id = INVALID_NODE_ID,
-            Node::Element(node) => node.id = INVALID_NODE_ID,
+            Node::Comment(node) => node.id = INVALID_NODE_ID,
+            Node::ProcessingInstruction(node) => node.id = INVALID_NODE_ID,
+        }
+    }
+
+    /// the element data of nodes that can have children
+    fn as_parent(&self) -> Option<&ElementNode> {
+        match self {
+            Node::Document(node) | Node::DocumentFragment(node) | Node::Element(node) => Some(node),
+            _ => None,
         }
     }
 }
@@ -60,13 +153,24 @@ impl Node {
 pub struct Dom {
     text: Soup<u8>,
     ranges: Soup<Range<usize>>,
+    strings: StrIndex, // interned strings -> range index
+    free_ranges: Vec<usize>,
 
     node_buf: Vec<Node>, // temp working mem for moving nodes
     nodes: Vec<Node>,
     node_id_counter: usize,
+    node_indices: Vec<usize>,                  // node id -> index in nodes
+    parents: Vec<usize>,                       // node id -> parent id
+    free_blocks: BTreeMap<usize, usize>,       // free block start -> length
+    free_block_lens: BTreeSet<(usize, usize)>, // free block (length, start)
 
     attr_buf: Vec<[usize; 2]>, // temp working mem for moving attrs
     attrs: Vec<[usize; 2]>,
+
+    form_owners: HashMap<usize, usize>, // element id -> form id
+    template_contents: HashMap<usize, usize>, // template id -> fragment id
+
+    quirks_mode: QuirksMode,
 }
 
 impl Dom {
@@ -76,31 +180,171 @@ impl Dom {
         let empty_range_index = ranges.append(&[EMPTY_RANGE]).start;
         debug_assert!(empty_range_index == EMPTY_RANGE_INDEX);
 
-        // node 0 is the root node
-        let root = Node::Element(ElementNode {
+        // then every atom, in order, so their ids are fixed
+        let mut text = Soup::new();
+        for name in atoms::NAMES {
+            let start = text.items.len();
+            text.items.extend_from_slice(name.as_bytes());
+            ranges.items.push(start..text.items.len());
+        }
+        debug_assert!(ranges.items.len() == atoms::FIRST_ATOM + atoms::NAMES.len());
+
+        // node 0 is the document
+        let root = Node::Document(ElementNode {
             id: ROOT_NODE_ID,
+            namespace: Namespace::Html,
             name: EMPTY_RANGE_INDEX,
             attrs: EMPTY_RANGE_INDEX,
             kids: EMPTY_RANGE_INDEX,
         });
 
         Self {
-            text: Soup::new(),
+            text,
             ranges,
+            strings: StrIndex::new(),
+            free_ranges: Vec::new(),
             node_buf: Vec::new(),
             nodes: vec![root],
             node_id_counter: 0,
+            node_indices: vec![ROOT_NODE_INDEX],
+            parents: vec![INVALID_NODE_ID],
+            free_blocks: BTreeMap::new(),
+            free_block_lens: BTreeSet::new(),
             attr_buf: Vec::new(),
             attrs: Vec::new(),
+            form_owners: HashMap::new(),
+            template_contents: HashMap::new(),
+            quirks_mode: QuirksMode::default(),
         }
     }
 
     fn get_node_by_id(&self, id: usize) -> Option<(usize, Node)> {
-        self.nodes
-            .iter()
-            .enumerate()
-            .find(|(_, node)| node.id() == id)
-            .map(|(index, node)| (index, node.clone()))
+        match self.node_indices.get(id) {
+            Some(&INVALID_NODE_INDEX) | None => None,
+            Some(&index) => Some((index, self.nodes[index].clone())),
+        }
+    }
+
+    fn next_node_id(&mut self) -> usize {
+        self.node_id_counter += 1;
+        self.node_indices.push(INVALID_NODE_INDEX);
+        self.parents.push(INVALID_NODE_ID);
+        self.node_id_counter
+    }
+
+    /// moves the siblings in `node_buf` into a free block, or the end of
+    /// nodes, and points the `kids` range of `parent` at them
+    fn push_sibling_block(&mut self, parent: usize, kids: usize) -> usize {
+        let len = self.node_buf.len();
+        if len == 0 {
+            self.free_range(kids);
+            return EMPTY_RANGE_INDEX;
+        }
+        let start = self.alloc_sibling_block(len);
+        for (index, node) in (start..).zip(self.node_buf.drain(..)) {
+            self.nodes[index] = node;
+        }
+        for index in start..start + len {
+            let id = self.nodes[index].id();
+            self.node_indices[id] = index;
+            self.parents[id] = parent;
+        }
+        if kids == EMPTY_RANGE_INDEX {
+            self.insert_range(start..start + len)
+        } else {
+            self.ranges.items[kids] = start..start + len;
+            kids
+        }
+    }
+
+    /// returns the start of `len` unused nodes, from the start of the
+    /// smallest free block they fit in, so the block can grow into the rest.
+    /// A free block at the end of nodes grows to fit instead
+    fn alloc_sibling_block(&mut self, len: usize) -> usize {
+        if let Some(&(free_len, start)) = self.free_block_lens.range((len, 0)..).next() {
+            self.take_free_block(start);
+            self.free_sibling_block(start + len..start + free_len);
+            return start;
+        }
+        let start = match self.free_blocks.last_key_value() {
+            Some((&start, &free_len)) if start + free_len == self.nodes.len() => {
+                self.take_free_block(start);
+                start
+            }
+            _ => self.nodes.len(),
+        };
+        self.nodes.resize(start + len, INVALID_NODE);
+        start
+    }
+
+    /// makes room for one more sibling at `end`, the end of a sibling block,
+    /// if it's also the end of nodes or the start of a free block
+    fn grow_sibling_block(&mut self, end: usize) -> bool {
+        if end == self.nodes.len() {
+            self.nodes.push(INVALID_NODE);
+        } else if self.free_blocks.contains_key(&end) {
+            let len = self.take_free_block(end);
+            self.free_sibling_block(end + 1..end + len);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// makes a block of invalidated siblings available for reuse,
+    /// merged with any free blocks right before or after it
+    fn free_sibling_block(&mut self, block: Range<usize>) {
+        if block.is_empty() {
+            return;
+        }
+        let Range { mut start, mut end } = block;
+        if self.free_blocks.contains_key(&end) {
+            end += self.take_free_block(end);
+        }
+        if let Some((&before, &len)) = self.free_blocks.range(..start).next_back() {
+            if before + len == start {
+                self.take_free_block(before);
+                start = before;
+            }
+        }
+        self.free_blocks.insert(start, end - start);
+        self.free_block_lens.insert((end - start, start));
+    }
+
+    /// removes the free block starting at `start` and returns its length
+    fn take_free_block(&mut self, start: usize) -> usize {
+        let len = self.free_blocks.remove(&start).unwrap();
+        self.free_block_lens.remove(&(len, start));
+        len
+    }
+
+    /// invalidates the node and all of its descendants
+    fn drop_subtree(&mut self, node: Node) {
+        self.node_indices[node.id()] = INVALID_NODE_INDEX;
+        self.parents[node.id()] = INVALID_NODE_ID;
+        if let Some(node) = node.as_parent() {
+            let kids = self.ranges.items[node.kids].clone();
+            for index in kids.clone() {
+                let kid = self.nodes[index].clone();
+                self.nodes[index].invalidate();
+                self.drop_subtree(kid);
+            }
+            self.free_sibling_block(kids);
+            self.free_range(node.kids);
+        }
+    }
+
+    fn set_parent_node(&mut self, index: usize, node: ElementNode) {
+        match &mut self.nodes[index] {
+            Node::Document(parent) | Node::DocumentFragment(parent) | Node::Element(parent) => {
+                *parent = node
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn get_node_kind(&self, id: usize) -> Option<NodeKind> {
+        self.get_node_by_id(id).map(|(_, node)| node.kind())
     }
 
     pub fn get_node_id_by_index(&self, index: usize) -> Option<usize> {
@@ -129,8 +373,37 @@ impl Dom {
         None
     }
 
+    pub fn get_comment_node(&self, id: usize) -> Option<CommentNodeHandle<'_>> {
+        if let Some((_, Node::Comment(node))) = self.get_node_by_id(id) {
+            return Some(CommentNodeHandle { dom: self, node });
+        }
+        None
+    }
+
+    pub fn get_doctype_node(&self, id: usize) -> Option<DocTypeNodeHandle<'_>> {
+        if let Some((_, Node::DocType(node))) = self.get_node_by_id(id) {
+            return Some(DocTypeNodeHandle { dom: self, node });
+        }
+        None
+    }
+
+    pub fn get_processing_instruction_node(
+        &self,
+        id: usize,
+    ) -> Option<ProcessingInstructionNodeHandle<'_>> {
+        if let Some((_, Node::ProcessingInstruction(node))) = self.get_node_by_id(id) {
+            return Some(ProcessingInstructionNodeHandle { dom: self, node });
+        }
+        None
+    }
+
+    /// the document and fragments can be used as elements without a name or attrs
     pub fn get_element_node(&self, id: usize) -> Option<ElementNodeHandle> {
-        if let Some((index, Node::Element(node))) = self.get_node_by_id(id) {
+        if let Some((
+            index,
+            Node::Element(node) | Node::Document(node) | Node::DocumentFragment(node),
+        )) = self.get_node_by_id(id)
+        {
             return Some(ElementNodeHandle {
                 dom: self,
                 index,
@@ -141,7 +414,11 @@ impl Dom {
     }
 
     pub fn get_element_node_mut(&mut self, id: usize) -> Option<ElementNodeHandleMut> {
-        if let Some((index, Node::Element(node))) = self.get_node_by_id(id) {
+        if let Some((
+            index,
+            Node::Element(node) | Node::Document(node) | Node::DocumentFragment(node),
+        )) = self.get_node_by_id(id)
+        {
             return Some(ElementNodeHandleMut {
                 dom: self,
                 index,
@@ -151,18 +428,14 @@ impl Dom {
         None
     }
 
-    pub fn get_element_node_by_attr(
-        &mut self,
-        name: &str,
-        value: &str,
-    ) -> Option<ElementNodeHandle> {
-        let name = self.insert_str(name);
-        let value = self.insert_str(value);
+    pub fn get_element_node_by_attr(&self, name: &str, value: &str) -> Option<ElementNodeHandle> {
+        let name = self.find_str(name)?;
+        let value = self.find_str(value)?;
         for (index, node) in self.nodes.iter().enumerate() {
             if !node.is_valid() {
                 continue;
             }
-            if let Node::Element(node) = node {
+            if let Node::Element(node) | Node::Document(node) = node {
                 let attrs = self.ranges.items[node.attrs].clone();
                 for attr in &self.attrs[attrs] {
                     if (attr[0] == name) && (attr[1] == value) {
@@ -183,13 +456,13 @@ impl Dom {
         name: &str,
         value: &str,
     ) -> Option<ElementNodeHandleMut> {
-        let name = self.insert_str(name);
-        let value = self.insert_str(value);
+        let name = self.find_str(name)?;
+        let value = self.find_str(value)?;
         for (index, node) in self.nodes.iter().enumerate() {
             if !node.is_valid() {
                 continue;
             }
-            if let Node::Element(node) = node {
+            if let Node::Element(node) | Node::Document(node) = node {
                 let attrs = self.ranges.items[node.attrs].clone();
                 for attr in &self.attrs[attrs] {
                     if (attr[0] == name) && (attr[1] == value) {
@@ -205,9 +478,194 @@ impl Dom {
         None
     }
 
+    /// returns id of an element that isn't in the tree yet
+    pub fn create_element(&mut self, namespace: Namespace, name: usize, attrs: usize) -> usize {
+        let id = self.next_node_id();
+        self.node_indices[id] = self.nodes.len();
+        self.nodes.push(Node::Element(ElementNode {
+            id,
+            namespace,
+            name,
+            attrs,
+            kids: EMPTY_RANGE_INDEX,
+        }));
+        id
+    }
+
+    /// moves the node and its children under `parent`,
+    /// before the child with id `before`, or last if `None`
+    pub fn move_node(&mut self, id: usize, parent: usize, before: Option<usize>) {
+        assert!(
+            id != ROOT_NODE_ID && !self.is_inclusive_ancestor(id, parent),
+            "a node can't be moved into itself"
+        );
+        let node = match self.get_parent_node_id(id) {
+            Some(old_parent) => {
+                let mut old_parent = self.get_element_node_mut(old_parent).unwrap();
+                old_parent.detach_child(id).unwrap()
+            }
+            None => {
+                // not in the tree, take it from where it was created
+                let (index, node) = self.get_node_by_id(id).unwrap();
+                self.nodes[index].invalidate();
+                self.free_sibling_block(index..index + 1);
+                node
+            }
+        };
+        let mut parent = self.get_element_node_mut(parent).unwrap();
+        parent.insert_child(node, before);
+    }
+
+    /// removes the node and its children from the tree,
+    /// or drops them if the node was never inserted
+    pub fn remove_node(&mut self, id: usize) {
+        if let Some(parent) = self.get_parent_node_id(id) {
+            let mut parent = self.get_element_node_mut(parent).unwrap();
+            if let Some(node) = parent.detach_child(id) {
+                self.drop_subtree(node);
+            }
+        } else if id != ROOT_NODE_ID {
+            if let Some((index, node)) = self.get_node_by_id(id) {
+                self.nodes[index].invalidate();
+                self.free_sibling_block(index..index + 1);
+                self.drop_subtree(node);
+            }
+        }
+    }
+
+    /// moves all children of `from` to the end of `to`
+    pub fn move_children(&mut self, from: usize, to: usize) {
+        assert!(
+            from == to || !self.is_inclusive_ancestor(from, to),
+            "children can't be moved into themselves"
+        );
+        let mut from = self.get_element_node_mut(from).unwrap();
+        let kids = from.take_children();
+        let mut to = self.get_element_node_mut(to).unwrap();
+        to.append_children(kids);
+    }
+
+    /// whether `ancestor` is `id` or one of its parents
+    fn is_inclusive_ancestor(&self, ancestor: usize, id: usize) -> bool {
+        let mut id = Some(id);
+        while let Some(node) = id {
+            if node == ancestor {
+                return true;
+            }
+            id = self.get_parent_node_id(node);
+        }
+        false
+    }
+
+    /// returns the id of the element whose children include the node
+    pub fn get_parent_node_id(&self, id: usize) -> Option<usize> {
+        match self.parents.get(id) {
+            Some(&INVALID_NODE_ID) | None => None,
+            Some(&parent) => Some(parent),
+        }
+    }
+
+    pub fn get_first_child_id(&self, id: usize) -> Option<usize> {
+        let (_, node) = self.get_node_by_id(id)?;
+        let kids = self.ranges.items[node.as_parent()?.kids].clone();
+        if kids.is_empty() {
+            return None;
+        }
+        self.get_node_id_by_index(kids.start)
+    }
+
+    pub fn get_last_child_id(&self, id: usize) -> Option<usize> {
+        let (_, node) = self.get_node_by_id(id)?;
+        let kids = self.ranges.items[node.as_parent()?.kids].clone();
+        if kids.is_empty() {
+            return None;
+        }
+        self.get_node_id_by_index(kids.end - 1)
+    }
+
+    pub fn get_next_sibling_id(&self, id: usize) -> Option<usize> {
+        let kids = self.get_sibling_indices(id)?;
+        let index = self.node_indices[id] + 1;
+        self.get_node_id_by_index(index)
+            .filter(|_| kids.contains(&index))
+    }
+
+    pub fn get_previous_sibling_id(&self, id: usize) -> Option<usize> {
+        let kids = self.get_sibling_indices(id)?;
+        let index = self.node_indices[id].checked_sub(1)?;
+        self.get_node_id_by_index(index)
+            .filter(|_| kids.contains(&index))
+    }
+
+    /// the indices of the node and its siblings
+    fn get_sibling_indices(&self, id: usize) -> Option<Range<usize>> {
+        let parent = self.get_parent_node_id(id)?;
+        let (_, parent) = self.get_node_by_id(parent)?;
+        Some(self.ranges.items[parent.as_parent()?.kids].clone())
+    }
+
+    pub fn get_attrs(&self, index: usize) -> &[[usize; 2]] {
+        let attrs = self.ranges.items[index].clone();
+        &self.attrs[attrs]
+    }
+
+    pub fn set_form_owner(&mut self, id: usize, form: usize) {
+        self.form_owners.insert(id, form);
+    }
+
+    pub fn get_form_owner(&self, id: usize) -> Option<usize> {
+        self.form_owners.get(&id).copied()
+    }
+
+    /// returns id of a new document fragment holding the template's contents,
+    /// which isn't in the tree
+    pub fn create_template_contents(&mut self, id: usize) -> usize {
+        let contents = self.next_node_id();
+        self.node_indices[contents] = self.nodes.len();
+        self.nodes.push(Node::DocumentFragment(ElementNode {
+            id: contents,
+            namespace: Namespace::Html,
+            name: EMPTY_RANGE_INDEX,
+            attrs: EMPTY_RANGE_INDEX,
+            kids: EMPTY_RANGE_INDEX,
+        }));
+        self.template_contents.insert(id, contents);
+        contents
+    }
+
+    pub fn get_template_contents(&self, id: usize) -> Option<usize> {
+        self.template_contents.get(&id).copied()
+    }
+
+    pub fn quirks_mode(&self) -> QuirksMode {
+        self.quirks_mode
+    }
+
+    pub fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.quirks_mode = mode;
+    }
+
     pub fn insert_str(&mut self, text: &str) -> usize {
-        let range = self.text.append(text);
-        self.insert_range(range)
+        if text.is_empty() {
+            return EMPTY_RANGE_INDEX;
+        }
+        if let Some(atom) = atoms::lookup(text) {
+            return atom;
+        }
+        let hash = self.strings.hash(text);
+        match self
+            .strings
+            .probe(hash, |index| self.get_str(index) == Some(text))
+        {
+            Ok(index) => index,
+            Err(slot) => {
+                let start = self.text.items.len();
+                self.text.items.extend_from_slice(text.as_bytes());
+                let index = self.insert_range(start..self.text.items.len());
+                self.strings.insert(slot, hash, index);
+                index
+            }
+        }
     }
 
     pub fn insert_attrs(&mut self, attrs: &[[usize; 2]]) -> usize {
@@ -220,10 +678,11 @@ impl Dom {
         self.insert_range(start..end)
     }
 
-    pub fn append_char(&mut self, c: char) {
-        let mut buf = [0; 4];
-        let string = c.encode_utf8(&mut buf);
-        self.text.items.extend_from_slice(string.as_bytes())
+    /// appends text for a text node, which can then grow in place
+    fn push_text(&mut self, text: &str) -> Range<usize> {
+        let start = self.text.items.len();
+        self.text.items.extend_from_slice(text.as_bytes());
+        start..self.text.items.len()
     }
 
     pub fn get_str(&self, index: usize) -> Option<&str> {
@@ -238,14 +697,142 @@ impl Dom {
     }
 
     pub fn find_str(&self, text: &str) -> Option<usize> {
-        if let Some(range) = self.text.find(text) {
-            return self.ranges.find(&[range]).map(|range| range.start);
+        if text.is_empty() {
+            return Some(EMPTY_RANGE_INDEX);
         }
-        None
+        if let Some(atom) = atoms::lookup(text) {
+            return Some(atom);
+        }
+        let hash = self.strings.hash(text);
+        self.strings
+            .probe(hash, |index| self.get_str(index) == Some(text))
+            .ok()
     }
 
     fn insert_range(&mut self, range: Range<usize>) -> usize {
-        self.ranges.append(&[range]).start
+        if let Some(index) = self.free_ranges.pop() {
+            self.ranges.items[index] = range;
+            return index;
+        }
+        self.ranges.items.push(range);
+        self.ranges.items.len() - 1
+    }
+
+    /// only for ranges owned by a single node, like its children
+    fn free_range(&mut self, index: usize) {
+        if index != EMPTY_RANGE_INDEX {
+            self.ranges.items[index] = EMPTY_RANGE;
+            self.free_ranges.push(index);
+        }
+    }
+
+    /// Garbage collects dead nodes, attr blocks, ranges, interned strings and
+    /// text. Node ids are kept, but attrs and strings that aren't used by a
+    /// node anymore must not be used afterwards
+    pub fn compact(&mut self) {
+        // live nodes are the ones in the tree or under a detached node
+        let mut owned = vec![false; self.nodes.len()];
+        for node in &self.nodes {
+            if let Some(node) = node.as_parent() {
+                if node.id != INVALID_NODE_ID {
+                    owned[self.ranges.items[node.kids].clone()].fill(true);
+                }
+            }
+        }
+        let mut nodes: Vec<Node> = (self.nodes.iter().zip(owned))
+            .filter(|(node, owned)| node.is_valid() && !owned)
+            .map(|(node, _)| node.clone())
+            .collect();
+        let mut i = 0;
+        while i < nodes.len() {
+            if let Some(&ElementNode { kids, .. }) = nodes[i].as_parent() {
+                if kids != EMPTY_RANGE_INDEX {
+                    let start = nodes.len();
+                    nodes.extend_from_slice(&self.nodes[self.ranges.items[kids].clone()]);
+                    self.ranges.items[kids] = start..nodes.len();
+                }
+            }
+            i += 1;
+        }
+        self.nodes = nodes;
+        self.free_blocks.clear();
+        self.free_block_lens.clear();
+        self.node_indices.fill(INVALID_NODE_INDEX);
+        for (index, node) in self.nodes.iter().enumerate() {
+            self.node_indices[node.id()] = index;
+        }
+
+        // the empty range and atoms are always live
+        let atoms_end = atoms::FIRST_ATOM + atoms::NAMES.len();
+        let mut live = vec![false; self.ranges.items.len()];
+        live[..atoms_end].fill(true);
+
+        // copy the attrs that are still used, blocks can be shared,
+        // and keep the strings the nodes use
+        let mut attrs = Vec::new();
+        for node in &self.nodes {
+            match node {
+                Node::Document(node) | Node::DocumentFragment(node) | Node::Element(node) => {
+                    live[node.name] = true;
+                    live[node.kids] = true;
+                    if !live[node.attrs] {
+                        live[node.attrs] = true;
+                        let start = attrs.len();
+                        attrs.extend_from_slice(&self.attrs[self.ranges.items[node.attrs].clone()]);
+                        self.ranges.items[node.attrs] = start..attrs.len();
+                    }
+                }
+                Node::DocType(node) => {
+                    live[node.name] = true;
+                    for id in [node.public_id, node.system_id].into_iter().flatten() {
+                        live[id] = true;
+                    }
+                }
+                Node::Comment(node) => live[node.data] = true,
+                Node::ProcessingInstruction(node) => {
+                    live[node.target] = true;
+                    live[node.data] = true;
+                }
+                Node::Text(_) => {}
+            }
+        }
+        for &[name, value] in &attrs {
+            live[name] = true;
+            live[value] = true;
+        }
+        self.attrs = attrs;
+        self.strings.retain(|index| live[index]);
+
+        // copy the text of atoms, interned strings and text nodes
+        let mut text = Soup::new();
+        let atoms_len = self.ranges.items[atoms_end - 1].end;
+        text.items.extend_from_slice(&self.text.items[..atoms_len]);
+        for &(_, index) in &self.strings.slots {
+            if index != EMPTY_RANGE_INDEX {
+                let start = text.items.len();
+                text.items
+                    .extend_from_slice(&self.text.items[self.ranges.items[index].clone()]);
+                self.ranges.items[index] = start..text.items.len();
+            }
+        }
+        for node in &mut self.nodes {
+            if let Node::Text(node) = node {
+                let start = text.items.len();
+                text.items
+                    .extend_from_slice(&self.text.items[node.range.clone()]);
+                node.range = start..text.items.len();
+            }
+        }
+        self.text = text;
+
+        // and the remaining ranges can be reused
+        self.free_ranges.clear();
+        for (index, live) in live.into_iter().enumerate() {
+            if !live {
+                self.ranges.items[index] = EMPTY_RANGE;
+                self.free_ranges.push(index);
+            }
+        }
     }
 
     pub fn write_tree(&self, writer: &mut dyn Write) -> io::Result<()> {
@@ -253,6 +840,74 @@ impl Dom {
         self.write_node(0, node, writer, false)
     }
 
+    /// writes the tree in the format of the html5lib tree-construction tests
+    pub fn write_html5lib_tree(&self, writer: &mut dyn Write) -> io::Result<()> {
+        self.write_html5lib_children(ROOT_NODE_ID, 0, writer)
+    }
+
+    fn write_html5lib_children(
+        &self,
+        id: usize,
+        depth: usize,
+        writer: &mut dyn Write,
+    ) -> io::Result<()> {
+        let indent = "  ".repeat(depth);
+        let mut child = self.get_first_child_id(id);
+        while let Some(id) = child {
+            child = self.get_next_sibling_id(id);
+            if let Some(text) = self.get_text_node(id) {
+                writeln!(writer, "| {indent}\"{}\"", text.text())?;
+            } else if let Some(comment) = self.get_comment_node(id) {
+                writeln!(writer, "| {indent}<!-- {} -->", comment.data())?;
+            } else if let Some(pi) = self.get_processing_instruction_node(id) {
+                writeln!(writer, "| {indent}<?{} {}>", pi.target(), pi.data())?;
+            } else if let Some(doctype) = self.get_doctype_node(id) {
+                let name = doctype.name();
+                match (doctype.public_id(), doctype.system_id()) {
+                    (None, None) => writeln!(writer, "| {indent}<!DOCTYPE {name}>")?,
+                    (public_id, system_id) => writeln!(
+                        writer,
+                        "| {indent}<!DOCTYPE {name} \"{}\" \"{}\">",
+                        public_id.unwrap_or_default(),
+                        system_id.unwrap_or_default()
+                    )?,
+                }
+            } else if let Some(element) = self.get_element_node(id) {
+                let name = self.get_str(element.name()).unwrap();
+                match element.namespace() {
+                    Namespace::Svg => writeln!(writer, "| {indent}<svg {name}>")?,
+                    Namespace::MathMl => writeln!(writer, "| {indent}<math {name}>")?,
+                    _ => writeln!(writer, "| {indent}<{name}>")?,
+                }
+                let mut attrs: Vec<_> = element
+                    .attrs()
+                    .iter()
+                    .map(|&[name, value]| {
+                        let value = self.get_str(value).unwrap();
+                        let prefixed = self.get_str(name).unwrap();
+                        // namespaced attributes are written as "prefix local-name"
+                        let name = match element.attr_namespace(name) {
+                            Some(_) if prefixed == "xmlns" => "xmlns xmlns".to_string(),
+                            Some(_) => prefixed.replacen(':', " ", 1),
+                            None => prefixed.to_string(),
+                        };
+                        (name, value)
+                    })
+                    .collect();
+                attrs.sort();
+                for (name, value) in attrs {
+                    writeln!(writer, "| {indent}  {name}=\"{value}\"")?;
+                }
+                if let Some(contents) = self.get_template_contents(id) {
+                    writeln!(writer, "| {indent}  content")?;
+                    self.write_html5lib_children(contents, depth + 2, writer)?;
+                }
+                self.write_html5lib_children(id, depth + 1, writer)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn write_junk(&self, writer: &mut dyn Write) -> io::Result<()> {
         for node in &self.nodes {
             if !node.is_valid() {
@@ -281,7 +936,27 @@ impl Dom {
                     writeln!(writer, "<>{text}")?;
                 }
             }
-            Node::Element(node) => {
+            Node::Comment(node) => {
+                let data = self.get_str(node.data).unwrap();
+                writeln!(writer, "<!--{data}-->")?;
+            }
+            Node::DocType(node) => {
+                let name = self.get_str(node.name).unwrap();
+                writeln!(writer, "<!DOCTYPE {name}>")?;
+            }
+            Node::ProcessingInstruction(node) => {
+                let target = self.get_str(node.target).unwrap();
+                let data = self.get_str(node.data).unwrap();
+                writeln!(writer, "<?{target} {data}>")?;
+            }
+            Node::DocumentFragment(node) => {
+                writeln!(writer, "#document-fragment")?;
+                let kids = self.ranges.items[node.kids].clone();
+                for kid in &self.nodes[kids] {
+                    self.write_node(depth + 2, kid.clone(), writer, show_ids)?;
+                }
+            }
+            Node::Document(node) | Node::Element(node) => {
                 let name = self.get_str(node.name).unwrap();
                 if show_ids {
                     write!(writer, "<{name}:{id}", id = node.id)?;
@@ -306,174 +981,586 @@ impl Dom {
     }
 }
 
-pub struct TextNodeHandle<'a> {
+pub struct CommentNodeHandle<'a> {
     dom: &'a Dom,
-    index: usize,
-    node: TextNode,
+    node: CommentNode,
 }
 
-impl<'a> TextNodeHandle<'a> {
-    pub fn text(&self) -> &str {
-        self.dom.range_to_str(self.node.range.clone())
+impl<'a> CommentNodeHandle<'a> {
+    pub fn id(&self) -> usize {
+        self.node.id
     }
-}
 
-pub struct TextNodeHandleMut<'a> {
-    dom: &'a mut Dom,
-    index: usize,
-    node: TextNode,
-}
+    pub fn parent(&self) -> Option<usize> {
+        self.dom.get_parent_node_id(self.node.id)
+    }
 
-impl<'a> TextNodeHandleMut<'a> {
-    pub fn set_text(&mut self, text: &str) -> Range<usize> {
-        let range = self.dom.text.append(text);
+    pub fn next_sibling(&self) -> Option<usize> {
+        self.dom.get_next_sibling_id(self.node.id)
+    }
 
-        // update the node
-        let node = TextNode {
-            range: range.clone(),
-            ..self.node
-        };
-        self.node = node.clone();
-        self.dom.nodes[self.index] = Node::Text(node);
+    pub fn previous_sibling(&self) -> Option<usize> {
+        self.dom.get_previous_sibling_id(self.node.id)
+    }
 
-        range
+    pub fn data(&self) -> &str {
+        self.dom.get_str(self.node.data).unwrap()
     }
 }
 
-pub struct ElementNodeHandle<'a> {
+pub struct DocTypeNodeHandle<'a> {
     dom: &'a Dom,
-    index: usize,
-    node: ElementNode,
+    node: DocTypeNode,
 }
 
-impl<'a> ElementNodeHandle<'a> {
-    pub fn child_indices(&self) -> Range<usize> {
-        self.dom.ranges.items[self.node.kids].clone()
+impl<'a> DocTypeNodeHandle<'a> {
+    pub fn id(&self) -> usize {
+        self.node.id
     }
 
-    pub fn name(&self) -> usize {
-        self.node.name
+    pub fn parent(&self) -> Option<usize> {
+        self.dom.get_parent_node_id(self.node.id)
     }
-}
 
-pub struct ElementNodeHandleMut<'a> {
-    dom: &'a mut Dom,
-    index: usize,
-    node: ElementNode,
-}
+    pub fn next_sibling(&self) -> Option<usize> {
+        self.dom.get_next_sibling_id(self.node.id)
+    }
 
-impl<'a> ElementNodeHandleMut<'a> {
-    pub fn children(&self) -> Range<usize> {
-        self.dom.ranges.items[self.node.kids].clone()
+    pub fn previous_sibling(&self) -> Option<usize> {
+        self.dom.get_previous_sibling_id(self.node.id)
     }
 
-    pub fn name(&self) -> usize {
-        self.node.name
+    pub fn name(&self) -> &str {
+        self.dom.get_str(self.node.name).unwrap()
+    }
+
+    pub fn public_id(&self) -> Option<&str> {
+        self.dom.get_str(self.node.public_id?)
+    }
+
+    pub fn system_id(&self) -> Option<&str> {
+        self.dom.get_str(self.node.system_id?)
+    }
+}
+
+pub struct ProcessingInstructionNodeHandle<'a> {
+    dom: &'a Dom,
+    node: ProcessingInstructionNode,
+}
+
+impl<'a> ProcessingInstructionNodeHandle<'a> {
+    pub fn id(&self) -> usize {
+        self.node.id
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        self.dom.get_parent_node_id(self.node.id)
+    }
+
+    pub fn next_sibling(&self) -> Option<usize> {
+        self.dom.get_next_sibling_id(self.node.id)
+    }
+
+    pub fn previous_sibling(&self) -> Option<usize> {
+        self.dom.get_previous_sibling_id(self.node.id)
+    }
+
+    pub fn target(&self) -> &str {
+        self.dom.get_str(self.node.target).unwrap()
+    }
+
+    pub fn data(&self) -> &str {
+        self.dom.get_str(self.node.data).unwrap()
+    }
+}
+
+pub struct TextNodeHandle<'a> {
+    dom: &'a Dom,
+    index: usize,
+    node: TextNode,
+}
+
+impl<'a> TextNodeHandle<'a> {
+    pub fn id(&self) -> usize {
+        self.node.id
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        self.dom.get_parent_node_id(self.node.id)
+    }
+
+    pub fn next_sibling(&self) -> Option<usize> {
+        self.dom.get_next_sibling_id(self.node.id)
+    }
+
+    pub fn previous_sibling(&self) -> Option<usize> {
+        self.dom.get_previous_sibling_id(self.node.id)
+    }
+
+    pub fn text(&self) -> &'a str {
+        self.dom.range_to_str(self.node.range.clone())
+    }
+}
+
+pub struct TextNodeHandleMut<'a> {
+    dom: &'a mut Dom,
+    index: usize,
+    node: TextNode,
+}
+
+impl<'a> TextNodeHandleMut<'a> {
+    pub fn id(&self) -> usize {
+        self.node.id
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        self.dom.get_parent_node_id(self.node.id)
+    }
+
+    pub fn next_sibling(&self) -> Option<usize> {
+        self.dom.get_next_sibling_id(self.node.id)
+    }
+
+    pub fn previous_sibling(&self) -> Option<usize> {
+        self.dom.get_previous_sibling_id(self.node.id)
+    }
+
+    /// appends to the text, in place while the node is at the end of the soup,
+    /// otherwise the text is moved there first so it can keep growing
+    pub fn push_str(&mut self, text: &str) -> Range<usize> {
+        let mut range = self.node.range.clone();
+        if range.end != self.dom.text.items.len() {
+            let start = self.dom.text.items.len();
+            self.dom.text.items.extend_from_within(range.clone());
+            range = start..self.dom.text.items.len();
+        }
+        self.dom.text.items.extend_from_slice(text.as_bytes());
+        range.end = self.dom.text.items.len();
+
+        // update the node
+        let node = TextNode {
+            range: range.clone(),
+            ..self.node
+        };
+        self.node = node.clone();
+        self.dom.nodes[self.index] = Node::Text(node);
+
+        range
+    }
+}
+
+pub struct ElementNodeHandle<'a> {
+    dom: &'a Dom,
+    index: usize,
+    node: ElementNode,
+}
+
+impl<'a> ElementNodeHandle<'a> {
+    pub fn id(&self) -> usize {
+        self.node.id
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        self.dom.get_parent_node_id(self.node.id)
+    }
+
+    pub fn next_sibling(&self) -> Option<usize> {
+        self.dom.get_next_sibling_id(self.node.id)
+    }
+
+    pub fn previous_sibling(&self) -> Option<usize> {
+        self.dom.get_previous_sibling_id(self.node.id)
+    }
+
+    pub fn first_child(&self) -> Option<usize> {
+        self.dom.get_first_child_id(self.node.id)
+    }
+
+    pub fn last_child(&self) -> Option<usize> {
+        self.dom.get_last_child_id(self.node.id)
+    }
+
+    pub fn child_indices(&self) -> Range<usize> {
+        self.dom.ranges.items[self.node.kids].clone()
+    }
+
+    pub fn name(&self) -> usize {
+        self.node.name
+    }
+
+    pub fn namespace(&self) -> Namespace {
+        self.node.namespace
+    }
+
+    pub fn attrs(&self) -> &'a [[usize; 2]] {
+        let attrs = self.dom.ranges.items[self.node.attrs].clone();
+        &self.dom.attrs[attrs]
+    }
+
+    /// returns the namespace of one of the element's attributes,
+    /// only foreign elements have namespaced attributes
+    pub fn attr_namespace(&self, name: usize) -> Option<Namespace> {
+        if self.node.namespace == Namespace::Html {
+            return None;
+        }
+        match self.dom.get_str(name)? {
+            "xlink:actuate" | "xlink:arcrole" | "xlink:href" | "xlink:role" | "xlink:show"
+            | "xlink:title" | "xlink:type" => Some(Namespace::XLink),
+            "xml:lang" | "xml:space" => Some(Namespace::Xml),
+            "xmlns" | "xmlns:xlink" => Some(Namespace::XmlNs),
+            _ => None,
+        }
+    }
+}
+
+pub struct ElementNodeHandleMut<'a> {
+    dom: &'a mut Dom,
+    index: usize,
+    node: ElementNode,
+}
+
+impl<'a> ElementNodeHandleMut<'a> {
+    pub fn id(&self) -> usize {
+        self.node.id
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        self.dom.get_parent_node_id(self.node.id)
+    }
+
+    pub fn next_sibling(&self) -> Option<usize> {
+        self.dom.get_next_sibling_id(self.node.id)
+    }
+
+    pub fn previous_sibling(&self) -> Option<usize> {
+        self.dom.get_previous_sibling_id(self.node.id)
+    }
+
+    pub fn first_child(&self) -> Option<usize> {
+        self.dom.get_first_child_id(self.node.id)
+    }
+
+    pub fn last_child(&self) -> Option<usize> {
+        self.dom.get_last_child_id(self.node.id)
+    }
+
+    pub fn children(&self) -> Range<usize> {
+        self.dom.ranges.items[self.node.kids].clone()
+    }
+
+    pub fn name(&self) -> usize {
+        self.node.name
     }
 
     /// returns id of appended node
-    pub fn append_child_element(&mut self, name: usize, attrs: usize) -> usize {
+    pub fn append_child_element(
+        &mut self,
+        namespace: Namespace,
+        name: usize,
+        attrs: usize,
+    ) -> usize {
+        let id = self.dom.next_node_id();
+        self.insert_child(
+            Node::Element(ElementNode {
+                id,
+                namespace,
+                name,
+                attrs,
+                kids: EMPTY_RANGE_INDEX,
+            }),
+            None,
+        )
+    }
+
+    /// returns id of inserted node
+    pub fn insert_child_element_before(
+        &mut self,
+        namespace: Namespace,
+        name: usize,
+        attrs: usize,
+        before: usize,
+    ) -> usize {
+        let id = self.dom.next_node_id();
+        self.insert_child(
+            Node::Element(ElementNode {
+                id,
+                namespace,
+                name,
+                attrs,
+                kids: EMPTY_RANGE_INDEX,
+            }),
+            Some(before),
+        )
+    }
+
+    /// returns id of appended node
+    pub fn append_child_element_old(&mut self, name: usize, attrs: &[[usize; 2]]) -> usize {
         // sibling nodes *must* be contiguous in memory,
         // so we will copy the children into temp storage
-        // TODO: sibling block freelist
         let kids = self.dom.ranges.items[self.node.kids].clone();
-        for kid in &mut self.dom.nodes[kids] {
+        for kid in &mut self.dom.nodes[kids.clone()] {
             self.dom.node_buf.push(kid.clone());
             // invalidate child
             kid.invalidate();
         }
+        self.dom.free_sibling_block(kids);
+
+        // add new child to temp storage
+        let attrs = self.dom.insert_attrs(attrs);
 
-        self.dom.node_id_counter += 1;
+        let id = self.dom.next_node_id();
         self.dom.node_buf.push(Node::Element(ElementNode {
-            id: self.dom.node_id_counter,
+            id,
+            namespace: Namespace::Html,
             name,
             attrs,
             kids: EMPTY_RANGE_INDEX,
         }));
 
         // copy all the childen back into nodes
-        let start = self.dom.nodes.len();
-        let end = start + self.dom.node_buf.len();
-        self.dom.nodes.extend(self.dom.node_buf.drain(..));
-        let kids = self.dom.insert_range(start..end);
+        let kids = self.dom.push_sibling_block(self.node.id, self.node.kids);
 
         // update the parent node to new children
         let node = ElementNode { kids, ..self.node };
         self.node = node;
-        self.dom.nodes[self.index] = Node::Element(node);
+        self.dom.set_parent_node(self.index, node);
 
-        self.dom.node_id_counter
+        id
     }
 
     /// returns id of appended node
-    pub fn append_child_element_old(&mut self, name: usize, attrs: &[[usize; 2]]) -> usize {
-        // sibling nodes *must* be contiguous in memory,
-        // so we will copy the children into temp storage
-        // TODO: sibling block freelist
+    pub fn append_child_text(&mut self, text: &str) -> usize {
+        let id = self.dom.next_node_id();
+        let range = self.dom.push_text(text);
+        self.insert_child(Node::Text(TextNode { id, range }), None)
+    }
+
+    /// returns id of inserted node
+    pub fn insert_child_text_before(&mut self, text: &str, before: usize) -> usize {
+        let id = self.dom.next_node_id();
+        let range = self.dom.push_text(text);
+        self.insert_child(Node::Text(TextNode { id, range }), Some(before))
+    }
+
+    /// returns id of appended node
+    pub fn append_child_comment(&mut self, data: usize) -> usize {
+        let id = self.dom.next_node_id();
+        self.insert_child(Node::Comment(CommentNode { id, data }), None)
+    }
+
+    /// returns id of inserted node
+    pub fn insert_child_comment_before(&mut self, data: usize, before: usize) -> usize {
+        let id = self.dom.next_node_id();
+        self.insert_child(Node::Comment(CommentNode { id, data }), Some(before))
+    }
+
+    /// returns id of appended node
+    pub fn append_child_doctype(
+        &mut self,
+        name: usize,
+        public_id: Option<usize>,
+        system_id: Option<usize>,
+    ) -> usize {
+        let id = self.dom.next_node_id();
+        let node = DocTypeNode {
+            id,
+            name,
+            public_id,
+            system_id,
+        };
+        self.insert_child(Node::DocType(node), None)
+    }
+
+    /// returns id of appended node
+    pub fn append_child_processing_instruction(&mut self, target: usize, data: usize) -> usize {
+        let id = self.dom.next_node_id();
+        let node = ProcessingInstructionNode { id, target, data };
+        self.insert_child(Node::ProcessingInstruction(node), None)
+    }
+
+    /// moves the node and its children here, before the child with id
+    /// `before`, or last if `None`
+    pub fn insert_before(&mut self, id: usize, before: Option<usize>) {
+        self.dom.move_node(id, self.node.id, before);
+        self.reload();
+    }
+
+    /// removes the child and its children from the tree,
+    /// returns false if `id` isn't a child of this node
+    pub fn remove_child(&mut self, id: usize) -> bool {
+        if self.dom.get_parent_node_id(id) != Some(self.node.id) {
+            return false;
+        }
+        self.dom.remove_node(id);
+        self.reload();
+        true
+    }
+
+    /// puts the node `new` in place of the child `old`, which is removed
+    /// from the tree, returns false if `old` isn't a child of this node
+    pub fn replace_child(&mut self, new: usize, old: usize) -> bool {
+        if self.dom.get_parent_node_id(old) != Some(self.node.id) {
+            return false;
+        }
+        if new != old {
+            self.dom.move_node(new, self.node.id, Some(old));
+            self.dom.remove_node(old);
+            self.reload();
+        }
+        true
+    }
+
+    /// moves all the children to the end of `to`
+    pub fn reparent_children(&mut self, to: usize) {
+        self.dom.move_children(self.node.id, to);
+        self.reload();
+    }
+
+    /// returns false if the attribute wasn't set
+    pub fn remove_attr(&mut self, name: &str) -> bool {
+        let Some(name) = self.dom.find_str(name) else {
+            return false;
+        };
+        let attrs = self.dom.ranges.items[self.node.attrs].clone();
+        let Some(position) = self.dom.attrs[attrs.clone()]
+            .iter()
+            .position(|[k, _]| *k == name)
+        else {
+            return false;
+        };
+
+        self.dom.attr_buf.extend_from_slice(&self.dom.attrs[attrs]);
+        self.dom.attr_buf.remove(position);
+        self.push_attr_block();
+        true
+    }
+
+    /// moves the attrs in `attr_buf` into a new block for the node, the old
+    /// block may be shared with other elements, compact() reclaims it
+    fn push_attr_block(&mut self) -> usize {
+        let start = self.dom.attrs.len();
+        self.dom.attrs.extend(self.dom.attr_buf.drain(..));
+        let attrs = match self.dom.attrs.len() {
+            end if end == start => EMPTY_RANGE_INDEX,
+            end => self.dom.insert_range(start..end),
+        };
+
+        // update the node
+        let node = ElementNode { attrs, ..self.node };
+        self.node = node;
+        self.dom.set_parent_node(self.index, node);
+
+        attrs
+    }
+
+    /// operations through the dom may have moved this node
+    fn reload(&mut self) {
+        self.index = self.dom.node_indices[self.node.id];
+        self.node = *self.dom.nodes[self.index].as_parent().unwrap();
+    }
+
+    /// returns the removed child, which keeps its own children
+    fn detach_child(&mut self, id: usize) -> Option<Node> {
         let kids = self.dom.ranges.items[self.node.kids].clone();
-        for kid in &mut self.dom.nodes[kids] {
-            self.dom.node_buf.push(kid.clone());
+        let mut child = None;
+        for kid in &mut self.dom.nodes[kids.clone()] {
+            if kid.id() == id {
+                child = Some(kid.clone());
+            } else {
+                self.dom.node_buf.push(kid.clone());
+            }
             // invalidate child
             kid.invalidate();
         }
+        self.dom.free_sibling_block(kids);
 
-        // add new child to temp storage
-        let attrs = self.dom.insert_attrs(attrs);
+        // copy the remaining children back into nodes
+        let kids = self.dom.push_sibling_block(self.node.id, self.node.kids);
+        if child.is_some() {
+            self.dom.node_indices[id] = INVALID_NODE_INDEX;
+            self.dom.parents[id] = INVALID_NODE_ID;
+        }
 
-        self.dom.node_id_counter += 1;
-        self.dom.node_buf.push(Node::Element(ElementNode {
-            id: self.dom.node_id_counter,
-            name,
-            attrs,
+        // update the parent node to new children
+        let node = ElementNode { kids, ..self.node };
+        self.node = node;
+        self.dom.set_parent_node(self.index, node);
+
+        child
+    }
+
+    /// returns the range of the children, which are no longer part of the node
+    fn take_children(&mut self) -> Range<usize> {
+        let kids = self.dom.ranges.items[self.node.kids].clone();
+        self.dom.free_range(self.node.kids);
+        let node = ElementNode {
             kids: EMPTY_RANGE_INDEX,
-        }));
+            ..self.node
+        };
+        self.node = node;
+        self.dom.set_parent_node(self.index, node);
+        kids
+    }
+
+    /// appends the nodes in `moved`, which must not belong to another node
+    fn append_children(&mut self, moved: Range<usize>) {
+        // sibling nodes *must* be contiguous in memory,
+        // so we will copy both blocks into temp storage
+        let kids = self.dom.ranges.items[self.node.kids].clone();
+        for kids in [kids, moved] {
+            for kid in &mut self.dom.nodes[kids.clone()] {
+                self.dom.node_buf.push(kid.clone());
+                // invalidate child
+                kid.invalidate();
+            }
+            self.dom.free_sibling_block(kids);
+        }
 
         // copy all the childen back into nodes
-        let start = self.dom.nodes.len();
-        let end = start + self.dom.node_buf.len();
-        self.dom.nodes.extend(self.dom.node_buf.drain(..));
-        let kids = self.dom.insert_range(start..end);
+        let kids = self.dom.push_sibling_block(self.node.id, self.node.kids);
 
         // update the parent node to new children
         let node = ElementNode { kids, ..self.node };
         self.node = node;
-        self.dom.nodes[self.index] = Node::Element(node);
-
-        self.dom.node_id_counter
+        self.dom.set_parent_node(self.index, node);
     }
 
-    /// returns id of appended node
-    pub fn append_child_text(&mut self, text: &str) -> usize {
+    /// inserts the node before the child with id `before`, or last if `None`
+    fn insert_child(&mut self, child: Node, before: Option<usize>) -> usize {
+        let id = child.id();
+        let kids = self.dom.ranges.items[self.node.kids].clone();
+        if before.is_none() && !kids.is_empty() && self.dom.grow_sibling_block(kids.end) {
+            // the siblings stay where they are and the child goes right after
+            self.dom.nodes[kids.end] = child;
+            self.dom.node_indices[id] = kids.end;
+            self.dom.parents[id] = self.node.id;
+            self.dom.ranges.items[self.node.kids].end += 1;
+            return id;
+        }
+
         // sibling nodes *must* be contiguous in memory,
         // so we will copy the children into temp storage
-        // TODO: sibling block freelist
-        let kids = self.dom.ranges.items[self.node.kids].clone();
-        for kid in &mut self.dom.nodes[kids] {
+        let mut child = Some(child);
+        for kid in &mut self.dom.nodes[kids.clone()] {
+            if before == Some(kid.id()) {
+                self.dom.node_buf.extend(child.take());
+            }
             self.dom.node_buf.push(kid.clone());
             // invalidate child
             kid.invalidate();
         }
-
-        // add new child to temp storage
-        self.dom.node_id_counter += 1;
-        let range = self.dom.text.append(text);
-        self.dom.node_buf.push(Node::Text(TextNode {
-            id: self.dom.node_id_counter,
-            range: range.clone(),
-        }));
+        self.dom.node_buf.extend(child);
+        self.dom.free_sibling_block(kids);
 
         // copy all the childen back into nodes
-        let start = self.dom.nodes.len();
-        let end = start + self.dom.node_buf.len();
-        self.dom.nodes.extend(self.dom.node_buf.drain(..));
-        let kids = self.dom.insert_range(start..end);
+        let kids = self.dom.push_sibling_block(self.node.id, self.node.kids);
 
         // update the parent node to new children
         let node = ElementNode { kids, ..self.node };
         self.node = node;
-        self.dom.nodes[self.index] = Node::Element(node);
+        self.dom.set_parent_node(self.index, node);
 
-        self.dom.node_id_counter
+        id
     }
 
     /// returns possibly updated index of attrs for node
@@ -485,7 +1572,6 @@ impl<'a> ElementNodeHandleMut<'a> {
 
         // like sibling nodes, attrs *must* be contiguous in memory
         // we'll copy them into temp storage
-        // TODO: attr block freelist
         let old_attrs = self.dom.ranges.items[self.node.attrs].clone();
         self.dom
             .attr_buf
@@ -505,25 +1591,13 @@ impl<'a> ElementNodeHandleMut<'a> {
         }
 
         // copy all the ranges back in
-        let start = self.dom.attrs.len();
-        let end = start + self.dom.attr_buf.len();
-        self.dom.attrs.extend(self.dom.attr_buf.drain(..));
-        let attrs = self.dom.insert_range(start..end);
-
-        // update the node
-        let node = ElementNode { attrs, ..self.node };
-        self.node = node;
-        self.dom.nodes[self.index] = Node::Element(node);
-
-        attrs
+        self.push_attr_block()
     }
 
     /// returns possibly updated index of attrs for node
-    // TODO: I don't even think I need to add attributes
-    fn insert_attr(&mut self, name: &str, value: &str) -> usize {
+    pub fn set_attr(&mut self, name: &str, value: &str) -> usize {
         // like sibling nodes, attrs *must* be contiguous in memory
         // we'll copy them into temp storage
-        // TODO: attr block freelist
         let attrs = self.dom.ranges.items[self.node.attrs].clone();
         self.dom.attr_buf.extend_from_slice(&self.dom.attrs[attrs]);
 
@@ -537,17 +1611,76 @@ impl<'a> ElementNodeHandleMut<'a> {
         }
 
         // copy all the ranges back in
-        let start = self.dom.attrs.len();
-        let end = start + self.dom.attr_buf.len();
-        self.dom.attrs.extend(self.dom.attr_buf.drain(..));
-        let attrs = self.dom.insert_range(start..end);
+        self.push_attr_block()
+    }
+}
 
-        // update the node
-        let node = ElementNode { attrs, ..self.node };
-        self.node = node;
-        self.dom.nodes[self.index] = Node::Element(node);
+// Open addressing over the interned strings, the text itself stays in
+// the soup so each slot only holds the hash and the range index
+struct StrIndex {
+    slots: Vec<(u64, usize)>, // EMPTY_RANGE_INDEX marks a free slot
+    len: usize,
+    hasher: RandomState,
+}
 
-        attrs
+impl StrIndex {
+    fn new() -> Self {
+        Self {
+            slots: vec![(0, EMPTY_RANGE_INDEX); 64],
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn hash(&self, text: &str) -> u64 {
+        self.hasher.hash_one(text)
+    }
+
+    /// `Ok` with the range index of the matching string, or `Err` with
+    /// the free slot it would be inserted into
+    fn probe(&self, hash: u64, eq: impl Fn(usize) -> bool) -> Result<usize, usize> {
+        let mask = self.slots.len() - 1;
+        let mut slot = hash as usize & mask;
+        loop {
+            match self.slots[slot] {
+                (_, EMPTY_RANGE_INDEX) => return Err(slot),
+                (h, index) if h == hash && eq(index) => return Ok(index),
+                _ => slot = (slot + 1) & mask,
+            }
+        }
+    }
+
+    fn insert(&mut self, slot: usize, hash: u64, index: usize) {
+        self.slots[slot] = (hash, index);
+        self.len += 1;
+        if self.len * 2 > self.slots.len() {
+            self.grow();
+        }
+    }
+
+    /// drops the strings that `keep` returns false for
+    fn retain(&mut self, keep: impl Fn(usize) -> bool) {
+        let slots = vec![(0, EMPTY_RANGE_INDEX); 64];
+        let old = std::mem::replace(&mut self.slots, slots);
+        self.len = 0;
+        for (hash, index) in old {
+            if index != EMPTY_RANGE_INDEX && keep(index) {
+                let slot = self.probe(hash, |_| false).unwrap_err();
+                self.insert(slot, hash, index);
+            }
+        }
+    }
+
+    fn grow(&mut self) {
+        let slots = vec![(0, EMPTY_RANGE_INDEX); self.slots.len() * 2];
+        let old = std::mem::replace(&mut self.slots, slots);
+        for (hash, index) in old {
+            if index != EMPTY_RANGE_INDEX {
+                // every string is unique so any free slot will do
+                let slot = self.probe(hash, |_| false).unwrap_err();
+                self.slots[slot] = (hash, index);
+            }
+        }
     }
 }
 
@@ -610,25 +1743,390 @@ mod tests {
         assert_eq!(11..15, range);
     }
 
+    #[test]
+    fn atoms() {
+        let mut dom = Dom::new();
+        assert_eq!(atoms::DIV, dom.insert_str("div"));
+        assert_eq!(Some("div"), dom.get_str(atoms::DIV));
+        assert_eq!(Some("foreignObject"), dom.get_str(atoms::FOREIGN_OBJECT));
+        let custom = dom.insert_str("custom-element");
+        assert!(custom >= atoms::FIRST_ATOM + atoms::NAMES.len());
+    }
+
+    #[test]
+    fn interning() {
+        let mut dom = Dom::new();
+        assert_eq!(EMPTY_RANGE_INDEX, dom.insert_str(""));
+        let ids: Vec<_> = (0..1000).map(|i| dom.insert_str(&i.to_string())).collect();
+        let len = dom.text.items.len();
+        for (i, &id) in ids.iter().enumerate() {
+            assert_eq!(id, dom.insert_str(&i.to_string()));
+            assert_eq!(Some(id), dom.find_str(&i.to_string()));
+        }
+        assert_eq!(len, dom.text.items.len());
+        assert_eq!(None, dom.find_str("1000"));
+        assert!(dom.get_element_node_by_attr("1000", "1").is_none());
+        assert_eq!(None, dom.find_str("1000"));
+    }
+
+    #[test]
+    fn node_lookup() {
+        let mut dom = Dom::new();
+        let div = dom.insert_str("div");
+        let ids: Vec<_> = (0..10)
+            .map(|_| {
+                let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+                root.append_child_element(Namespace::Html, div, EMPTY_RANGE_INDEX)
+            })
+            .collect();
+        let kids = dom.get_element_node(ROOT_NODE_ID).unwrap().child_indices();
+        for (&id, kid) in ids.iter().zip(kids) {
+            let (index, node) = dom.get_node_by_id(id).unwrap();
+            assert_eq!(id, node.id());
+            assert_eq!(kid, index);
+        }
+        dom.remove_node(ids[3]);
+        assert!(dom.get_element_node(ids[3]).is_none());
+        assert_eq!(ids[4], dom.get_element_node(ids[4]).unwrap().node.id);
+        dom.move_node(ids[0], ids[9], None);
+        assert_eq!(Some(ids[9]), dom.get_parent_node_id(ids[0]));
+        assert_eq!(ids[0], dom.get_element_node(ids[0]).unwrap().node.id);
+    }
+
+    #[test]
+    fn sibling_block_reuse() {
+        let mut dom = Dom::new();
+        let div = dom.insert_str("div");
+        let append = |dom: &mut Dom, parent| {
+            let mut parent = dom.get_element_node_mut(parent).unwrap();
+            parent.append_child_element(Namespace::Html, div, EMPTY_RANGE_INDEX)
+        };
+        let a = append(&mut dom, ROOT_NODE_ID);
+        let b = append(&mut dom, ROOT_NODE_ID);
+        for _ in 0..10 {
+            append(&mut dom, a);
+        }
+        dom.remove_node(a);
+        let len = dom.nodes.len();
+        for _ in 0..10 {
+            append(&mut dom, b);
+        }
+        // the children of `b` fit in the blocks freed by `a`
+        assert_eq!(len, dom.nodes.len());
+    }
+
+    #[test]
+    fn append_in_place() {
+        let mut dom = Dom::new();
+        let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+        root.append_child_text("a");
+        let capacity = dom.node_buf.capacity();
+        let first = dom.get_first_child_id(ROOT_NODE_ID).unwrap();
+        let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+        for _ in 0..1000 {
+            root.append_child_text("a");
+        }
+        // the siblings were never copied through node_buf
+        assert_eq!(capacity, dom.node_buf.capacity());
+        assert_eq!(
+            1001,
+            dom.get_element_node(ROOT_NODE_ID)
+                .unwrap()
+                .child_indices()
+                .len()
+        );
+        assert_eq!(Some(first), dom.get_first_child_id(ROOT_NODE_ID));
+    }
+
+    #[test]
+    fn append_many_children() {
+        let mut dom = Dom::new();
+        let div = dom.insert_str("div");
+        let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+        let parent = root.append_child_element(Namespace::Html, div, EMPTY_RANGE_INDEX);
+        for i in 0..5000 {
+            let mut parent = dom.get_element_node_mut(parent).unwrap();
+            let child = parent.append_child_element(Namespace::Html, div, EMPTY_RANGE_INDEX);
+            // children of children take the end of nodes between appends
+            if i % 2 == 0 {
+                let mut child = dom.get_element_node_mut(child).unwrap();
+                child.append_child_text("a");
+            }
+        }
+        // the root, the parent, 5000 children and 2500 text nodes
+        assert!(dom.nodes.len() < 2 * 7502);
+    }
+
+    #[test]
+    fn compact() {
+        let mut dom = Dom::new();
+        let div = dom.insert_str("div");
+        let mut ids = Vec::new();
+        for i in 0..20 {
+            let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+            let id = root.append_child_element(Namespace::Html, div, EMPTY_RANGE_INDEX);
+            let mut element = dom.get_element_node_mut(id).unwrap();
+            element.set_attr("id", &i.to_string());
+            element.append_child_text(&format!("text {i}"));
+            ids.push(id);
+        }
+        for &id in ids.iter().step_by(2) {
+            dom.remove_node(id);
+        }
+        let mut before = Vec::new();
+        dom.write_tree(&mut before).unwrap();
+        let text_len = dom.text.items.len();
+
+        dom.compact();
+        let mut after = Vec::new();
+        dom.write_tree(&mut after).unwrap();
+        assert_eq!(String::from_utf8(before), String::from_utf8(after));
+        let mut junk = Vec::new();
+        dom.write_junk(&mut junk).unwrap();
+        assert!(junk.is_empty());
+        assert_eq!(21, dom.nodes.len());
+        assert!(dom.text.items.len() < text_len);
+        for &id in ids.iter().skip(1).step_by(2) {
+            let element = dom.get_element_node(id).unwrap();
+            assert_eq!(1, element.child_indices().len());
+        }
+        assert!(dom.get_element_node(ids[0]).is_none());
+        // only the attr values of removed elements are gone
+        assert_eq!(None, dom.find_str("18"));
+        let value = dom.find_str("19").unwrap();
+        let element = dom.get_element_node(ids[19]).unwrap();
+        assert_eq!(value, element.attrs()[0][1]);
+        assert_eq!(value, dom.insert_str("19"));
+    }
+
+    #[test]
+    fn text_growth() {
+        let mut dom = Dom::new();
+        let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+        let a = root.append_child_text("a");
+        let b = root.append_child_text("b");
+        let len = dom.text.items.len();
+        // a isn't at the end of the soup, so it's moved there once
+        for _ in 0..100 {
+            dom.get_text_node_mut(a).unwrap().push_str("a");
+        }
+        assert_eq!(len + 101, dom.text.items.len());
+        dom.get_text_node_mut(b).unwrap().push_str("b");
+        assert_eq!("a".repeat(101), dom.get_text_node(a).unwrap().text());
+        assert_eq!("bb", dom.get_text_node(b).unwrap().text());
+    }
+
+    #[test]
+    fn navigation() {
+        let mut dom = Dom::new();
+        let div = dom.insert_str("div");
+        let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+        let a = root.append_child_element(Namespace::Html, div, EMPTY_RANGE_INDEX);
+        let c = root.append_child_element(Namespace::Html, div, EMPTY_RANGE_INDEX);
+        let b = root.insert_child_element_before(Namespace::Html, div, EMPTY_RANGE_INDEX, c);
+        let text = dom.get_element_node_mut(b).unwrap().append_child_text("b");
+
+        let root = dom.get_element_node(ROOT_NODE_ID).unwrap();
+        assert_eq!(None, root.parent());
+        assert_eq!(Some(a), root.first_child());
+        assert_eq!(Some(c), root.last_child());
+        let b = dom.get_element_node(b).unwrap();
+        assert_eq!(Some(ROOT_NODE_ID), b.parent());
+        assert_eq!(Some(a), b.previous_sibling());
+        assert_eq!(Some(c), b.next_sibling());
+        assert_eq!(b.first_child(), b.last_child());
+        let text = dom.get_text_node(text).unwrap();
+        assert_eq!(Some(b.id()), text.parent());
+        assert_eq!(None, text.next_sibling());
+        assert_eq!(None, dom.get_next_sibling_id(c));
+        assert_eq!(None, dom.get_previous_sibling_id(a));
+
+        // moving a node updates both old and new parents
+        let b = b.id();
+        dom.move_node(a, b, None);
+        assert_eq!(Some(b), dom.get_parent_node_id(a));
+        assert_eq!(Some(b), dom.get_first_child_id(ROOT_NODE_ID));
+        assert_eq!(Some(a), dom.get_last_child_id(b));
+        assert_eq!(None, dom.get_first_child_id(a));
+        dom.remove_node(b);
+        assert_eq!(None, dom.get_parent_node_id(b));
+        assert_eq!(None, dom.get_parent_node_id(a));
+        assert_eq!(Some(c), dom.get_first_child_id(ROOT_NODE_ID));
+    }
+
+    #[test]
+    fn node_kinds() {
+        let mut dom = Dom::new();
+        let html = dom.insert_str("html");
+        let system_id = dom.insert_str("about:legacy-compat");
+        let data = dom.insert_str(" note ");
+        let target = dom.insert_str("xml");
+        let version = dom.insert_str("version=\"1.0\"");
+        let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+        let pi = root.append_child_processing_instruction(target, version);
+        let doctype = root.append_child_doctype(html, None, Some(system_id));
+        let element = root.append_child_element(Namespace::Html, html, EMPTY_RANGE_INDEX);
+        let comment = root.insert_child_comment_before(data, element);
+
+        assert_eq!(Some(NodeKind::Document), dom.get_node_kind(ROOT_NODE_ID));
+        assert_eq!(Some(NodeKind::Element), dom.get_node_kind(element));
+        assert_eq!(Some(NodeKind::Comment), dom.get_node_kind(comment));
+        let doctype = dom.get_doctype_node(doctype).unwrap();
+        assert_eq!("html", doctype.name());
+        assert_eq!(None, doctype.public_id());
+        assert_eq!(Some("about:legacy-compat"), doctype.system_id());
+        assert_eq!(Some(comment), doctype.next_sibling());
+        let comment = dom.get_comment_node(comment).unwrap();
+        assert_eq!(" note ", comment.data());
+        assert_eq!(Some(ROOT_NODE_ID), comment.parent());
+        let pi = dom.get_processing_instruction_node(pi).unwrap();
+        assert_eq!("xml", pi.target());
+        assert_eq!("version=\"1.0\"", pi.data());
+        assert!(dom.get_element_node(comment.id()).is_none());
+
+        let mut out = Vec::new();
+        dom.write_tree(&mut out).unwrap();
+        assert_eq!(
+            "<>\n  <?xml version=\"1.0\">\n  <!DOCTYPE html>\n  <!-- note -->\n  <html>\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn html5lib_tree() {
+        let mut dom = Dom::new();
+        let html = dom.insert_str("html");
+        let svg = dom.insert_str("svg");
+        let data = dom.insert_str("a");
+        let href = dom.insert_str("xlink:href");
+        let class = dom.insert_str("class");
+        let value = dom.insert_str("b");
+        let attrs = dom.insert_attrs(&[[href, value], [class, value]]);
+        let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+        root.append_child_doctype(html, None, Some(value));
+        root.append_child_comment(data);
+        let html = root.append_child_element(Namespace::Html, html, EMPTY_RANGE_INDEX);
+        let mut html = dom.get_element_node_mut(html).unwrap();
+        let svg = html.append_child_element(Namespace::Svg, svg, attrs);
+        dom.get_element_node_mut(svg)
+            .unwrap()
+            .append_child_text("c\nd");
+
+        let mut out = Vec::new();
+        dom.write_html5lib_tree(&mut out).unwrap();
+        assert_eq!(
+            concat!(
+                "| <!DOCTYPE html \"\" \"b\">\n",
+                "| <!-- a -->\n",
+                "| <html>\n",
+                "|   <svg svg>\n",
+                "|     class=\"b\"\n",
+                "|     xlink href=\"b\"\n",
+                "|     \"c\nd\"\n",
+            ),
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn remove_detached() {
+        let mut dom = Dom::new();
+        let div = dom.insert_str("div");
+        let a = dom.create_element(Namespace::Html, div, EMPTY_RANGE_INDEX);
+        let b = dom.get_element_node_mut(a).unwrap().append_child_element(
+            Namespace::Html,
+            div,
+            EMPTY_RANGE_INDEX,
+        );
+        dom.remove_node(a);
+        assert!(dom.get_element_node(a).is_none());
+        assert!(dom.get_element_node(b).is_none());
+        dom.compact();
+        assert_eq!(1, dom.nodes.len());
+    }
+
+    #[test]
+    fn mutation() {
+        let mut dom = Dom::new();
+        let div = dom.insert_str("div");
+        let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+        let a = root.append_child_element(Namespace::Html, div, EMPTY_RANGE_INDEX);
+        let b = root.append_child_element(Namespace::Html, div, EMPTY_RANGE_INDEX);
+        let text = dom.get_element_node_mut(a).unwrap().append_child_text("a");
+        let c = dom.create_element(Namespace::Html, div, EMPTY_RANGE_INDEX);
+
+        // insert a detached node, then move it around
+        let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+        root.insert_before(c, Some(a));
+        assert_eq!(Some(c), root.first_child());
+        root.insert_before(c, None);
+        assert_eq!(Some(c), root.last_child());
+        assert_eq!(Some(b), dom.get_previous_sibling_id(c));
+
+        // replacing drops the old child and its children
+        let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+        assert!(root.replace_child(c, a));
+        assert!(!root.remove_child(a));
+        assert_eq!(Some(c), root.first_child());
+        assert!(dom.get_text_node(text).is_none());
+
+        let text = dom.get_element_node_mut(b).unwrap().append_child_text("b");
+        let mut b = dom.get_element_node_mut(b).unwrap();
+        b.reparent_children(c);
+        assert_eq!(None, b.first_child());
+        assert_eq!(Some(c), dom.get_parent_node_id(text));
+        let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+        assert!(root.remove_child(c));
+        assert_eq!(root.first_child(), root.last_child());
+
+        let b = root.last_child().unwrap();
+        let mut b = dom.get_element_node_mut(b).unwrap();
+        b.set_attr("href", "a");
+        b.set_attr("class", "b");
+        assert!(b.remove_attr("href"));
+        assert!(!b.remove_attr("href"));
+        let b = b.id();
+        let b = dom.get_element_node(b).unwrap();
+        let [name, value] = b.attrs()[0];
+        assert_eq!(1, b.attrs().len());
+        assert_eq!(Some("class"), dom.get_str(name));
+        assert_eq!(Some("b"), dom.get_str(value));
+    }
+
+    #[test]
+    #[should_panic]
+    fn move_into_descendant() {
+        let mut dom = Dom::new();
+        let div = dom.insert_str("div");
+        let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+        let a = root.append_child_element(Namespace::Html, div, EMPTY_RANGE_INDEX);
+        let mut parent = dom.get_element_node_mut(a).unwrap();
+        let b = parent.append_child_element(Namespace::Html, div, EMPTY_RANGE_INDEX);
+        let mut b = dom.get_element_node_mut(b).unwrap();
+        b.insert_before(a, None);
+    }
+
     #[test]
     fn root_attrs() {
         let mut dom = Dom::new();
+        let start = dom.text.items.len();
         // add and update some attrs
         {
             let mut root = dom.get_element_node_mut(ROOT_NODE_INDEX).unwrap();
-            let attrs = root.insert_attr("key", "value");
+            let attrs = root.set_attr("key", "value");
             let attrs = root.dom.ranges.items[attrs].clone();
-            let attr = root.dom.attrs[attrs][0].clone();
+            let attr = root.dom.attrs[attrs][0];
             assert_eq!("key", root.dom.get_str(attr[0]).unwrap());
             assert_eq!("value", root.dom.get_str(attr[1]).unwrap());
 
-            let attrs = root.insert_attr("key", "new");
+            let attrs = root.set_attr("key", "new");
             let attrs = root.dom.ranges.items[attrs].clone();
-            let attr = root.dom.attrs[attrs][0].clone();
+            let attr = root.dom.attrs[attrs][0];
             assert_eq!("key", root.dom.get_str(attr[0]).unwrap());
             assert_eq!("new", root.dom.get_str(attr[1]).unwrap());
         }
-        assert_eq!("keyvaluenew".as_bytes(), dom.text.items);
+        // "value" is an atom
+        assert_eq!("keynew".as_bytes(), &dom.text.items[start..]);
 
         // find root node by attr
         assert_eq!(
@@ -651,9 +2149,9 @@ mod tests {
 
         {
             let mut child = dom.get_element_node_mut(index).unwrap();
-            let attrs = child.insert_attr("key", "value");
+            let attrs = child.set_attr("key", "value");
             let attrs = child.dom.ranges.items[attrs].clone();
-            let attr = child.dom.attrs[attrs][1].clone();
+            let attr = child.dom.attrs[attrs][1];
             assert_eq!("key", child.dom.get_str(attr[0]).unwrap());
             assert_eq!("value", child.dom.get_str(attr[1]).unwrap());
         }
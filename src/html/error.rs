@@ -0,0 +1,159 @@
+/// The parse errors defined by the WHATWG HTML standard
+///
+/// None of these stop parsing, they are reported alongside the tokens and tree
+/// so that callers can list every problem in a document.
+#[derive(thiserror::Error, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParseError {
+    #[error("abrupt-closing-of-empty-comment")]
+    AbruptClosingOfEmptyComment,
+
+    #[error("abrupt-doctype-public-identifier")]
+    AbruptDoctypePublicIdentifier,
+
+    #[error("abrupt-doctype-system-identifier")]
+    AbruptDoctypeSystemIdentifier,
+
+    #[error("absence-of-digits-in-numeric-character-reference")]
+    AbsenceOfDigitsInNumericCharacterReference,
+
+    #[error("cdata-in-html-content")]
+    CdataInHtmlContent,
+
+    #[error("character-reference-outside-unicode-range")]
+    CharacterReferenceOutsideUnicodeRange,
+
+    #[error("control-character-in-input-stream")]
+    ControlCharacterInInputStream,
+
+    #[error("control-character-reference")]
+    ControlCharacterReference,
+
+    #[error("duplicate-attribute")]
+    DuplicateAttribute,
+
+    #[error("end-tag-with-attributes")]
+    EndTagWithAttributes,
+
+    #[error("end-tag-with-trailing-solidus")]
+    EndTagWithTrailingSolidus,
+
+    #[error("eof-before-tag-name")]
+    EofBeforeTagName,
+
+    #[error("eof-in-cdata")]
+    EofInCdata,
+
+    #[error("eof-in-comment")]
+    EofInComment,
+
+    #[error("eof-in-doctype")]
+    EofInDoctype,
+
+    #[error("eof-in-script-html-comment-like-text")]
+    EofInScriptHtmlCommentLikeText,
+
+    #[error("eof-in-tag")]
+    EofInTag,
+
+    #[error("incorrectly-closed-comment")]
+    IncorrectlyClosedComment,
+
+    #[error("incorrectly-opened-comment")]
+    IncorrectlyOpenedComment,
+
+    #[error("invalid-character-sequence-after-doctype-name")]
+    InvalidCharacterSequenceAfterDoctypeName,
+
+    #[error("invalid-first-character-of-tag-name")]
+    InvalidFirstCharacterOfTagName,
+
+    #[error("missing-attribute-value")]
+    MissingAttributeValue,
+
+    #[error("missing-doctype-name")]
+    MissingDoctypeName,
+
+    #[error("missing-doctype-public-identifier")]
+    MissingDoctypePublicIdentifier,
+
+    #[error("missing-doctype-system-identifier")]
+    MissingDoctypeSystemIdentifier,
+
+    #[error("missing-end-tag-name")]
+    MissingEndTagName,
+
+    #[error("missing-quote-before-doctype-public-identifier")]
+    MissingQuoteBeforeDoctypePublicIdentifier,
+
+    #[error("missing-quote-before-doctype-system-identifier")]
+    MissingQuoteBeforeDoctypeSystemIdentifier,
+
+    #[error("missing-semicolon-after-character-reference")]
+    MissingSemicolonAfterCharacterReference,
+
+    #[error("missing-whitespace-after-doctype-public-keyword")]
+    MissingWhitespaceAfterDoctypePublicKeyword,
+
+    #[error("missing-whitespace-after-doctype-system-keyword")]
+    MissingWhitespaceAfterDoctypeSystemKeyword,
+
+    #[error("missing-whitespace-before-doctype-name")]
+    MissingWhitespaceBeforeDoctypeName,
+
+    #[error("missing-whitespace-between-attributes")]
+    MissingWhitespaceBetweenAttributes,
+
+    #[error("missing-whitespace-between-doctype-public-and-system-identifiers")]
+    MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
+
+    #[error("nested-comment")]
+    NestedComment,
+
+    #[error("noncharacter-character-reference")]
+    NoncharacterCharacterReference,
+
+    #[error("noncharacter-in-input-stream")]
+    NoncharacterInInputStream,
+
+    #[error("non-void-html-element-start-tag-with-trailing-solidus")]
+    NonVoidHtmlElementStartTagWithTrailingSolidus,
+
+    #[error("null-character-reference")]
+    NullCharacterReference,
+
+    #[error("surrogate-character-reference")]
+    SurrogateCharacterReference,
+
+    // a `str` can't hold surrogates, invalid input fails to decode instead
+    #[allow(dead_code)]
+    #[error("surrogate-in-input-stream")]
+    SurrogateInInputStream,
+
+    #[error("unexpected-character-after-doctype-system-identifier")]
+    UnexpectedCharacterAfterDoctypeSystemIdentifier,
+
+    #[error("unexpected-character-in-attribute-name")]
+    UnexpectedCharacterInAttributeName,
+
+    #[error("unexpected-character-in-unquoted-attribute-value")]
+    UnexpectedCharacterInUnquotedAttributeValue,
+
+    #[error("unexpected-equals-sign-before-attribute-name")]
+    UnexpectedEqualsSignBeforeAttributeName,
+
+    #[error("unexpected-null-character")]
+    UnexpectedNullCharacter,
+
+    #[error("unexpected-question-mark-instead-of-tag-name")]
+    UnexpectedQuestionMarkInsteadOfTagName,
+
+    #[error("unexpected-solidus-in-tag")]
+    UnexpectedSolidusInTag,
+
+    #[error("unknown-named-character-reference")]
+    UnknownNamedCharacterReference,
+
+    // the tree construction stage doesn't name its errors
+    #[error("unexpected-token")]
+    UnexpectedToken,
+}
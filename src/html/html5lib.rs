@@ -0,0 +1,483 @@
+// Runs the vendored html5lib-tests fixtures through the tokenizer and the
+// tree builder and prints a pass/fail matrix per fixture file.
+//
+// Cases that are expected to fail are listed in `known-failures/`, one id per
+// line. A case failing that isn't on the list, or a listed case passing, fails
+// the test so the lists always match reality. Run with
+// `UPDATE_KNOWN_FAILURES=1 cargo test html5lib` to rewrite them.
+//
+// Parse errors aren't compared: the fixtures predate the error codes and only
+// list `ParseError` inline, so only the tokens and trees are checked.
+
+use std::{
+    collections::BTreeSet,
+    env, fs, panic,
+    path::Path,
+    pin::Pin,
+    process,
+    sync::{mpsc, Once},
+    task::{Context, Poll},
+    thread,
+    time::Duration,
+};
+
+use serde_json::{json, Map, Value};
+use smol::io::Cursor;
+
+use super::{
+    tokenizer::tests::MockInterner, Interner, ParseEvent, Parser, State, Token, Tokenizer,
+};
+use crate::{
+    asyncro,
+    dom::{Dom, Namespace, ROOT_NODE_ID},
+    io::AsyncStrReader,
+};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/html5lib-tests");
+const KNOWN_FAILURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/html/known-failures");
+const TIMEOUT: Duration = Duration::from_secs(2);
+// polls per input byte before a case counts as stuck in a loop
+const STEPS_PER_BYTE: usize = 64;
+const CASE_THREAD_NAME: &str = "html5lib-case";
+
+enum Outcome {
+    Pass,
+    Fail(String),
+    Skip,
+}
+
+#[derive(Default)]
+struct Report {
+    rows: Vec<(String, [usize; 3])>,
+    failures: Vec<(String, String)>,
+}
+
+impl Report {
+    fn record(&mut self, file: &str, id: String, outcome: Outcome) {
+        if self.rows.last().map(|(name, _)| name.as_str()) != Some(file) {
+            self.rows.push((file.into(), [0; 3]));
+        }
+        let counts = &mut self.rows.last_mut().unwrap().1;
+        match outcome {
+            Outcome::Pass => counts[0] += 1,
+            Outcome::Fail(reason) => {
+                counts[1] += 1;
+                self.failures.push((id, reason));
+            }
+            Outcome::Skip => counts[2] += 1,
+        }
+    }
+
+    fn print(&self, suite: &str) {
+        println!("html5lib {suite}");
+        println!("  {:<48} {:>6} {:>6} {:>6}", "file", "pass", "fail", "skip");
+        let mut total = [0; 3];
+        for (file, counts) in &self.rows {
+            println!(
+                "  {:<48} {:>6} {:>6} {:>6}",
+                file, counts[0], counts[1], counts[2]
+            );
+            for (total, count) in total.iter_mut().zip(counts) {
+                *total += count;
+            }
+        }
+        println!(
+            "  {:<48} {:>6} {:>6} {:>6}",
+            "total", total[0], total[1], total[2]
+        );
+    }
+
+    /// Compares the failures against the known failures list for the suite
+    fn check(&self, suite: &str) {
+        let path = Path::new(KNOWN_FAILURES).join(format!("{suite}.txt"));
+        if env::var_os("UPDATE_KNOWN_FAILURES").is_some() {
+            let mut list = String::new();
+            for (id, _) in &self.failures {
+                list.push_str(id);
+                list.push('\n');
+            }
+            fs::write(&path, list).unwrap();
+            return;
+        }
+        let known = fs::read_to_string(&path).unwrap_or_default();
+        let known: BTreeSet<_> = known
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+        let failed: BTreeSet<_> = self.failures.iter().map(|(id, _)| id.as_str()).collect();
+        let mut problems = Vec::new();
+        for (id, reason) in &self.failures {
+            if !known.contains(id.as_str()) {
+                problems.push(format!("{id} regressed: {reason}"));
+            }
+        }
+        for id in known.difference(&failed) {
+            problems.push(format!(
+                "{id} passes now, remove it from the known failures"
+            ));
+        }
+        assert!(problems.is_empty(), "\n{}\n", problems.join("\n"));
+    }
+}
+
+/// Runs a case on its own thread so panics only fail that case. Loops are
+/// caught by the step budget of each case, a case that still hangs inside a
+/// single poll can't be stopped, so the whole run is aborted instead of
+/// leaving the thread spinning.
+fn run_isolated<F: FnOnce() -> Outcome + Send + 'static>(f: F) -> Outcome {
+    static QUIET_PANICS: Once = Once::new();
+    QUIET_PANICS.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if thread::current().name() != Some(CASE_THREAD_NAME) {
+                default_hook(info);
+            }
+        }));
+    });
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::Builder::new()
+        .name(CASE_THREAD_NAME.into())
+        .spawn(move || {
+            let _ = sender.send(f());
+        })
+        .unwrap();
+    match receiver.recv_timeout(TIMEOUT) {
+        Ok(outcome) => outcome,
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            eprintln!("html5lib case timed out after {TIMEOUT:?}, aborting");
+            process::abort();
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            let panic = handle.join().unwrap_err();
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Outcome::Fail(format!("panicked: {message}"))
+        }
+    }
+}
+
+fn step_budget(input: &str) -> usize {
+    STEPS_PER_BYTE * (input.len() + 1)
+}
+
+fn fixture_files(dir: &str, extension: &str) -> Vec<(String, String)> {
+    let mut files: Vec<_> = fs::read_dir(Path::new(FIXTURES).join(dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    files.sort();
+    files
+        .into_iter()
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            // some of the fixtures are deliberately not valid UTF-8
+            let bytes = fs::read(&path).unwrap();
+            (name, String::from_utf8_lossy(&bytes).into_owned())
+        })
+        .collect()
+}
+
+/// Undoes the extra level of `\uXXXX` escaping used by `doubleEscaped` tests
+fn unescape(s: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let code = u32::from_str_radix(&hex, 16).ok()?;
+                // lone surrogates can't be represented in a `str`
+                out.push(char::from_u32(code)?);
+            }
+            Some(c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            None => out.push('\\'),
+        }
+    }
+    Some(out)
+}
+
+fn unescape_value(value: &Value) -> Option<Value> {
+    Some(match value {
+        Value::String(s) => Value::String(unescape(s)?),
+        Value::Array(values) => {
+            Value::Array(values.iter().map(unescape_value).collect::<Option<_>>()?)
+        }
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| Some((unescape(key)?, unescape_value(value)?)))
+                .collect::<Option<_>>()?,
+        ),
+        value => value.clone(),
+    })
+}
+
+/// Drops parse errors and merges adjacent character tokens
+fn normalize_tokens(tokens: &[Value]) -> Vec<Value> {
+    let mut out: Vec<Value> = Vec::new();
+    for token in tokens {
+        if token == "ParseError" {
+            continue;
+        }
+        if token[0] == "Character" {
+            if let Some(last) = out.last_mut() {
+                if last[0] == "Character" {
+                    let text = format!(
+                        "{}{}",
+                        last[1].as_str().unwrap(),
+                        token[1].as_str().unwrap()
+                    );
+                    *last = json!(["Character", text]);
+                    continue;
+                }
+            }
+        }
+        out.push(token.clone());
+    }
+    out
+}
+
+fn initial_state(name: &str) -> Option<State> {
+    Some(match name {
+        "Data state" => State::Data,
+        "PLAINTEXT state" => State::PlainText,
+        "RCDATA state" => State::RcData,
+        "RAWTEXT state" => State::RawText,
+        "Script data state" => State::ScriptData,
+        "CDATA section state" => State::CDataSection,
+        _ => return None,
+    })
+}
+
+fn tokenize(
+    input: String,
+    state: State,
+    last_start_tag: Option<String>,
+) -> Result<Vec<Value>, String> {
+    let mut int = MockInterner::new();
+    let mut cx = Context::from_waker(asyncro::noop_waker_ref());
+    let mut tokenizer = Tokenizer::new(AsyncStrReader::new(Cursor::new(input.clone())));
+    tokenizer.set_state(state);
+    if let Some(name) = last_start_tag {
+        tokenizer.set_last_start_tag(Some(int.intern_str(&name)));
+    }
+    let mut tokens = Vec::new();
+    let mut steps = step_budget(&input);
+    loop {
+        steps = steps.checked_sub(1).ok_or("too many steps")?;
+        let token = match Pin::new(&mut tokenizer).poll_next(&mut cx, &mut int) {
+            Poll::Ready(Some((_, Ok(token)))) => token,
+            Poll::Ready(Some((_, Err(err)))) => return Err(err.to_string()),
+            Poll::Ready(None) => break,
+            Poll::Pending => continue,
+        };
+        let string = |index: usize| int.strings[index].clone();
+        tokens.push(match token {
+            Token::Char(c) => json!(["Character", c.to_string()]),
+            Token::StartTag {
+                name,
+                attrs,
+                self_closing,
+            } => {
+                let mut map = Map::new();
+                for [name, value] in &int.attrs[attrs] {
+                    map.insert(string(*name), Value::String(string(*value)));
+                }
+                if self_closing {
+                    json!(["StartTag", string(name), map, true])
+                } else {
+                    json!(["StartTag", string(name), map])
+                }
+            }
+            Token::EndTag { name } => json!(["EndTag", string(name)]),
+            Token::Comment(data) => json!(["Comment", string(data)]),
+            Token::DocType {
+                name,
+                public_id,
+                system_id,
+                force_quirks,
+            } => json!([
+                "DOCTYPE",
+                name.map(string),
+                public_id.map(string),
+                system_id.map(string),
+                !force_quirks
+            ]),
+        });
+    }
+    Ok(normalize_tokens(&tokens))
+}
+
+fn tokenizer_case(test: &Value) -> Outcome {
+    let (input, output) = if test["doubleEscaped"].as_bool().unwrap_or(false) {
+        match (
+            unescape_value(&test["input"]),
+            unescape_value(&test["output"]),
+        ) {
+            (Some(input), Some(output)) => (input, output),
+            _ => return Outcome::Fail("lone surrogates can't be tokenized".into()),
+        }
+    } else {
+        (test["input"].clone(), test["output"].clone())
+    };
+    let input = input.as_str().unwrap().to_string();
+    let expected = normalize_tokens(output.as_array().unwrap());
+    let last_start_tag = test["lastStartTag"].as_str().map(String::from);
+    let states = match test["initialStates"].as_array() {
+        Some(states) => states.iter().map(|s| s.as_str().unwrap()).collect(),
+        None => vec!["Data state"],
+    };
+    for name in states {
+        let Some(state) = initial_state(name) else {
+            return Outcome::Fail(format!("unknown initial state {name}"));
+        };
+        let input = input.clone();
+        let last_start_tag = last_start_tag.clone();
+        let expected = expected.clone();
+        let name = name.to_string();
+        let outcome = run_isolated(move || match tokenize(input, state, last_start_tag) {
+            Ok(actual) if actual == expected => Outcome::Pass,
+            Ok(actual) => Outcome::Fail(format!(
+                "{name}: expected {} got {}",
+                Value::Array(expected),
+                Value::Array(actual)
+            )),
+            Err(err) => Outcome::Fail(err),
+        });
+        if !matches!(outcome, Outcome::Pass) {
+            return outcome;
+        }
+    }
+    Outcome::Pass
+}
+
+#[derive(Default)]
+struct TreeTest {
+    data: Vec<String>,
+    document: Vec<String>,
+    fragment: Option<String>,
+    scripting: Option<bool>,
+}
+
+fn parse_dat(content: &str) -> Vec<TreeTest> {
+    let mut tests: Vec<TreeTest> = Vec::new();
+    let mut section = "";
+    for line in content.lines() {
+        match line {
+            "#data" => tests.push(TreeTest::default()),
+            "#errors" | "#new-errors" | "#document-fragment" | "#document" => {}
+            "#script-on" | "#script-off" => {
+                tests.last_mut().unwrap().scripting = Some(line == "#script-on");
+            }
+            _ => {
+                let test = tests.last_mut().unwrap();
+                match section {
+                    "#data" => test.data.push(line.into()),
+                    "#document-fragment" => test.fragment = Some(line.into()),
+                    "#document" => test.document.push(line.into()),
+                    _ => {}
+                }
+                continue;
+            }
+        }
+        section = line;
+    }
+    for test in &mut tests {
+        // a blank line separates each test from the next
+        while test.document.last().is_some_and(|line| line.is_empty()) {
+            test.document.pop();
+        }
+    }
+    tests
+}
+
+fn parse(data: String, fragment: Option<String>) -> Result<Vec<String>, String> {
+    let mut steps = step_budget(&data);
+    let mut dom = Dom::new();
+    let mut cx = Context::from_waker(asyncro::noop_waker_ref());
+    let reader = AsyncStrReader::new(Cursor::new(data));
+    let mut parser = match fragment.as_deref() {
+        // foreign contexts are written as "svg path" or "math mi"
+        Some(context) => {
+            let (namespace, name) = match context.split_once(' ') {
+                Some(("svg", name)) => (Namespace::Svg, name),
+                Some(("math", name)) => (Namespace::MathMl, name),
+                _ => (Namespace::Html, context),
+            };
+            Parser::new_fragment(reader, &mut dom, namespace, name, ROOT_NODE_ID)
+        }
+        None => Parser::new(reader),
+    };
+    loop {
+        steps = steps.checked_sub(1).ok_or("too many steps")?;
+        match Pin::new(&mut parser).poll_next(&mut cx, &mut dom) {
+            Poll::Ready(ParseEvent::Done) => break,
+            Poll::Ready(ParseEvent::Fatal(_, err)) => return Err(err.to_string()),
+            Poll::Ready(_) | Poll::Pending => {}
+        }
+    }
+    // the text of multi-line text nodes spans several lines of the dump
+    let mut tree = Vec::new();
+    dom.write_html5lib_tree(&mut tree).unwrap();
+    let tree = String::from_utf8(tree).unwrap();
+    Ok(tree.lines().map(String::from).collect())
+}
+
+fn tree_construction_case(test: TreeTest) -> Outcome {
+    if test.scripting == Some(true) {
+        // we never run scripts
+        return Outcome::Skip;
+    }
+    let data = test.data.join("\n");
+    let expected = test.document;
+    let fragment = test.fragment;
+    run_isolated(move || match parse(data, fragment) {
+        Ok(actual) if actual == expected => Outcome::Pass,
+        Ok(actual) => Outcome::Fail(format!(
+            "expected\n{}\ngot\n{}",
+            expected.join("\n"),
+            actual.join("\n")
+        )),
+        Err(err) => Outcome::Fail(err),
+    })
+}
+
+#[test]
+fn tokenizer() {
+    let mut report = Report::default();
+    for (file, content) in fixture_files("tokenizer", "test") {
+        let json: Value = serde_json::from_str(&content).unwrap();
+        // xmlViolation.test is for an XML compatible mode that we don't have
+        let Some(tests) = json["tests"].as_array() else {
+            continue;
+        };
+        for (i, test) in tests.iter().enumerate() {
+            report.record(&file, format!("{file}#{i}"), tokenizer_case(test));
+        }
+    }
+    report.print("tokenizer");
+    report.check("tokenizer");
+}
+
+#[test]
+fn tree_construction() {
+    let mut report = Report::default();
+    for (file, content) in fixture_files("tree-construction", "dat") {
+        for (i, test) in parse_dat(&content).into_iter().enumerate() {
+            report.record(&file, format!("{file}#{i}"), tree_construction_case(test));
+        }
+    }
+    report.print("tree-construction");
+    report.check("tree-construction");
+}
@@ -1,5 +1,14 @@
+pub use error::*;
 pub use parser::*;
+pub use serializer::*;
 pub use tokenizer::*;
 
+mod entities;
+mod error;
+mod foreign;
+#[cfg(test)]
+mod html5lib;
 mod parser;
+mod quirks;
+mod serializer;
 mod tokenizer;
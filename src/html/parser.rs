@@ -1,16 +1,19 @@
 use std::{
+    collections::VecDeque,
     pin::Pin,
     task::{Context, Poll},
 };
 
-use smol::{lock::BarrierWaitResult, prelude::AsyncRead};
+use smol::prelude::AsyncRead;
 
 use super::{
+    foreign, quirks,
     tokenizer::{Interner, Token, Tokenizer, TokenizerError},
-    State,
+    ParseError, State,
 };
 use crate::{
-    dom::{Dom, EMPTY_RANGE_INDEX, ROOT_NODE_ID},
+    atoms,
+    dom::{Dom, Namespace, QuirksMode, EMPTY_RANGE_INDEX, ROOT_NODE_ID},
     io::{AsyncStrReader, Location},
 };
 
@@ -20,13 +23,144 @@ enum InsertionMode {
     BeforeHtml,
     BeforeHead,
     InHead,
+    InHeadNoscript,
     AfterHead,
     InBody,
+    InTable,
+    InTableText,
+    InCaption,
+    InColumnGroup,
+    InTableBody,
+    InRow,
+    InCell,
+    InSelect,
+    InSelectInTable,
+    InTemplate,
     AfterBody,
+    InFrameset,
+    AfterFrameset,
     AfterAfterBody,
+    AfterAfterFrameset,
     Text,
 }
 
+const SCOPE_MARKERS: &[usize] = &[
+    atoms::APPLET,
+    atoms::CAPTION,
+    atoms::HTML,
+    atoms::TABLE,
+    atoms::TD,
+    atoms::TH,
+    atoms::MARQUEE,
+    atoms::OBJECT,
+    atoms::TEMPLATE,
+];
+
+const SPECIAL: &[usize] = &[
+    atoms::ADDRESS,
+    atoms::APPLET,
+    atoms::AREA,
+    atoms::ARTICLE,
+    atoms::ASIDE,
+    atoms::BASE,
+    atoms::BASEFONT,
+    atoms::BGSOUND,
+    atoms::BLOCKQUOTE,
+    atoms::BODY,
+    atoms::BR,
+    atoms::BUTTON,
+    atoms::CAPTION,
+    atoms::CENTER,
+    atoms::COL,
+    atoms::COLGROUP,
+    atoms::DD,
+    atoms::DETAILS,
+    atoms::DIR,
+    atoms::DIV,
+    atoms::DL,
+    atoms::DT,
+    atoms::EMBED,
+    atoms::FIELDSET,
+    atoms::FIGCAPTION,
+    atoms::FIGURE,
+    atoms::FOOTER,
+    atoms::FORM,
+    atoms::FRAME,
+    atoms::FRAMESET,
+    atoms::H1,
+    atoms::H2,
+    atoms::H3,
+    atoms::H4,
+    atoms::H5,
+    atoms::H6,
+    atoms::HEAD,
+    atoms::HEADER,
+    atoms::HGROUP,
+    atoms::HR,
+    atoms::HTML,
+    atoms::IFRAME,
+    atoms::IMG,
+    atoms::INPUT,
+    atoms::KEYGEN,
+    atoms::LI,
+    atoms::LINK,
+    atoms::LISTING,
+    atoms::MAIN,
+    atoms::MARQUEE,
+    atoms::MENU,
+    atoms::META,
+    atoms::NAV,
+    atoms::NOEMBED,
+    atoms::NOFRAMES,
+    atoms::NOSCRIPT,
+    atoms::OBJECT,
+    atoms::OL,
+    atoms::P,
+    atoms::PARAM,
+    atoms::PLAINTEXT,
+    atoms::PRE,
+    atoms::SCRIPT,
+    atoms::SEARCH,
+    atoms::SECTION,
+    atoms::SELECT,
+    atoms::SOURCE,
+    atoms::STYLE,
+    atoms::SUMMARY,
+    atoms::TABLE,
+    atoms::TBODY,
+    atoms::TD,
+    atoms::TEMPLATE,
+    atoms::TEXTAREA,
+    atoms::TFOOT,
+    atoms::TH,
+    atoms::THEAD,
+    atoms::TITLE,
+    atoms::TR,
+    atoms::TRACK,
+    atoms::UL,
+    atoms::WBR,
+    atoms::XMP,
+];
+
+#[derive(Copy, Clone)]
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+    Table,
+    Select,
+}
+
+#[derive(Copy, Clone)]
+enum FormattingElement {
+    Marker,
+    Element {
+        id: usize,
+        name: usize,
+        attrs: usize,
+    },
+}
+
 #[must_use]
 #[pin_project::pin_project]
 pub struct Parser<R> {
@@ -36,11 +170,18 @@ pub struct Parser<R> {
     original_insertion_mode: InsertionMode,
     template_insertion_modes: Vec<InsertionMode>,
     stack: Vec<usize>,
+    active_formatting_elements: Vec<FormattingElement>,
     head: Option<usize>,
-    text_buf: String,
+    form: Option<usize>,
+    pending_table_chars: String,
     tok_buf: Vec<(Location, Token)>,
+    loc: Location,
+    errors: VecDeque<(Location, ParseError)>,
     frameset_ok: bool,
+    foster_parenting: bool,
     skip_next_linefeed: bool,
+    context: Option<usize>,         // context element when parsing a fragment
+    fragment_parent: Option<usize>, // where the fragment goes once parsed
 }
 
 impl Interner for Dom {
@@ -56,10 +197,16 @@ impl Interner for Dom {
 pub enum ParseEvent {
     Done,
     Fatal(Location, TokenizerError),
+    Error(Location, ParseError),
     Title(usize),
     Link, // TODO: need to fire off when a link tag is ready to fetch
     Style(usize),
     IFrame, // TODO
+    Frame(usize),
+}
+
+fn is_atom_in(name: usize, atoms: &[usize]) -> bool {
+    atoms.contains(&name)
 }
 
 impl<R> Parser<R> {
@@ -70,136 +217,951 @@ impl<R> Parser<R> {
             original_insertion_mode: InsertionMode::Initial,
             template_insertion_modes: Vec::new(),
             stack: Vec::new(),
+            active_formatting_elements: Vec::new(),
             head: None,
-            text_buf: String::new(),
+            form: None,
+            pending_table_chars: String::new(),
             tok_buf: Vec::new(),
+            loc: Location { line: 1, column: 1 },
+            errors: VecDeque::new(),
             frameset_ok: true,
+            foster_parenting: false,
             skip_next_linefeed: false,
+            context: None,
+            fragment_parent: None,
         }
     }
 
-    fn is_str_in(&self, dom: &Dom, index: usize, strings: &[&str]) -> bool {
-        for &string in strings {
-            let string = dom.find_str(string);
-            if matches!(string, Some(i) if i == index) {
-                return true;
+    /// A parser for a fragment of HTML, as if it were the contents of an
+    /// element named `name`, the parsed nodes are appended to `parent`
+    pub fn new_fragment(
+        reader: AsyncStrReader<R>,
+        dom: &mut Dom,
+        namespace: Namespace,
+        name: &str,
+        parent: usize,
+    ) -> Self {
+        let mut parser = Self::new(reader);
+        let state = match name {
+            _ if namespace != Namespace::Html => State::Data,
+            "title" | "textarea" => State::RcData,
+            "style" | "xmp" | "iframe" | "noembed" | "noframes" => State::RawText,
+            "script" => State::ScriptData,
+            "plaintext" => State::PlainText,
+            _ => State::Data,
+        };
+        parser.tokenizer.set_state(state);
+
+        // the context element only exists to drive the tree construction rules
+        let name = dom.insert_str(name);
+        let context = dom.create_element(namespace, name, EMPTY_RANGE_INDEX);
+        let root = dom.create_element(Namespace::Html, atoms::HTML, EMPTY_RANGE_INDEX);
+        parser.stack.push(root);
+        parser.context = Some(context);
+        parser.fragment_parent = Some(parent);
+        if parser.is_html_element_in(dom, context, &[atoms::TEMPLATE]) {
+            parser
+                .template_insertion_modes
+                .push(InsertionMode::InTemplate);
+        }
+        parser.reset_insertion_mode(dom);
+
+        // the form pointer is the nearest form the fragment will end up in
+        let mut ancestor = Some(parent);
+        while let Some(id) = ancestor {
+            if parser.is_html_element_in(dom, id, &[atoms::FORM]) {
+                parser.form = Some(id);
+                break;
             }
+            ancestor = dom.get_parent_node_id(id);
         }
-        false
+        parser
+    }
+
+    fn error(&mut self, err: ParseError) {
+        self.errors.push_back((self.loc, err));
     }
 
     fn append_text(&mut self, dom: &mut Dom, c: char) {
-        dom.append_char(c);
-        let top = *self.stack.last().unwrap();
-        let element = dom.get_element_node(top).unwrap();
-        // if the last child is a text node, then update its text
-        if let Some(child) = element.child_indices().last() {
-            if let Some(child) = dom.get_node_id_by_index(child) {
-                if let Some(mut text) = dom.get_text_node_mut(child) {
-                    self.text_buf.push(c);
-                    text.set_text(&self.text_buf);
-                    return;
-                }
+        let mut buf = [0; 4];
+        let c = c.encode_utf8(&mut buf);
+        let (parent, before) = self.insertion_place(dom);
+        let element = dom.get_element_node(parent).unwrap();
+        // the sibling right before the insertion place
+        let kids = element.child_indices();
+        let sibling = match before {
+            Some(before) => kids
+                .take_while(|kid| dom.get_node_id_by_index(*kid) != Some(before))
+                .last(),
+            None => kids.last(),
+        };
+        // if that sibling is a text node, then update its text
+        if let Some(child) = sibling.and_then(|kid| dom.get_node_id_by_index(kid)) {
+            if let Some(mut text) = dom.get_text_node_mut(child) {
+                text.push_str(c);
+                return;
             }
         }
-        // otherwise create a new child and set the text
-        self.text_buf.clear();
-        self.text_buf.push(c);
-        let mut top = dom.get_element_node_mut(top).unwrap();
-        top.append_child_text(&self.text_buf);
-    }
-
-    fn stack_contains(&self, dom: &Dom, names: &[&str]) -> bool {
-        for &name in names {
-            if let Some(name) = dom.find_str(name) {
-                if self
-                    .stack
-                    .iter()
-                    .find(|id| dom.get_element_node(**id).unwrap().name() == name)
-                    .is_some()
-                {
-                    return true;
-                }
-            }
+        // otherwise create a new child with the text
+        let mut parent = dom.get_element_node_mut(parent).unwrap();
+        match before {
+            Some(before) => parent.insert_child_text_before(c, before),
+            None => parent.append_child_text(c),
+        };
+    }
+
+    fn insert_comment(&mut self, dom: &mut Dom, data: usize) {
+        let (parent, before) = self.insertion_place(dom);
+        let mut parent = dom.get_element_node_mut(parent).unwrap();
+        match before {
+            Some(before) => parent.insert_child_comment_before(data, before),
+            None => parent.append_child_comment(data),
+        };
+    }
+
+    fn is_element_in(&self, dom: &Dom, id: usize, namespace: Namespace, names: &[usize]) -> bool {
+        let element = dom.get_element_node(id).unwrap();
+        element.namespace() == namespace && is_atom_in(element.name(), names)
+    }
+
+    fn is_html_element_in(&self, dom: &Dom, id: usize, names: &[usize]) -> bool {
+        self.is_element_in(dom, id, Namespace::Html, names)
+    }
+
+    fn is_html_element_named(&self, dom: &Dom, id: usize, name: usize) -> bool {
+        let element = dom.get_element_node(id).unwrap();
+        element.namespace() == Namespace::Html && element.name() == name
+    }
+
+    /// MathML and SVG elements that are both special and scope markers
+    fn is_foreign_scope_marker(&self, dom: &Dom, id: usize) -> bool {
+        self.is_element_in(
+            dom,
+            id,
+            Namespace::MathMl,
+            &[
+                atoms::MI,
+                atoms::MO,
+                atoms::MN,
+                atoms::MS,
+                atoms::MTEXT,
+                atoms::ANNOTATION_XML,
+            ],
+        ) || self.is_element_in(
+            dom,
+            id,
+            Namespace::Svg,
+            &[atoms::FOREIGN_OBJECT, atoms::DESC, atoms::TITLE],
+        )
+    }
+
+    fn is_scope_marker(&self, dom: &Dom, id: usize) -> bool {
+        self.is_html_element_in(dom, id, SCOPE_MARKERS) || self.is_foreign_scope_marker(dom, id)
+    }
+
+    fn is_mathml_text_integration_point(&self, dom: &Dom, id: usize) -> bool {
+        self.is_element_in(
+            dom,
+            id,
+            Namespace::MathMl,
+            &[atoms::MI, atoms::MO, atoms::MN, atoms::MS, atoms::MTEXT],
+        )
+    }
+
+    fn is_html_integration_point(&self, dom: &Dom, id: usize) -> bool {
+        if self.is_element_in(dom, id, Namespace::MathMl, &[atoms::ANNOTATION_XML]) {
+            let element = dom.get_element_node(id).unwrap();
+            return element
+                .attrs()
+                .iter()
+                .find(|[name, _]| *name == atoms::ENCODING)
+                .and_then(|[_, value]| dom.get_str(*value))
+                .is_some_and(|value| {
+                    value.eq_ignore_ascii_case("text/html")
+                        || value.eq_ignore_ascii_case("application/xhtml+xml")
+                });
         }
-        false
+        self.is_element_in(
+            dom,
+            id,
+            Namespace::Svg,
+            &[atoms::FOREIGN_OBJECT, atoms::DESC, atoms::TITLE],
+        )
     }
 
-    fn is_in_scope(&self, dom: &mut Dom, name: &str) -> bool {
-        let name = dom.insert_str(name);
-        self.is_index_in_scope(dom, name)
+    fn stack_contains(&self, dom: &Dom, names: &[usize]) -> bool {
+        self.stack
+            .iter()
+            .any(|&id| self.is_html_element_in(dom, id, names))
+    }
+
+    /// Whether the element limits how far down the stack `scope` reaches
+    fn is_scope_boundary(&self, dom: &Dom, id: usize, scope: Scope) -> bool {
+        match scope {
+            Scope::Default => self.is_scope_marker(dom, id),
+            Scope::ListItem => {
+                self.is_scope_marker(dom, id)
+                    || self.is_html_element_in(dom, id, &[atoms::OL, atoms::UL])
+            }
+            Scope::Button => {
+                self.is_scope_marker(dom, id) || self.is_html_element_in(dom, id, &[atoms::BUTTON])
+            }
+            Scope::Table => {
+                self.is_html_element_in(dom, id, &[atoms::HTML, atoms::TABLE, atoms::TEMPLATE])
+            }
+            Scope::Select => !self.is_html_element_in(dom, id, &[atoms::OPTGROUP, atoms::OPTION]),
+        }
     }
 
-    fn is_index_in_scope(&self, dom: &mut Dom, name: usize) -> bool {
+    fn is_in_scope_where(&self, dom: &Dom, scope: Scope, target: impl Fn(usize) -> bool) -> bool {
         for &element in self.stack.iter().rev() {
-            let element = dom.get_element_node(element).unwrap();
-            if element.name() == name {
+            if target(element) {
                 return true;
             }
-            if self.is_str_in(
-                dom,
-                element.name(),
-                &["html", "table", "td", "th", "marquee"],
-            ) {
+            if self.is_scope_boundary(dom, element, scope) {
                 return false;
             }
         }
         unreachable!()
     }
 
-    fn is_in_button_scope(&self, dom: &mut Dom, name: &str) -> bool {
-        let name = dom.insert_str(name);
-        for &element in self.stack.iter().rev() {
-            let element = dom.get_element_node(element).unwrap();
-            if element.name() == name {
-                return true;
+    fn is_in_scope(&self, dom: &Dom, name: usize, scope: Scope) -> bool {
+        self.is_in_scope_where(dom, scope, |id| self.is_html_element_named(dom, id, name))
+    }
+
+    fn is_one_of_in_scope(&self, dom: &Dom, names: &[usize], scope: Scope) -> bool {
+        self.is_in_scope_where(dom, scope, |id| self.is_html_element_in(dom, id, names))
+    }
+
+    fn is_element_in_scope(&self, dom: &Dom, id: usize) -> bool {
+        self.is_in_scope_where(dom, Scope::Default, |element| element == id)
+    }
+
+    fn close_until(&mut self, dom: &Dom, name: usize) {
+        while let Some(top) = self.stack.pop() {
+            if self.is_html_element_named(dom, top, name) {
+                break;
+            }
+        }
+    }
+
+    fn close_p(&mut self, dom: &Dom) {
+        self.generate_implied_end_tags(dom, Some(atoms::P));
+        if !self.is_current_node_in(dom, &[atoms::P]) {
+            self.error(ParseError::UnexpectedToken);
+        }
+        while let Some(top) = self.stack.pop() {
+            if self.is_html_element_in(dom, top, &[atoms::P]) {
+                break;
+            }
+        }
+    }
+
+    fn adjusted_current_node(&self) -> usize {
+        match self.context {
+            Some(context) if self.stack.len() == 1 => context,
+            _ => *self.stack.last().unwrap(),
+        }
+    }
+
+    fn adjusted_current_node_namespace(&self, dom: &Dom) -> Namespace {
+        let node = self.adjusted_current_node();
+        dom.get_element_node(node).unwrap().namespace()
+    }
+
+    fn is_adjusted_current_node_foreign(&self, dom: &Dom) -> bool {
+        !self.stack.is_empty() && self.adjusted_current_node_namespace(dom) != Namespace::Html
+    }
+
+    fn is_current_node_in(&self, dom: &Dom, names: &[usize]) -> bool {
+        let top = *self.stack.last().unwrap();
+        self.is_html_element_in(dom, top, names)
+    }
+
+    fn generate_implied_end_tags(&mut self, dom: &Dom, except: Option<usize>) {
+        while let Some(&top) = self.stack.last() {
+            if except.is_some_and(|name| self.is_html_element_named(dom, top, name))
+                || !self.is_html_element_in(
+                    dom,
+                    top,
+                    &[
+                        atoms::DD,
+                        atoms::DT,
+                        atoms::LI,
+                        atoms::OPTGROUP,
+                        atoms::OPTION,
+                        atoms::P,
+                        atoms::RB,
+                        atoms::RP,
+                        atoms::RT,
+                        atoms::RTC,
+                    ],
+                )
+            {
+                break;
+            }
+            self.stack.pop();
+        }
+    }
+
+    fn generate_all_implied_end_tags_thoroughly(&mut self, dom: &Dom) {
+        while self.is_current_node_in(
+            dom,
+            &[
+                atoms::CAPTION,
+                atoms::COLGROUP,
+                atoms::DD,
+                atoms::DT,
+                atoms::LI,
+                atoms::OPTGROUP,
+                atoms::OPTION,
+                atoms::P,
+                atoms::RB,
+                atoms::RP,
+                atoms::RT,
+                atoms::RTC,
+                atoms::TBODY,
+                atoms::TD,
+                atoms::TFOOT,
+                atoms::TH,
+                atoms::THEAD,
+                atoms::TR,
+            ],
+        ) {
+            self.stack.pop();
+        }
+    }
+
+    /// Pops elements until one of `names` has been popped
+    fn close_until_one_of(&mut self, dom: &Dom, names: &[usize]) {
+        while let Some(top) = self.stack.pop() {
+            if self.is_html_element_in(dom, top, names) {
+                break;
             }
-            if self.is_str_in(
+        }
+    }
+
+    /// Clears the stack back to a table, table body or table row context
+    fn clear_stack_back_to(&mut self, dom: &Dom, names: &[usize]) {
+        while !self.is_current_node_in(dom, names) {
+            self.stack.pop();
+        }
+    }
+
+    fn close_cell(&mut self, dom: &Dom) {
+        self.generate_implied_end_tags(dom, None);
+        if !self.is_current_node_in(dom, &[atoms::TD, atoms::TH]) {
+            self.error(ParseError::UnexpectedToken);
+        }
+        self.close_until_one_of(dom, &[atoms::TD, atoms::TH]);
+        self.clear_active_formatting_elements_to_last_marker();
+        self.insertion_mode = InsertionMode::InRow;
+    }
+
+    fn reset_insertion_mode(&mut self, dom: &Dom) {
+        for (i, &element) in self.stack.iter().enumerate().rev() {
+            let last = i == 0;
+            let element = match self.context {
+                Some(context) if last => context,
+                _ => element,
+            };
+            let mode = if self.is_html_element_in(dom, element, &[atoms::SELECT]) {
+                // a select inside a table, unless there's a template in between
+                let table = self.stack[..i].iter().rev().find_map(|&ancestor| {
+                    if self.is_html_element_in(dom, ancestor, &[atoms::TEMPLATE]) {
+                        Some(false)
+                    } else if self.is_html_element_in(dom, ancestor, &[atoms::TABLE]) {
+                        Some(true)
+                    } else {
+                        None
+                    }
+                });
+                if table == Some(true) {
+                    InsertionMode::InSelectInTable
+                } else {
+                    InsertionMode::InSelect
+                }
+            } else if !last && self.is_html_element_in(dom, element, &[atoms::TD, atoms::TH]) {
+                InsertionMode::InCell
+            } else if self.is_html_element_in(dom, element, &[atoms::TR]) {
+                InsertionMode::InRow
+            } else if self.is_html_element_in(
                 dom,
-                element.name(),
-                &["html", "table", "td", "th", "marquee", "button"],
+                element,
+                &[atoms::TBODY, atoms::THEAD, atoms::TFOOT],
             ) {
-                return false;
+                InsertionMode::InTableBody
+            } else if self.is_html_element_in(dom, element, &[atoms::CAPTION]) {
+                InsertionMode::InCaption
+            } else if self.is_html_element_in(dom, element, &[atoms::COLGROUP]) {
+                InsertionMode::InColumnGroup
+            } else if self.is_html_element_in(dom, element, &[atoms::TABLE]) {
+                InsertionMode::InTable
+            } else if self.is_html_element_in(dom, element, &[atoms::TEMPLATE]) {
+                *self.template_insertion_modes.last().unwrap()
+            } else if !last && self.is_html_element_in(dom, element, &[atoms::HEAD]) {
+                InsertionMode::InHead
+            } else if self.is_html_element_in(dom, element, &[atoms::BODY]) {
+                InsertionMode::InBody
+            } else if self.is_html_element_in(dom, element, &[atoms::FRAMESET]) {
+                InsertionMode::InFrameset
+            } else if self.is_html_element_in(dom, element, &[atoms::HTML]) {
+                if self.head.is_none() {
+                    InsertionMode::BeforeHead
+                } else {
+                    InsertionMode::AfterHead
+                }
+            } else if last {
+                InsertionMode::InBody
+            } else {
+                continue;
+            };
+            self.insertion_mode = mode;
+            return;
+        }
+    }
+
+    fn get_attr<'a>(&self, dom: &'a Dom, attrs: usize, name: usize) -> Option<&'a str> {
+        dom.get_attrs(attrs)
+            .iter()
+            .find(|[n, _]| *n == name)
+            .and_then(|[_, value]| dom.get_str(*value))
+    }
+
+    fn is_hidden_input(&self, dom: &Dom, attrs: usize) -> bool {
+        self.get_attr(dom, attrs, atoms::TYPE)
+            .is_some_and(|value| value.eq_ignore_ascii_case("hidden"))
+    }
+
+    /// The appropriate place for inserting a node, as a parent and
+    /// the child to insert before (`None` to append)
+    fn insertion_place(&self, dom: &Dom) -> (usize, Option<usize>) {
+        self.insertion_place_in(dom, *self.stack.last().unwrap())
+    }
+
+    /// The appropriate place for inserting a node with an override target
+    fn insertion_place_in(&self, dom: &Dom, target: usize) -> (usize, Option<usize>) {
+        let (parent, before) = if self.foster_parenting
+            && self.is_html_element_in(
+                dom,
+                target,
+                &[
+                    atoms::TABLE,
+                    atoms::TBODY,
+                    atoms::TFOOT,
+                    atoms::THEAD,
+                    atoms::TR,
+                ],
+            ) {
+            // misnested content goes right before the table it ended up in
+            let last = |names| {
+                self.stack
+                    .iter()
+                    .rposition(|&element| self.is_html_element_in(dom, element, names))
+            };
+            match (last(&[atoms::TEMPLATE]), last(&[atoms::TABLE])) {
+                (Some(template), table) if table.is_none_or(|table| template > table) => {
+                    (self.stack[template], None)
+                }
+                (_, Some(table)) => match dom.get_parent_node_id(self.stack[table]) {
+                    Some(parent) => (parent, Some(self.stack[table])),
+                    None => (self.stack[table - 1], None),
+                },
+                (_, None) => (self.stack[0], None),
             }
+        } else {
+            (target, None)
+        };
+        // templates keep their children in a separate fragment
+        match dom.get_template_contents(parent) {
+            Some(contents) => (contents, before),
+            None => (parent, before),
         }
-        unreachable!()
     }
 
-    fn close_implied_end_elements(&mut self, dom: &Dom, names: &[&str]) {
-        for &name in names {
-            if let Some(name) = dom.find_str(name) {
-                let top = *self.stack.last().unwrap();
-                let top = dom.get_element_node(top).unwrap();
-                if top.name() == name {
-                    self.stack.pop();
+    fn insert_foreign_element(
+        &mut self,
+        dom: &mut Dom,
+        namespace: Namespace,
+        name: usize,
+        attrs: usize,
+    ) -> usize {
+        let (parent, before) = self.insertion_place(dom);
+        let mut parent = dom.get_element_node_mut(parent).unwrap();
+        let element = match before {
+            Some(before) => parent.insert_child_element_before(namespace, name, attrs, before),
+            None => parent.append_child_element(namespace, name, attrs),
+        };
+        self.stack.push(element);
+        element
+    }
+
+    fn insert_element(&mut self, dom: &mut Dom, name: usize, attrs: usize) -> usize {
+        let element = self.insert_foreign_element(dom, Namespace::Html, name, attrs);
+        // form-associated elements belong to the form they're parsed in,
+        // unless a form attribute points somewhere else
+        if let Some(form) = self.form {
+            let listed = [
+                atoms::BUTTON,
+                atoms::FIELDSET,
+                atoms::INPUT,
+                atoms::OBJECT,
+                atoms::OUTPUT,
+                atoms::SELECT,
+                atoms::TEXTAREA,
+            ];
+            if (is_atom_in(name, &listed) && self.get_attr(dom, attrs, atoms::FORM).is_none()
+                || name == atoms::IMG)
+                && !self.stack_contains(dom, &[atoms::TEMPLATE])
+            {
+                dom.set_form_owner(element, form);
+            }
+        }
+        element
+    }
+
+    /// Inserts an element for a tag the parser made up
+    fn insert_synthetic_element(&mut self, dom: &mut Dom, name: usize) -> usize {
+        self.insert_element(dom, name, EMPTY_RANGE_INDEX)
+    }
+
+    fn is_special(&self, dom: &Dom, id: usize) -> bool {
+        self.is_html_element_in(dom, id, SPECIAL) || self.is_foreign_scope_marker(dom, id)
+    }
+
+    fn formatting_element_position(&self, id: usize) -> Option<usize> {
+        self.active_formatting_elements
+            .iter()
+            .position(|entry| matches!(entry, FormattingElement::Element { id: i, .. } if *i == id))
+    }
+
+    /// The last element named `name` in the list after the last marker
+    fn last_formatting_element(&self, name: usize) -> Option<usize> {
+        for (i, entry) in self.active_formatting_elements.iter().enumerate().rev() {
+            match *entry {
+                FormattingElement::Marker => break,
+                FormattingElement::Element { name: n, .. } if n == name => return Some(i),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn push_active_formatting_element(&mut self, dom: &Dom, id: usize, name: usize, attrs: usize) {
+        // the Noah's Ark clause, keep at most three identical entries after the last marker
+        let mut count = 0;
+        let mut earliest = None;
+        for (i, entry) in self.active_formatting_elements.iter().enumerate().rev() {
+            match *entry {
+                FormattingElement::Marker => break,
+                FormattingElement::Element {
+                    name: n, attrs: a, ..
+                } if n == name => {
+                    let (a, b) = (dom.get_attrs(a), dom.get_attrs(attrs));
+                    if a.len() == b.len() && a.iter().all(|attr| b.contains(attr)) {
+                        count += 1;
+                        earliest = Some(i);
+                    }
                 }
+                _ => {}
             }
         }
+        if count >= 3 {
+            self.active_formatting_elements.remove(earliest.unwrap());
+        }
+        self.active_formatting_elements
+            .push(FormattingElement::Element { id, name, attrs });
     }
 
-    fn close_until(&mut self, dom: &Dom, name: usize) {
-        while let Some(top) = self.stack.pop() {
-            let top = dom.get_element_node(top).unwrap();
-            if top.name() == name {
+    fn reconstruct_active_formatting_elements(&mut self, dom: &mut Dom) {
+        // find the first entry after the last marker or open element
+        let mut start = self.active_formatting_elements.len();
+        while start > 0 {
+            match self.active_formatting_elements[start - 1] {
+                FormattingElement::Marker => break,
+                FormattingElement::Element { id, .. } if self.stack.contains(&id) => break,
+                _ => start -= 1,
+            }
+        }
+        // and reopen everything from there
+        for i in start..self.active_formatting_elements.len() {
+            if let FormattingElement::Element { name, attrs, .. } =
+                self.active_formatting_elements[i]
+            {
+                let id = self.insert_element(dom, name, attrs);
+                self.active_formatting_elements[i] = FormattingElement::Element { id, name, attrs };
+            }
+        }
+    }
+
+    fn clear_active_formatting_elements_to_last_marker(&mut self) {
+        while let Some(entry) = self.active_formatting_elements.pop() {
+            if matches!(entry, FormattingElement::Marker) {
                 break;
             }
         }
     }
 
-    fn close_p(&mut self, dom: &Dom) {
-        self.close_implied_end_elements(dom, &["dd", "dt", "li", "optgroup", "option"]);
-        while let Some(top) = self.stack.pop() {
-            let top = dom.get_element_node(top).unwrap();
-            if self.is_str_in(dom, top.name(), &["p"]) {
+    /// The adoption agency algorithm, returns false when the token
+    /// should be handled like any other end tag instead
+    fn adoption_agency(&mut self, dom: &mut Dom, subject: usize) -> bool {
+        let top = *self.stack.last().unwrap();
+        if self.is_html_element_named(dom, top, subject)
+            && self.formatting_element_position(top).is_none()
+        {
+            self.stack.pop();
+            return true;
+        }
+        for _ in 0..8 {
+            let Some(formatting_pos) = self.last_formatting_element(subject) else {
+                return false;
+            };
+            let FormattingElement::Element {
+                id: formatting,
+                name,
+                attrs,
+            } = self.active_formatting_elements[formatting_pos]
+            else {
+                unreachable!()
+            };
+            let Some(stack_pos) = self.stack.iter().position(|&id| id == formatting) else {
+                self.error(ParseError::UnexpectedToken);
+                self.active_formatting_elements.remove(formatting_pos);
+                return true;
+            };
+            if !self.is_element_in_scope(dom, formatting) {
+                self.error(ParseError::UnexpectedToken);
+                return true;
+            }
+            if formatting != *self.stack.last().unwrap() {
+                self.error(ParseError::UnexpectedToken);
+            }
+
+            // the topmost special element below the formatting element
+            let furthest_block = self.stack[stack_pos + 1..]
+                .iter()
+                .find(|&&id| self.is_special(dom, id))
+                .copied();
+            let Some(furthest_block) = furthest_block else {
+                self.stack.truncate(stack_pos);
+                self.active_formatting_elements.remove(formatting_pos);
+                return true;
+            };
+            let common_ancestor = self.stack[stack_pos - 1];
+
+            // the bookmark is where the new formatting element goes in the list,
+            // counted while the old formatting element is still in it
+            let mut bookmark = formatting_pos + 1;
+            let mut node_pos = self
+                .stack
+                .iter()
+                .position(|&id| id == furthest_block)
+                .unwrap();
+            let mut last_node = furthest_block;
+            let mut inner_loop_counter = 0;
+            loop {
+                inner_loop_counter += 1;
+                node_pos -= 1;
+                let node = self.stack[node_pos];
+                if node == formatting {
+                    break;
+                }
+                let mut entry = self.formatting_element_position(node);
+                if let Some(i) = entry.filter(|_| inner_loop_counter > 3) {
+                    self.active_formatting_elements.remove(i);
+                    if i < bookmark {
+                        bookmark -= 1;
+                    }
+                    entry = None;
+                }
+                let Some(entry) = entry else {
+                    self.stack.remove(node_pos);
+                    continue;
+                };
+                let FormattingElement::Element { name, attrs, .. } =
+                    self.active_formatting_elements[entry]
+                else {
+                    unreachable!()
+                };
+                let element = dom.create_element(Namespace::Html, name, attrs);
+                self.active_formatting_elements[entry] = FormattingElement::Element {
+                    id: element,
+                    name,
+                    attrs,
+                };
+                self.stack[node_pos] = element;
+                if last_node == furthest_block {
+                    bookmark = entry + 1;
+                }
+                dom.move_node(last_node, element, None);
+                last_node = element;
+            }
+
+            let (parent, before) = self.insertion_place_in(dom, common_ancestor);
+            dom.move_node(last_node, parent, before);
+
+            let element = dom.create_element(Namespace::Html, name, attrs);
+            dom.move_children(furthest_block, element);
+            dom.move_node(element, furthest_block, None);
+
+            self.active_formatting_elements.insert(
+                bookmark,
+                FormattingElement::Element {
+                    id: element,
+                    name,
+                    attrs,
+                },
+            );
+            let i = self.formatting_element_position(formatting).unwrap();
+            self.active_formatting_elements.remove(i);
+
+            self.stack.retain(|&id| id != formatting);
+            let i = self
+                .stack
+                .iter()
+                .position(|&id| id == furthest_block)
+                .unwrap();
+            self.stack.insert(i + 1, element);
+        }
+        true
+    }
+
+    fn any_other_end_tag(&mut self, dom: &Dom, name: usize) {
+        for i in (0..self.stack.len()).rev() {
+            let node = self.stack[i];
+            if self.is_html_element_named(dom, node, name) {
+                self.generate_implied_end_tags(dom, Some(name));
+                if node != *self.stack.last().unwrap() {
+                    self.error(ParseError::UnexpectedToken);
+                }
+                self.stack.truncate(i);
+                return;
+            }
+            if self.is_special(dom, node) {
+                self.error(ParseError::UnexpectedToken);
+                return;
+            }
+        }
+    }
+
+    /// Whether the token is processed using the rules for foreign content
+    /// rather than the rules of the current insertion mode
+    fn is_foreign_token(&self, dom: &Dom, tok: Option<Token>) -> bool {
+        if !self.is_adjusted_current_node_foreign(dom) {
+            return false;
+        }
+        let node = self.adjusted_current_node();
+        match tok {
+            Some(Token::Char(_)) => {
+                !self.is_mathml_text_integration_point(dom, node)
+                    && !self.is_html_integration_point(dom, node)
+            }
+            Some(Token::StartTag { name, .. }) => {
+                let text_integration_point = self.is_mathml_text_integration_point(dom, node)
+                    && !is_atom_in(name, &[atoms::MGLYPH, atoms::MALIGNMARK]);
+                let svg_in_annotation = name == atoms::SVG
+                    && self.is_element_in(dom, node, Namespace::MathMl, &[atoms::ANNOTATION_XML]);
+                !text_integration_point
+                    && !svg_in_annotation
+                    && !self.is_html_integration_point(dom, node)
+            }
+            None => false,
+            _ => true,
+        }
+    }
+
+    /// Maps lowercased attribute names back to their proper case
+    fn adjust_attrs(
+        &self,
+        dom: &mut Dom,
+        attrs: usize,
+        adjust: fn(&str) -> Option<&'static str>,
+    ) -> usize {
+        let mut adjusted = dom.get_attrs(attrs).to_vec();
+        let mut changed = false;
+        for attr in &mut adjusted {
+            if let Some(name) = dom.get_str(attr[0]).and_then(adjust) {
+                attr[0] = dom.insert_str(name);
+                changed = true;
+            }
+        }
+        if changed {
+            dom.insert_attrs(&adjusted)
+        } else {
+            attrs
+        }
+    }
+
+    /// Pops foreign elements until the current node is an HTML element
+    /// or an integration point, for HTML tags that break out of foreign content
+    fn break_out_of_foreign_content(&mut self, dom: &Dom) {
+        self.error(ParseError::UnexpectedToken);
+        while let Some(&top) = self.stack.last() {
+            let element = dom.get_element_node(top).unwrap();
+            if element.namespace() == Namespace::Html
+                || self.is_mathml_text_integration_point(dom, top)
+                || self.is_html_integration_point(dom, top)
+            {
                 break;
             }
+            self.stack.pop();
+        }
+    }
+
+    /// The rules for parsing tokens in foreign content, returns false when
+    /// the token should be reprocessed in the current insertion mode
+    fn process_foreign_content(&mut self, dom: &mut Dom, tok: Option<Token>) -> bool {
+        // in the fragment case these are inserted like any other start tag
+        let fragment = self.context.is_some();
+        match tok {
+            Some(Token::Char('\0')) => {
+                self.error(ParseError::UnexpectedToken);
+                self.append_text(dom, '\u{FFFD}');
+            }
+            Some(Token::Char(c)) => {
+                self.append_text(dom, c);
+                if !"\t\n\x0C ".contains(c) {
+                    self.frameset_ok = false;
+                }
+            }
+            Some(Token::Comment(data)) => self.insert_comment(dom, data),
+            Some(Token::DocType { .. }) => self.error(ParseError::UnexpectedToken),
+            Some(Token::StartTag { name, attrs, .. })
+                if !fragment
+                    && (is_atom_in(
+                        name,
+                        &[
+                            atoms::B,
+                            atoms::BIG,
+                            atoms::BLOCKQUOTE,
+                            atoms::BODY,
+                            atoms::BR,
+                            atoms::CENTER,
+                            atoms::CODE,
+                            atoms::DD,
+                            atoms::DIV,
+                            atoms::DL,
+                            atoms::DT,
+                            atoms::EM,
+                            atoms::EMBED,
+                            atoms::H1,
+                            atoms::H2,
+                            atoms::H3,
+                            atoms::H4,
+                            atoms::H5,
+                            atoms::H6,
+                            atoms::HEAD,
+                            atoms::HR,
+                            atoms::I,
+                            atoms::IMG,
+                            atoms::LI,
+                            atoms::LISTING,
+                            atoms::MENU,
+                            atoms::META,
+                            atoms::NOBR,
+                            atoms::OL,
+                            atoms::P,
+                            atoms::PRE,
+                            atoms::RUBY,
+                            atoms::S,
+                            atoms::SMALL,
+                            atoms::SPAN,
+                            atoms::STRONG,
+                            atoms::STRIKE,
+                            atoms::SUB,
+                            atoms::SUP,
+                            atoms::TABLE,
+                            atoms::TT,
+                            atoms::U,
+                            atoms::UL,
+                            atoms::VAR,
+                        ],
+                    ) || name == atoms::FONT
+                        && [atoms::COLOR, atoms::FACE, atoms::SIZE]
+                            .iter()
+                            .any(|attr| self.get_attr(dom, attrs, *attr).is_some())) =>
+            {
+                self.break_out_of_foreign_content(dom);
+                return false;
+            }
+            Some(Token::EndTag { name }) if is_atom_in(name, &[atoms::BR, atoms::P]) => {
+                self.break_out_of_foreign_content(dom);
+                return false;
+            }
+            Some(Token::StartTag {
+                name,
+                attrs,
+                self_closing,
+            }) => {
+                let namespace = self.adjusted_current_node_namespace(dom);
+                let (name, attrs) = match namespace {
+                    Namespace::Svg => {
+                        let adjusted = dom.get_str(name).and_then(foreign::adjust_svg_tag_name);
+                        let name = adjusted.map_or(name, |adjusted| dom.insert_str(adjusted));
+                        let attrs = self.adjust_attrs(dom, attrs, foreign::adjust_svg_attribute);
+                        (name, attrs)
+                    }
+                    _ => {
+                        let attrs = self.adjust_attrs(dom, attrs, foreign::adjust_mathml_attribute);
+                        (name, attrs)
+                    }
+                };
+                self.insert_foreign_element(dom, namespace, name, attrs);
+                if self_closing {
+                    self.stack.pop();
+                }
+            }
+            Some(Token::EndTag { name }) => {
+                // foreign tag names keep their case, end tags are lowercased
+                let matches = |id: usize| {
+                    let element = dom.get_element_node(id).unwrap();
+                    let element = dom.get_str(element.name()).unwrap();
+                    element.eq_ignore_ascii_case(dom.get_str(name).unwrap())
+                };
+                let mut i = self.stack.len() - 1;
+                if !matches(self.stack[i]) {
+                    self.error(ParseError::UnexpectedToken);
+                }
+                while i > 0 {
+                    if matches(self.stack[i]) {
+                        self.stack.truncate(i);
+                        break;
+                    }
+                    i -= 1;
+                    let element = dom.get_element_node(self.stack[i]).unwrap();
+                    if element.namespace() == Namespace::Html {
+                        return false;
+                    }
+                }
+            }
+            None => unreachable!(),
         }
+        true
+    }
+
+    /// The generic raw text and RCDATA element parsing algorithms
+    fn parse_text_element(&mut self, dom: &mut Dom, name: usize, attrs: usize, state: State) {
+        self.insert_element(dom, name, attrs);
+        self.tokenizer.set_state(state);
+        self.original_insertion_mode = self.insertion_mode;
+        self.insertion_mode = InsertionMode::Text;
     }
 
-    fn stop_parsing(&mut self) -> Poll<ParseEvent> {
+    fn stop_parsing(&mut self, dom: &mut Dom) -> Poll<ParseEvent> {
+        self.finish_fragment(dom);
         self.stack.drain(..);
         Poll::Ready(ParseEvent::Done)
     }
+
+    /// hands what was parsed of a fragment to its parent, however parsing ended
+    fn finish_fragment(&mut self, dom: &mut Dom) {
+        if let Some(parent) = self.fragment_parent.take() {
+            // the made up root and context element aren't part of the tree
+            dom.move_children(self.stack[0], parent);
+            dom.remove_node(self.stack[0]);
+            dom.remove_node(self.context.unwrap());
+        }
+    }
 }
 
 impl<R: AsyncRead + Unpin> Parser<R> {
@@ -210,99 +1172,212 @@ impl<R: AsyncRead + Unpin> Parser<R> {
     ) -> Poll<ParseEvent> {
         let this = self.get_mut();
         loop {
+            if let Some((loc, err)) = this.errors.pop_front() {
+                return Poll::Ready(ParseEvent::Error(loc, err));
+            }
             let tok = {
-                if let Some((_, tok)) = this.tok_buf.pop() {
-                    Some(tok)
+                if let Some((loc, tok)) = this.tok_buf.pop() {
+                    Some((loc, tok))
                 } else {
-                    match Pin::new(&mut this.tokenizer).poll_next(cx, dom) {
+                    let foreign = this.is_adjusted_current_node_foreign(dom);
+                    this.tokenizer.set_cdata_allowed(foreign);
+                    let tok = match Pin::new(&mut this.tokenizer).poll_next(cx, dom) {
                         Poll::Ready(Some((loc, Ok(tok)))) => Some((loc, tok)),
                         Poll::Ready(Some((loc, Err(err)))) => {
+                            this.finish_fragment(dom);
                             return Poll::Ready(ParseEvent::Fatal(loc, err));
                         }
                         Poll::Ready(None) => None,
                         Poll::Pending => return Poll::Pending,
+                    };
+                    // errors come before the token that they were found in
+                    this.errors.extend(this.tokenizer.take_errors());
+                    if !this.errors.is_empty() {
+                        // the tokenizer keeps returning none at the end
+                        this.tok_buf.extend(tok);
+                        continue;
                     }
-                    .map(|(_, tok)| tok)
+                    tok
                 }
             };
-            if this.skip_next_linefeed && matches!(tok, Some(Token::Char('\n'))) {
+            let tok = tok.map(|(loc, tok)| {
+                this.loc = loc;
+                tok
+            });
+            if let Some(Token::StartTag {
+                name,
+                self_closing: true,
+                ..
+            }) = tok
+            {
+                // the flag is only acknowledged by void and foreign elements
+                if !this.is_adjusted_current_node_foreign(dom)
+                    && !is_atom_in(
+                        name,
+                        &[
+                            atoms::AREA,
+                            atoms::BASE,
+                            atoms::BASEFONT,
+                            atoms::BGSOUND,
+                            atoms::BR,
+                            atoms::COL,
+                            atoms::EMBED,
+                            atoms::FRAME,
+                            atoms::HR,
+                            atoms::IMAGE,
+                            atoms::IMG,
+                            atoms::INPUT,
+                            atoms::KEYGEN,
+                            atoms::LINK,
+                            atoms::MATH,
+                            atoms::META,
+                            atoms::PARAM,
+                            atoms::SOURCE,
+                            atoms::SVG,
+                            atoms::TRACK,
+                            atoms::WBR,
+                        ],
+                    )
+                {
+                    this.error(ParseError::NonVoidHtmlElementStartTagWithTrailingSolidus);
+                }
+            }
+            if this.skip_next_linefeed {
                 this.skip_next_linefeed = false;
+                if matches!(tok, Some(Token::Char('\n'))) {
+                    continue;
+                }
+            }
+            if this.is_foreign_token(dom, tok) && this.process_foreign_content(dom, tok) {
                 continue;
             }
+            // some modes process tokens using the rules of another mode
+            let mut using_rules_for = None;
+            let mut pop_head = false;
             loop {
-                match this.insertion_mode {
+                match using_rules_for.take().unwrap_or(this.insertion_mode) {
                     InsertionMode::Initial => match tok {
-                        Some(Token::Comment | Token::Char('\t' | '\n' | '\x0C' | ' ')) => break,
-                        Some(Token::DocType) => todo!("doctype"),
-                        _ => this.insertion_mode = InsertionMode::BeforeHtml,
+                        Some(Token::Char('\t' | '\n' | '\x0C' | ' ')) => break,
+                        Some(Token::Comment(data)) => {
+                            let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+                            root.append_child_comment(data);
+                            break;
+                        }
+                        Some(Token::DocType {
+                            name,
+                            public_id,
+                            system_id,
+                            force_quirks,
+                        }) => {
+                            let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+                            root.append_child_doctype(
+                                name.unwrap_or(EMPTY_RANGE_INDEX),
+                                public_id,
+                                system_id,
+                            );
+                            let name = name.and_then(|name| dom.get_str(name));
+                            let public_id = public_id.and_then(|id| dom.get_str(id));
+                            let system_id = system_id.and_then(|id| dom.get_str(id));
+                            if name != Some("html")
+                                || public_id.is_some()
+                                || system_id.is_some_and(|id| id != "about:legacy-compat")
+                            {
+                                this.error(ParseError::UnexpectedToken);
+                            }
+                            let mode = quirks::doctype_quirks_mode(
+                                name,
+                                public_id,
+                                system_id,
+                                force_quirks,
+                            );
+                            dom.set_quirks_mode(mode);
+                            this.insertion_mode = InsertionMode::BeforeHtml;
+                            break;
+                        }
+                        _ => {
+                            this.error(ParseError::UnexpectedToken);
+                            dom.set_quirks_mode(QuirksMode::Quirks);
+                            this.insertion_mode = InsertionMode::BeforeHtml;
+                        }
                     },
                     InsertionMode::BeforeHtml => match tok {
-                        Some(
-                            Token::DocType
-                            | Token::Comment
-                            | Token::Char('\t' | '\n' | '\x0C' | ' '),
-                        ) => break,
-                        Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(dom, name, &["html"]) =>
-                        {
+                        Some(Token::DocType { .. }) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::Comment(data)) => {
+                            let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+                            root.append_child_comment(data);
+                            break;
+                        }
+                        Some(Token::Char('\t' | '\n' | '\x0C' | ' ')) => break,
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::HTML => {
                             let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
-                            let html = root.append_child_element(name, attrs);
+                            let html = root.append_child_element(Namespace::Html, name, attrs);
                             this.stack.push(html);
                             this.insertion_mode = InsertionMode::BeforeHead;
                             break;
                         }
                         Some(Token::EndTag { name })
-                            if !this.is_str_in(dom, name, &["head", "body", "html", "br"]) =>
+                            if !is_atom_in(
+                                name,
+                                &[atoms::HEAD, atoms::BODY, atoms::HTML, atoms::BR],
+                            ) =>
                         {
                             break
                         }
                         _ => {
-                            let html = dom.insert_str("html"); // synthetic
+                            let html = atoms::HTML; // synthetic
                             let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
-                            let html = root.append_child_element(html, EMPTY_RANGE_INDEX);
+                            let html =
+                                root.append_child_element(Namespace::Html, html, EMPTY_RANGE_INDEX);
                             this.stack.push(html);
                             this.insertion_mode = InsertionMode::BeforeHead;
                         }
                     },
                     InsertionMode::BeforeHead => match tok {
-                        Some(
-                            Token::DocType
-                            | Token::Comment
-                            | Token::Char('\t' | '\n' | '\x0C' | ' '),
-                        ) => break,
-                        Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(dom, name, &["html"]) =>
-                        {
-                            if this.stack_contains(dom, &["template"]) {
+                        Some(Token::DocType { .. }) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::Comment(data)) => {
+                            this.insert_comment(dom, data);
+                            break;
+                        }
+                        Some(Token::Char('\t' | '\n' | '\x0C' | ' ')) => break,
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::HTML => {
+                            this.error(ParseError::UnexpectedToken);
+                            if this.stack_contains(dom, &[atoms::TEMPLATE]) {
                                 break;
                             }
-                            // error: merge attrs
-                            let top = *this.stack.last().unwrap();
-                            let mut top = dom.get_element_node_mut(top).unwrap();
-                            top.insert_missing_attrs(attrs);
+                            let html = this.stack[0];
+                            let mut html = dom.get_element_node_mut(html).unwrap();
+                            html.insert_missing_attrs(attrs);
                             break;
                         }
-                        Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(dom, name, &["head"]) =>
-                        {
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::HEAD => {
                             let top = *this.stack.last().unwrap();
                             let mut top = dom.get_element_node_mut(top).unwrap();
-                            let head = top.append_child_element(name, attrs);
+                            let head = top.append_child_element(Namespace::Html, name, attrs);
                             this.head = Some(head);
                             this.stack.push(head);
                             this.insertion_mode = InsertionMode::InHead;
                             break;
                         }
                         Some(Token::EndTag { name })
-                            if !this.is_str_in(dom, name, &["head", "body", "html", "br"]) =>
+                            if !is_atom_in(
+                                name,
+                                &[atoms::HEAD, atoms::BODY, atoms::HTML, atoms::BR],
+                            ) =>
                         {
                             break
                         }
                         _ => {
-                            let head = dom.insert_str("head"); // synthetic
+                            let head = atoms::HEAD; // synthetic
                             let top = *this.stack.last().unwrap();
                             let mut top = dom.get_element_node_mut(top).unwrap();
-                            let head = top.append_child_element(head, EMPTY_RANGE_INDEX);
+                            let head =
+                                top.append_child_element(Namespace::Html, head, EMPTY_RANGE_INDEX);
                             this.head = Some(head);
                             this.stack.push(head);
                             this.insertion_mode = InsertionMode::InHead;
@@ -313,81 +1388,145 @@ impl<R: AsyncRead + Unpin> Parser<R> {
                             this.append_text(dom, c);
                             break;
                         }
-                        Some(Token::DocType | Token::Comment) => break,
-                        Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(dom, name, &["html"]) =>
-                        {
-                            if this.stack_contains(dom, &["template"]) {
+                        Some(Token::DocType { .. }) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::Comment(data)) => {
+                            this.insert_comment(dom, data);
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::HTML => {
+                            this.error(ParseError::UnexpectedToken);
+                            if this.stack_contains(dom, &[atoms::TEMPLATE]) {
                                 break;
                             }
-                            // error: merge attrs
-                            let top = *this.stack.last().unwrap();
-                            let mut top = dom.get_element_node_mut(top).unwrap();
-                            top.insert_missing_attrs(attrs);
+                            let html = this.stack[0];
+                            let mut html = dom.get_element_node_mut(html).unwrap();
+                            html.insert_missing_attrs(attrs);
                             break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["base", "link", "meta"]) =>
-                        {
-                            todo!()
-                        }
                         Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(dom, name, &["title"]) =>
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::BASE,
+                                    atoms::BASEFONT,
+                                    atoms::BGSOUND,
+                                    atoms::LINK,
+                                    atoms::META,
+                                ],
+                            ) =>
                         {
-                            let top = *this.stack.last().unwrap();
-                            let mut top = dom.get_element_node_mut(top).unwrap();
-                            let element = top.append_child_element(name, attrs);
-                            this.stack.push(element);
-                            this.tokenizer.set_state(State::RcData);
-                            this.original_insertion_mode = this.insertion_mode;
-                            this.insertion_mode = InsertionMode::Text;
+                            this.insert_element(dom, name, attrs);
+                            this.stack.pop();
                             break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["noframes", "style"]) =>
-                        {
-                            todo!()
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::TITLE => {
+                            this.parse_text_element(dom, name, attrs, State::RcData);
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["noscript"]) =>
+                        Some(Token::StartTag { name, attrs, .. })
+                            if is_atom_in(name, &[atoms::NOFRAMES, atoms::STYLE]) =>
                         {
-                            todo!()
+                            this.parse_text_element(dom, name, attrs, State::RawText);
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["script"]) =>
-                        {
-                            todo!()
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::NOSCRIPT => {
+                            // we never run script, so the contents are parsed as markup
+                            this.insert_element(dom, name, attrs);
+                            this.insertion_mode = InsertionMode::InHeadNoscript;
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::SCRIPT => {
+                            this.parse_text_element(dom, name, attrs, State::ScriptData);
+                            break;
                         }
-                        Some(Token::EndTag { name }) if this.is_str_in(dom, name, &["head"]) => {
+                        Some(Token::EndTag { name }) if name == atoms::HEAD => {
                             this.stack.pop();
                             this.insertion_mode = InsertionMode::AfterHead;
                             break;
                         }
                         Some(Token::EndTag { name })
-                            if this.is_str_in(dom, name, &["body", "html", "br"]) =>
+                            if is_atom_in(name, &[atoms::BODY, atoms::HTML, atoms::BR]) =>
                         {
                             this.stack.pop();
                             this.insertion_mode = InsertionMode::AfterHead;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["template"]) =>
-                        {
-                            todo!()
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::TEMPLATE => {
+                            let template = this.insert_element(dom, name, attrs);
+                            dom.create_template_contents(template);
+                            this.active_formatting_elements
+                                .push(FormattingElement::Marker);
+                            this.frameset_ok = false;
+                            this.insertion_mode = InsertionMode::InTemplate;
+                            this.template_insertion_modes
+                                .push(InsertionMode::InTemplate);
+                            break;
                         }
-                        Some(Token::EndTag { name })
-                            if this.is_str_in(dom, name, &["template"]) =>
+                        Some(Token::EndTag { name }) if name == atoms::TEMPLATE => {
+                            if !this.stack_contains(dom, &[atoms::TEMPLATE]) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.generate_all_implied_end_tags_thoroughly(dom);
+                            if !this.is_current_node_in(dom, &[atoms::TEMPLATE]) {
+                                this.error(ParseError::UnexpectedToken);
+                            }
+                            this.close_until(dom, name);
+                            this.clear_active_formatting_elements_to_last_marker();
+                            this.template_insertion_modes.pop();
+                            this.reset_insertion_mode(dom);
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. }) if name == atoms::HEAD => break,
+                        Some(Token::EndTag { .. }) => break,
+                        _ => {
+                            this.stack.pop();
+                            this.insertion_mode = InsertionMode::AfterHead;
+                        }
+                    },
+                    InsertionMode::InHeadNoscript => match tok {
+                        Some(Token::DocType { .. }) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. }) if name == atoms::HTML => {
+                            using_rules_for = Some(InsertionMode::InBody);
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::NOSCRIPT => {
+                            this.stack.pop();
+                            this.insertion_mode = InsertionMode::InHead;
+                            break;
+                        }
+                        Some(Token::Char('\t' | '\n' | '\x0C' | ' ') | Token::Comment(_)) => {
+                            using_rules_for = Some(InsertionMode::InHead);
+                        }
+                        Some(Token::StartTag { name, .. })
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::BASEFONT,
+                                    atoms::BGSOUND,
+                                    atoms::LINK,
+                                    atoms::META,
+                                    atoms::NOFRAMES,
+                                    atoms::STYLE,
+                                ],
+                            ) =>
                         {
-                            todo!()
+                            using_rules_for = Some(InsertionMode::InHead);
                         }
                         Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["head"]) =>
+                            if is_atom_in(name, &[atoms::HEAD, atoms::NOSCRIPT]) =>
                         {
                             break
                         }
-                        Some(Token::EndTag { .. }) => break,
+                        Some(Token::EndTag { name }) if name != atoms::BR => break,
                         _ => {
+                            this.error(ParseError::UnexpectedToken);
                             this.stack.pop();
-                            this.insertion_mode = InsertionMode::AfterHead;
+                            this.insertion_mode = InsertionMode::InHead;
                         }
                     },
                     InsertionMode::AfterHead => match tok {
@@ -395,115 +1534,146 @@ impl<R: AsyncRead + Unpin> Parser<R> {
                             this.append_text(dom, c);
                             break;
                         }
-                        Some(Token::DocType | Token::Comment) => break,
-                        Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(dom, name, &["html"]) =>
-                        {
-                            if this.stack_contains(dom, &["template"]) {
+                        Some(Token::DocType { .. }) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::Comment(data)) => {
+                            this.insert_comment(dom, data);
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::HTML => {
+                            this.error(ParseError::UnexpectedToken);
+                            if this.stack_contains(dom, &[atoms::TEMPLATE]) {
                                 break;
                             }
-                            // error: merge attrs
-                            let top = *this.stack.last().unwrap();
-                            let mut top = dom.get_element_node_mut(top).unwrap();
-                            top.insert_missing_attrs(attrs);
+                            let html = this.stack[0];
+                            let mut html = dom.get_element_node_mut(html).unwrap();
+                            html.insert_missing_attrs(attrs);
                             break;
                         }
-                        Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(dom, name, &["body"]) =>
-                        {
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::BODY => {
                             let top = *this.stack.last().unwrap();
                             let mut top = dom.get_element_node_mut(top).unwrap();
-                            let body = top.append_child_element(name, attrs);
+                            let body = top.append_child_element(Namespace::Html, name, attrs);
                             this.stack.push(body);
+                            this.frameset_ok = false;
                             this.insertion_mode = InsertionMode::InBody;
                             break;
                         }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::FRAMESET => {
+                            this.insert_element(dom, name, attrs);
+                            this.insertion_mode = InsertionMode::InFrameset;
+                            break;
+                        }
                         Some(Token::StartTag { name, .. })
-                            if this.is_str_in(
-                                dom,
+                            if is_atom_in(
                                 name,
                                 &[
-                                    "base", "link", "meta", "noframes", "script", "style",
-                                    "template", "title",
+                                    atoms::BASE,
+                                    atoms::BASEFONT,
+                                    atoms::BGSOUND,
+                                    atoms::LINK,
+                                    atoms::META,
+                                    atoms::NOFRAMES,
+                                    atoms::SCRIPT,
+                                    atoms::STYLE,
+                                    atoms::TEMPLATE,
+                                    atoms::TITLE,
                                 ],
                             ) =>
                         {
-                            todo!()
+                            this.error(ParseError::UnexpectedToken);
+                            this.stack.push(this.head.unwrap());
+                            using_rules_for = Some(InsertionMode::InHead);
+                            pop_head = true;
                         }
-                        Some(Token::EndTag { name })
-                            if this.is_str_in(dom, name, &["template"]) =>
-                        {
-                            todo!()
+                        Some(Token::EndTag { name }) if name == atoms::TEMPLATE => {
+                            using_rules_for = Some(InsertionMode::InHead);
                         }
                         Some(Token::EndTag { name })
-                            if this.is_str_in(dom, name, &["body", "html", "br"]) =>
+                            if is_atom_in(name, &[atoms::BODY, atoms::HTML, atoms::BR]) =>
                         {
-                            let body = dom.insert_str("body"); // synthetic
+                            let body = atoms::BODY; // synthetic
                             let top = *this.stack.last().unwrap();
                             let mut top = dom.get_element_node_mut(top).unwrap();
-                            let body = top.append_child_element(body, EMPTY_RANGE_INDEX);
+                            let body =
+                                top.append_child_element(Namespace::Html, body, EMPTY_RANGE_INDEX);
                             this.stack.push(body);
                             this.insertion_mode = InsertionMode::InBody;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["head"]) =>
-                        {
-                            break
-                        }
+                        Some(Token::StartTag { name, .. }) if name == atoms::HEAD => break,
                         Some(Token::EndTag { .. }) => break,
                         _ => {
-                            let body = dom.insert_str("body"); // synthetic
+                            let body = atoms::BODY; // synthetic
                             let top = *this.stack.last().unwrap();
                             let mut top = dom.get_element_node_mut(top).unwrap();
-                            let body = top.append_child_element(body, EMPTY_RANGE_INDEX);
+                            let body =
+                                top.append_child_element(Namespace::Html, body, EMPTY_RANGE_INDEX);
                             this.stack.push(body);
                             this.insertion_mode = InsertionMode::InBody;
                         }
                     },
                     InsertionMode::InBody => match tok {
+                        Some(Token::Char('\0')) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
                         Some(Token::Char(c)) if "\t\n\x0C ".contains(c) => {
+                            this.reconstruct_active_formatting_elements(dom);
                             this.append_text(dom, c);
                             break;
                         }
                         Some(Token::Char(c)) => {
+                            this.reconstruct_active_formatting_elements(dom);
                             this.append_text(dom, c);
                             this.frameset_ok = false;
                             break;
                         }
-                        Some(Token::DocType | Token::Comment) => break,
-                        Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(dom, name, &["html"]) =>
-                        {
-                            if this.stack_contains(dom, &["template"]) {
+                        Some(Token::DocType { .. }) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::Comment(data)) => {
+                            this.insert_comment(dom, data);
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::HTML => {
+                            this.error(ParseError::UnexpectedToken);
+                            if this.stack_contains(dom, &[atoms::TEMPLATE]) {
                                 break;
                             }
-                            // error: merge attrs
-                            let top = *this.stack.last().unwrap();
-                            let mut top = dom.get_element_node_mut(top).unwrap();
-                            top.insert_missing_attrs(attrs);
+                            let html = this.stack[0];
+                            let mut html = dom.get_element_node_mut(html).unwrap();
+                            html.insert_missing_attrs(attrs);
                             break;
                         }
                         Some(Token::StartTag { name, .. })
-                            if this.is_str_in(
-                                dom,
+                            if is_atom_in(
                                 name,
                                 &[
-                                    "base", "link", "meta", "noframes", "script", "style",
-                                    "template", "title",
+                                    atoms::BASE,
+                                    atoms::BASEFONT,
+                                    atoms::BGSOUND,
+                                    atoms::LINK,
+                                    atoms::META,
+                                    atoms::NOFRAMES,
+                                    atoms::SCRIPT,
+                                    atoms::STYLE,
+                                    atoms::TEMPLATE,
+                                    atoms::TITLE,
                                 ],
                             ) =>
                         {
-                            todo!()
+                            using_rules_for = Some(InsertionMode::InHead);
                         }
-                        Some(Token::EndTag { name })
-                            if this.is_str_in(dom, name, &["template"]) =>
-                        {
-                            todo!()
+                        Some(Token::EndTag { name }) if name == atoms::TEMPLATE => {
+                            using_rules_for = Some(InsertionMode::InHead);
                         }
-                        Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(dom, name, &["body"]) =>
-                        {
-                            if (this.stack.len() == 1) || this.stack_contains(dom, &["template"]) {
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::BODY => {
+                            if (this.stack.len() == 1)
+                                || this.stack_contains(dom, &[atoms::TEMPLATE])
+                            {
                                 break;
                             }
                             let element = this.stack[1];
@@ -512,376 +1682,1643 @@ impl<R: AsyncRead + Unpin> Parser<R> {
                                 break;
                             }
                             this.frameset_ok = false;
-                            // error: merge attrs
+                            this.error(ParseError::UnexpectedToken);
                             element.insert_missing_attrs(attrs);
                             break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["frameset"]) =>
-                        {
-                            todo!()
-                        }
-                        None => {
-                            if !this.template_insertion_modes.is_empty() {
-                                todo!();
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::FRAMESET => {
+                            this.error(ParseError::UnexpectedToken);
+                            if this.stack.len() == 1
+                                || !this.is_html_element_in(dom, this.stack[1], &[atoms::BODY])
+                                || !this.frameset_ok
+                            {
+                                break;
                             }
-                            return this.stop_parsing();
+                            // the frameset replaces the body
+                            dom.remove_node(this.stack[1]);
+                            this.stack.truncate(1);
+                            this.insert_element(dom, name, attrs);
+                            this.insertion_mode = InsertionMode::InFrameset;
+                            break;
                         }
-                        Some(Token::EndTag { name }) if this.is_str_in(dom, name, &["body"]) => {
-                            if !this.is_in_scope(dom, "body") {
+                        None if !this.template_insertion_modes.is_empty() => {
+                            using_rules_for = Some(InsertionMode::InTemplate);
+                        }
+                        None => return this.stop_parsing(dom),
+                        Some(Token::EndTag { name }) if name == atoms::BODY => {
+                            if !this.is_in_scope(dom, atoms::BODY, Scope::Default) {
                                 break;
                             }
                             this.insertion_mode = InsertionMode::AfterBody;
                             break;
                         }
-                        Some(Token::EndTag { name }) if this.is_str_in(dom, name, &["html"]) => {
-                            if !this.is_in_scope(dom, "body") {
+                        Some(Token::EndTag { name }) if name == atoms::HTML => {
+                            if !this.is_in_scope(dom, atoms::BODY, Scope::Default) {
                                 break;
                             }
                             this.insertion_mode = InsertionMode::AfterBody;
                         }
                         Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(
-                                dom,
+                            if is_atom_in(
                                 name,
                                 &[
-                                    "address",
-                                    "article",
-                                    "aside",
-                                    "blockquote",
-                                    "center",
-                                    "details",
-                                    "dialog",
-                                    "dir",
-                                    "div",
-                                    "dl",
-                                    "fieldset",
-                                    "figcaption",
-                                    "figure",
-                                    "footer",
-                                    "header",
-                                    "hgroup",
-                                    "main",
-                                    "menu",
-                                    "nav",
-                                    "ol",
-                                    "p",
-                                    "search",
-                                    "section",
-                                    "summary",
-                                    "ul",
+                                    atoms::ADDRESS,
+                                    atoms::ARTICLE,
+                                    atoms::ASIDE,
+                                    atoms::BLOCKQUOTE,
+                                    atoms::CENTER,
+                                    atoms::DETAILS,
+                                    atoms::DIALOG,
+                                    atoms::DIR,
+                                    atoms::DIV,
+                                    atoms::DL,
+                                    atoms::FIELDSET,
+                                    atoms::FIGCAPTION,
+                                    atoms::FIGURE,
+                                    atoms::FOOTER,
+                                    atoms::HEADER,
+                                    atoms::HGROUP,
+                                    atoms::MAIN,
+                                    atoms::MENU,
+                                    atoms::NAV,
+                                    atoms::OL,
+                                    atoms::P,
+                                    atoms::SEARCH,
+                                    atoms::SECTION,
+                                    atoms::SUMMARY,
+                                    atoms::UL,
                                 ],
                             ) =>
                         {
-                            if this.is_in_button_scope(dom, "p") {
+                            if this.is_in_scope(dom, atoms::P, Scope::Button) {
                                 this.close_p(dom);
                             }
-                            let top = *this.stack.last().unwrap();
-                            let mut top = dom.get_element_node_mut(top).unwrap();
-                            let element = top.append_child_element(name, attrs);
-                            this.stack.push(element);
+                            this.insert_element(dom, name, attrs);
                             break;
                         }
                         Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(dom, name, &["h1", "h2", "h3", "h4", "h5", "h6"]) =>
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::H1,
+                                    atoms::H2,
+                                    atoms::H3,
+                                    atoms::H4,
+                                    atoms::H5,
+                                    atoms::H6,
+                                ],
+                            ) =>
                         {
-                            if this.is_in_button_scope(dom, "p") {
+                            if this.is_in_scope(dom, atoms::P, Scope::Button) {
                                 this.close_p(dom);
                             }
-                            let mut top = *this.stack.last().unwrap();
-                            let element = dom.get_element_node(top).unwrap();
-                            if this.is_str_in(
+                            if this.is_current_node_in(
                                 dom,
-                                element.name(),
-                                &["h1", "h2", "h3", "h4", "h5", "h6"],
+                                &[
+                                    atoms::H1,
+                                    atoms::H2,
+                                    atoms::H3,
+                                    atoms::H4,
+                                    atoms::H5,
+                                    atoms::H6,
+                                ],
                             ) {
                                 this.stack.pop();
-                                top = *this.stack.last().unwrap();
                             }
-                            let mut top = dom.get_element_node_mut(top).unwrap();
-                            let element = top.append_child_element(name, attrs);
-                            this.stack.push(element);
+                            this.insert_element(dom, name, attrs);
                             break;
                         }
                         Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(dom, name, &["pre", "listing"]) =>
+                            if is_atom_in(name, &[atoms::PRE, atoms::LISTING]) =>
                         {
-                            if this.is_in_button_scope(dom, "p") {
+                            if this.is_in_scope(dom, atoms::P, Scope::Button) {
                                 this.close_p(dom);
                             }
-                            let top = *this.stack.last().unwrap();
-                            let mut top = dom.get_element_node_mut(top).unwrap();
-                            let element = top.append_child_element(name, attrs);
-                            this.stack.push(element);
+                            this.insert_element(dom, name, attrs);
                             this.frameset_ok = false;
                             this.skip_next_linefeed = true;
                             break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["form"]) =>
-                        {
-                            todo!()
-                        }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["li"]) =>
-                        {
-                            todo!()
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::FORM => {
+                            let template = this.stack_contains(dom, &[atoms::TEMPLATE]);
+                            if this.form.is_some() && !template {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            if this.is_in_scope(dom, atoms::P, Scope::Button) {
+                                this.close_p(dom);
+                            }
+                            let form = this.insert_element(dom, name, attrs);
+                            if !template {
+                                this.form = Some(form);
+                            }
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["dd", "dt"]) =>
+                        Some(Token::StartTag { name, attrs, .. })
+                            if is_atom_in(name, &[atoms::LI, atoms::DD, atoms::DT]) =>
                         {
-                            todo!()
+                            this.frameset_ok = false;
+                            // a new list item closes the open one, dd and dt close each other
+                            let closes: &[usize] = if name == atoms::LI {
+                                &[atoms::LI]
+                            } else {
+                                &[atoms::DD, atoms::DT]
+                            };
+                            for &node in this.stack.iter().rev() {
+                                if this.is_html_element_in(dom, node, closes) {
+                                    let node = dom.get_element_node(node).unwrap().name();
+                                    this.generate_implied_end_tags(dom, Some(node));
+                                    if !this.is_current_node_in(dom, closes) {
+                                        this.error(ParseError::UnexpectedToken);
+                                    }
+                                    this.close_until_one_of(dom, closes);
+                                    break;
+                                }
+                                if this.is_special(dom, node)
+                                    && !this.is_html_element_in(
+                                        dom,
+                                        node,
+                                        &[atoms::ADDRESS, atoms::DIV, atoms::P],
+                                    )
+                                {
+                                    break;
+                                }
+                            }
+                            if this.is_in_scope(dom, atoms::P, Scope::Button) {
+                                this.close_p(dom);
+                            }
+                            this.insert_element(dom, name, attrs);
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["plaintext"]) =>
-                        {
-                            todo!()
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::PLAINTEXT => {
+                            if this.is_in_scope(dom, atoms::P, Scope::Button) {
+                                this.close_p(dom);
+                            }
+                            this.insert_element(dom, name, attrs);
+                            this.tokenizer.set_state(State::PlainText);
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["button"]) =>
-                        {
-                            todo!()
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::BUTTON => {
+                            if this.is_in_scope(dom, name, Scope::Default) {
+                                this.error(ParseError::UnexpectedToken);
+                                this.generate_implied_end_tags(dom, None);
+                                this.close_until(dom, name);
+                            }
+                            this.reconstruct_active_formatting_elements(dom);
+                            this.insert_element(dom, name, attrs);
+                            this.frameset_ok = false;
+                            break;
                         }
                         Some(Token::EndTag { name })
-                            if this.is_str_in(
-                                dom,
+                            if is_atom_in(
                                 name,
                                 &[
-                                    "address",
-                                    "article",
-                                    "aside",
-                                    "blockquote",
-                                    "center",
-                                    "details",
-                                    "dialog",
-                                    "dir",
-                                    "div",
-                                    "dl",
-                                    "fieldset",
-                                    "figcaption",
-                                    "figure",
-                                    "footer",
-                                    "header",
-                                    "hgroup",
-                                    "main",
-                                    "menu",
-                                    "nav",
-                                    "ol",
-                                    "p",
-                                    "search",
-                                    "section",
-                                    "summary",
-                                    "ul",
+                                    atoms::ADDRESS,
+                                    atoms::ARTICLE,
+                                    atoms::ASIDE,
+                                    atoms::BLOCKQUOTE,
+                                    atoms::BUTTON,
+                                    atoms::CENTER,
+                                    atoms::DETAILS,
+                                    atoms::DIALOG,
+                                    atoms::DIR,
+                                    atoms::DIV,
+                                    atoms::DL,
+                                    atoms::FIELDSET,
+                                    atoms::FIGCAPTION,
+                                    atoms::FIGURE,
+                                    atoms::FOOTER,
+                                    atoms::HEADER,
+                                    atoms::HGROUP,
+                                    atoms::MAIN,
+                                    atoms::MENU,
+                                    atoms::NAV,
+                                    atoms::OL,
+                                    atoms::SEARCH,
+                                    atoms::SECTION,
+                                    atoms::SUMMARY,
+                                    atoms::UL,
                                 ],
                             ) =>
                         {
-                            if !this.is_index_in_scope(dom, name) {
+                            if !this.is_in_scope(dom, name, Scope::Default) {
+                                this.error(ParseError::UnexpectedToken);
                                 break;
                             }
-                            this.close_implied_end_elements(
-                                dom,
-                                &["dd", "dt", "li", "optgroup", "option", "p"],
-                            );
+                            this.generate_implied_end_tags(dom, None);
+                            let top = *this.stack.last().unwrap();
+                            if !this.is_html_element_named(dom, top, name) {
+                                this.error(ParseError::UnexpectedToken);
+                            }
                             this.close_until(dom, name);
                             break;
                         }
-                        Some(Token::EndTag { name }) if this.is_str_in(dom, name, &["form"]) => {
-                            todo!()
-                        }
-                        Some(Token::EndTag { name }) if this.is_str_in(dom, name, &["p"]) => {
-                            todo!()
+                        Some(Token::EndTag { name }) if name == atoms::FORM => {
+                            if this.stack_contains(dom, &[atoms::TEMPLATE]) {
+                                if !this.is_in_scope(dom, name, Scope::Default) {
+                                    this.error(ParseError::UnexpectedToken);
+                                    break;
+                                }
+                                this.generate_implied_end_tags(dom, None);
+                                if !this.is_current_node_in(dom, &[atoms::FORM]) {
+                                    this.error(ParseError::UnexpectedToken);
+                                }
+                                this.close_until(dom, name);
+                                break;
+                            }
+                            let form = this.form.take();
+                            let Some(form) =
+                                form.filter(|&form| this.is_element_in_scope(dom, form))
+                            else {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            };
+                            this.generate_implied_end_tags(dom, None);
+                            if *this.stack.last().unwrap() != form {
+                                this.error(ParseError::UnexpectedToken);
+                            }
+                            this.stack.retain(|&element| element != form);
+                            break;
                         }
-                        Some(Token::EndTag { name }) if this.is_str_in(dom, name, &["li"]) => {
-                            todo!()
+                        Some(Token::EndTag { name }) if name == atoms::P => {
+                            if !this.is_in_scope(dom, atoms::P, Scope::Button) {
+                                this.error(ParseError::UnexpectedToken);
+                                this.insert_element(dom, name, EMPTY_RANGE_INDEX);
+                            }
+                            this.close_p(dom);
+                            break;
                         }
                         Some(Token::EndTag { name })
-                            if this.is_str_in(dom, name, &["dd", "dt"]) =>
+                            if is_atom_in(name, &[atoms::LI, atoms::DD, atoms::DT]) =>
                         {
-                            todo!()
+                            let scope = if name == atoms::LI {
+                                Scope::ListItem
+                            } else {
+                                Scope::Default
+                            };
+                            if !this.is_in_scope(dom, name, scope) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.generate_implied_end_tags(dom, Some(name));
+                            let top = *this.stack.last().unwrap();
+                            if !this.is_html_element_named(dom, top, name) {
+                                this.error(ParseError::UnexpectedToken);
+                            }
+                            this.close_until(dom, name);
+                            break;
                         }
                         Some(Token::EndTag { name })
-                            if this.is_str_in(dom, name, &["h1", "h2", "h3", "h4", "h5", "h6"]) =>
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::H1,
+                                    atoms::H2,
+                                    atoms::H3,
+                                    atoms::H4,
+                                    atoms::H5,
+                                    atoms::H6,
+                                ],
+                            ) =>
                         {
-                            todo!()
+                            let headings = &[
+                                atoms::H1,
+                                atoms::H2,
+                                atoms::H3,
+                                atoms::H4,
+                                atoms::H5,
+                                atoms::H6,
+                            ];
+                            if !this.is_one_of_in_scope(dom, headings, Scope::Default) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.generate_implied_end_tags(dom, None);
+                            let top = *this.stack.last().unwrap();
+                            if !this.is_html_element_named(dom, top, name) {
+                                this.error(ParseError::UnexpectedToken);
+                            }
+                            this.close_until_one_of(dom, headings);
+                            break;
                         }
-                        Some(Token::StartTag { name, .. }) if this.is_str_in(dom, name, &["a"]) => {
-                            todo!()
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::A => {
+                            if let Some(i) = this.last_formatting_element(name) {
+                                let FormattingElement::Element { id, .. } =
+                                    this.active_formatting_elements[i]
+                                else {
+                                    unreachable!()
+                                };
+                                this.error(ParseError::UnexpectedToken);
+                                if !this.adoption_agency(dom, name) {
+                                    this.any_other_end_tag(dom, name);
+                                }
+                                if let Some(i) = this.formatting_element_position(id) {
+                                    this.active_formatting_elements.remove(i);
+                                }
+                                this.stack.retain(|element| *element != id);
+                            }
+                            this.reconstruct_active_formatting_elements(dom);
+                            let element = this.insert_element(dom, name, attrs);
+                            this.push_active_formatting_element(dom, element, name, attrs);
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(
-                                dom,
+                        Some(Token::StartTag { name, attrs, .. })
+                            if is_atom_in(
                                 name,
                                 &[
-                                    "b", "big", "code", "em", "font", "i", "s", "small", "strike",
-                                    "strong", "tt", "u",
+                                    atoms::B,
+                                    atoms::BIG,
+                                    atoms::CODE,
+                                    atoms::EM,
+                                    atoms::FONT,
+                                    atoms::I,
+                                    atoms::S,
+                                    atoms::SMALL,
+                                    atoms::STRIKE,
+                                    atoms::STRONG,
+                                    atoms::TT,
+                                    atoms::U,
                                 ],
                             ) =>
                         {
-                            todo!()
+                            this.reconstruct_active_formatting_elements(dom);
+                            let element = this.insert_element(dom, name, attrs);
+                            this.push_active_formatting_element(dom, element, name, attrs);
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["nobr"]) =>
-                        {
-                            todo!()
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::NOBR => {
+                            this.reconstruct_active_formatting_elements(dom);
+                            if this.is_in_scope(dom, name, Scope::Default) {
+                                this.error(ParseError::UnexpectedToken);
+                                if !this.adoption_agency(dom, name) {
+                                    this.any_other_end_tag(dom, name);
+                                }
+                                this.reconstruct_active_formatting_elements(dom);
+                            }
+                            let element = this.insert_element(dom, name, attrs);
+                            this.push_active_formatting_element(dom, element, name, attrs);
+                            break;
                         }
                         Some(Token::EndTag { name })
-                            if this.is_str_in(
-                                dom,
+                            if is_atom_in(
                                 name,
                                 &[
-                                    "a", "b", "big", "code", "em", "font", "i", "nobr", "s",
-                                    "small", "strike", "strong", "tt", "u",
+                                    atoms::A,
+                                    atoms::B,
+                                    atoms::BIG,
+                                    atoms::CODE,
+                                    atoms::EM,
+                                    atoms::FONT,
+                                    atoms::I,
+                                    atoms::NOBR,
+                                    atoms::S,
+                                    atoms::SMALL,
+                                    atoms::STRIKE,
+                                    atoms::STRONG,
+                                    atoms::TT,
+                                    atoms::U,
                                 ],
                             ) =>
                         {
-                            todo!()
+                            if !this.adoption_agency(dom, name) {
+                                this.any_other_end_tag(dom, name);
+                            }
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["applet", "marquee", "object"]) =>
+                        Some(Token::StartTag { name, attrs, .. })
+                            if is_atom_in(
+                                name,
+                                &[atoms::APPLET, atoms::MARQUEE, atoms::OBJECT],
+                            ) =>
                         {
-                            todo!()
+                            this.reconstruct_active_formatting_elements(dom);
+                            this.insert_element(dom, name, attrs);
+                            this.active_formatting_elements
+                                .push(FormattingElement::Marker);
+                            this.frameset_ok = false;
+                            break;
                         }
                         Some(Token::EndTag { name })
-                            if this.is_str_in(dom, name, &["applet", "marquee", "object"]) =>
+                            if is_atom_in(
+                                name,
+                                &[atoms::APPLET, atoms::MARQUEE, atoms::OBJECT],
+                            ) =>
                         {
-                            todo!()
+                            if !this.is_in_scope(dom, name, Scope::Default) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.generate_implied_end_tags(dom, None);
+                            let top = *this.stack.last().unwrap();
+                            if !this.is_html_element_named(dom, top, name) {
+                                this.error(ParseError::UnexpectedToken);
+                            }
+                            this.close_until(dom, name);
+                            this.clear_active_formatting_elements_to_last_marker();
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["table"]) =>
-                        {
-                            todo!()
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::TABLE => {
+                            // quirks mode tables can go inside paragraphs
+                            if dom.quirks_mode() != QuirksMode::Quirks
+                                && this.is_in_scope(dom, atoms::P, Scope::Button)
+                            {
+                                this.close_p(dom);
+                            }
+                            this.insert_element(dom, name, attrs);
+                            this.frameset_ok = false;
+                            this.insertion_mode = InsertionMode::InTable;
+                            break;
                         }
-                        Some(Token::EndTag { name }) if this.is_str_in(dom, name, &["br"]) => {
-                            todo!()
+                        Some(Token::EndTag { name }) if name == atoms::BR => {
+                            // treated as a <br> without attributes
+                            this.error(ParseError::UnexpectedToken);
+                            let tok = Token::StartTag {
+                                name,
+                                attrs: EMPTY_RANGE_INDEX,
+                                self_closing: false,
+                            };
+                            this.tok_buf.push((this.loc, tok));
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(
-                                dom,
+                        Some(Token::StartTag { name, attrs, .. })
+                            if is_atom_in(
                                 name,
-                                &["area", "br", "embed", "img", "keygen", "wbr"],
+                                &[
+                                    atoms::AREA,
+                                    atoms::BR,
+                                    atoms::EMBED,
+                                    atoms::IMG,
+                                    atoms::KEYGEN,
+                                    atoms::WBR,
+                                ],
                             ) =>
                         {
-                            todo!()
-                        }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["input"]) =>
-                        {
-                            todo!()
+                            this.reconstruct_active_formatting_elements(dom);
+                            this.insert_element(dom, name, attrs);
+                            this.stack.pop();
+                            this.frameset_ok = false;
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["param", "source", "track"]) =>
-                        {
-                            todo!()
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::INPUT => {
+                            this.reconstruct_active_formatting_elements(dom);
+                            this.insert_element(dom, name, attrs);
+                            this.stack.pop();
+                            if !this.is_hidden_input(dom, attrs) {
+                                this.frameset_ok = false;
+                            }
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["hr"]) =>
+                        Some(Token::StartTag { name, attrs, .. })
+                            if is_atom_in(name, &[atoms::PARAM, atoms::SOURCE, atoms::TRACK]) =>
                         {
-                            todo!()
+                            this.insert_element(dom, name, attrs);
+                            this.stack.pop();
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["image"]) =>
-                        {
-                            todo!()
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::HR => {
+                            if this.is_in_scope(dom, atoms::P, Scope::Button) {
+                                this.close_p(dom);
+                            }
+                            this.insert_element(dom, name, attrs);
+                            this.stack.pop();
+                            this.frameset_ok = false;
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["textarea"]) =>
-                        {
-                            todo!()
+                        Some(Token::StartTag {
+                            name,
+                            attrs,
+                            self_closing,
+                        }) if name == atoms::IMAGE => {
+                            // an old alias for img
+                            this.error(ParseError::UnexpectedToken);
+                            let tok = Token::StartTag {
+                                name: atoms::IMG,
+                                attrs,
+                                self_closing,
+                            };
+                            this.tok_buf.push((this.loc, tok));
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["iframe"]) =>
-                        {
-                            todo!()
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::TEXTAREA => {
+                            this.parse_text_element(dom, name, attrs, State::RcData);
+                            // a newline right after the start tag is ignored
+                            this.skip_next_linefeed = true;
+                            this.frameset_ok = false;
+                            break;
                         }
-                        Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["noembed"]) =>
-                        {
-                            todo!()
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::XMP => {
+                            if this.is_in_scope(dom, atoms::P, Scope::Button) {
+                                this.close_p(dom);
+                            }
+                            this.reconstruct_active_formatting_elements(dom);
+                            this.frameset_ok = false;
+                            this.parse_text_element(dom, name, attrs, State::RawText);
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::IFRAME => {
+                            this.frameset_ok = false;
+                            this.parse_text_element(dom, name, attrs, State::RawText);
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::NOEMBED => {
+                            this.parse_text_element(dom, name, attrs, State::RawText);
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::SELECT => {
+                            this.reconstruct_active_formatting_elements(dom);
+                            this.insert_element(dom, name, attrs);
+                            this.frameset_ok = false;
+                            this.insertion_mode = match this.insertion_mode {
+                                InsertionMode::InTable
+                                | InsertionMode::InCaption
+                                | InsertionMode::InTableBody
+                                | InsertionMode::InRow
+                                | InsertionMode::InCell => InsertionMode::InSelectInTable,
+                                _ => InsertionMode::InSelect,
+                            };
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. })
+                            if is_atom_in(name, &[atoms::OPTGROUP, atoms::OPTION]) =>
+                        {
+                            if this.is_current_node_in(dom, &[atoms::OPTION]) {
+                                this.stack.pop();
+                            }
+                            this.reconstruct_active_formatting_elements(dom);
+                            this.insert_element(dom, name, attrs);
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. })
+                            if is_atom_in(name, &[atoms::RB, atoms::RTC]) =>
+                        {
+                            if this.is_in_scope(dom, atoms::RUBY, Scope::Default) {
+                                this.generate_implied_end_tags(dom, None);
+                                if !this.is_current_node_in(dom, &[atoms::RUBY]) {
+                                    this.error(ParseError::UnexpectedToken);
+                                }
+                            }
+                            this.insert_element(dom, name, attrs);
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. })
+                            if is_atom_in(name, &[atoms::RP, atoms::RT]) =>
+                        {
+                            if this.is_in_scope(dom, atoms::RUBY, Scope::Default) {
+                                let rtc = atoms::RTC;
+                                this.generate_implied_end_tags(dom, Some(rtc));
+                                if !this.is_current_node_in(dom, &[atoms::RTC, atoms::RUBY]) {
+                                    this.error(ParseError::UnexpectedToken);
+                                }
+                            }
+                            this.insert_element(dom, name, attrs);
+                            break;
+                        }
+                        Some(Token::StartTag {
+                            name,
+                            attrs,
+                            self_closing,
+                        }) if is_atom_in(name, &[atoms::MATH, atoms::SVG]) => {
+                            this.reconstruct_active_formatting_elements(dom);
+                            let (namespace, attrs) = if name == atoms::MATH {
+                                let adjust = foreign::adjust_mathml_attribute;
+                                (Namespace::MathMl, this.adjust_attrs(dom, attrs, adjust))
+                            } else {
+                                let adjust = foreign::adjust_svg_attribute;
+                                (Namespace::Svg, this.adjust_attrs(dom, attrs, adjust))
+                            };
+                            this.insert_foreign_element(dom, namespace, name, attrs);
+                            if self_closing {
+                                this.stack.pop();
+                            }
+                            break;
                         }
                         Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["noscript"]) =>
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::CAPTION,
+                                    atoms::COL,
+                                    atoms::COLGROUP,
+                                    atoms::FRAME,
+                                    atoms::HEAD,
+                                    atoms::TBODY,
+                                    atoms::TD,
+                                    atoms::TFOOT,
+                                    atoms::TH,
+                                    atoms::THEAD,
+                                    atoms::TR,
+                                ],
+                            ) =>
+                        {
+                            break
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) => {
+                            this.reconstruct_active_formatting_elements(dom);
+                            this.insert_element(dom, name, attrs);
+                            break;
+                        }
+                        Some(Token::EndTag { name }) => {
+                            this.any_other_end_tag(dom, name);
+                            break;
+                        }
+                    },
+                    InsertionMode::InTable => match tok {
+                        Some(Token::Char(_))
+                            if this.is_current_node_in(
+                                dom,
+                                &[
+                                    atoms::TABLE,
+                                    atoms::TBODY,
+                                    atoms::TEMPLATE,
+                                    atoms::TFOOT,
+                                    atoms::THEAD,
+                                    atoms::TR,
+                                ],
+                            ) =>
                         {
-                            todo!()
+                            this.pending_table_chars.clear();
+                            this.original_insertion_mode = this.insertion_mode;
+                            this.insertion_mode = InsertionMode::InTableText;
+                        }
+                        Some(Token::Comment(data)) => {
+                            this.insert_comment(dom, data);
+                            break;
+                        }
+                        Some(Token::DocType { .. }) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::CAPTION => {
+                            this.clear_stack_back_to(
+                                dom,
+                                &[atoms::TABLE, atoms::TEMPLATE, atoms::HTML],
+                            );
+                            this.insert_element(dom, name, attrs);
+                            this.active_formatting_elements
+                                .push(FormattingElement::Marker);
+                            this.insertion_mode = InsertionMode::InCaption;
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::COLGROUP => {
+                            this.clear_stack_back_to(
+                                dom,
+                                &[atoms::TABLE, atoms::TEMPLATE, atoms::HTML],
+                            );
+                            this.insert_element(dom, name, attrs);
+                            this.insertion_mode = InsertionMode::InColumnGroup;
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. }) if name == atoms::COL => {
+                            this.clear_stack_back_to(
+                                dom,
+                                &[atoms::TABLE, atoms::TEMPLATE, atoms::HTML],
+                            );
+                            this.insert_synthetic_element(dom, atoms::COLGROUP);
+                            this.insertion_mode = InsertionMode::InColumnGroup;
+                        }
+                        Some(Token::StartTag { name, attrs, .. })
+                            if is_atom_in(name, &[atoms::TBODY, atoms::TFOOT, atoms::THEAD]) =>
+                        {
+                            this.clear_stack_back_to(
+                                dom,
+                                &[atoms::TABLE, atoms::TEMPLATE, atoms::HTML],
+                            );
+                            this.insert_element(dom, name, attrs);
+                            this.insertion_mode = InsertionMode::InTableBody;
+                            break;
                         }
                         Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["select"]) =>
+                            if is_atom_in(name, &[atoms::TD, atoms::TH, atoms::TR]) =>
+                        {
+                            this.clear_stack_back_to(
+                                dom,
+                                &[atoms::TABLE, atoms::TEMPLATE, atoms::HTML],
+                            );
+                            this.insert_synthetic_element(dom, atoms::TBODY);
+                            this.insertion_mode = InsertionMode::InTableBody;
+                        }
+                        Some(Token::StartTag { name, .. }) if name == atoms::TABLE => {
+                            this.error(ParseError::UnexpectedToken);
+                            if !this.is_in_scope(dom, name, Scope::Table) {
+                                break;
+                            }
+                            this.close_until(dom, name);
+                            this.reset_insertion_mode(dom);
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::TABLE => {
+                            if !this.is_in_scope(dom, name, Scope::Table) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.close_until(dom, name);
+                            this.reset_insertion_mode(dom);
+                            break;
+                        }
+                        Some(Token::EndTag { name })
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::BODY,
+                                    atoms::CAPTION,
+                                    atoms::COL,
+                                    atoms::COLGROUP,
+                                    atoms::HTML,
+                                    atoms::TBODY,
+                                    atoms::TD,
+                                    atoms::TFOOT,
+                                    atoms::TH,
+                                    atoms::THEAD,
+                                    atoms::TR,
+                                ],
+                            ) =>
                         {
-                            todo!()
+                            this.error(ParseError::UnexpectedToken);
+                            break;
                         }
                         Some(Token::StartTag { name, .. })
-                            if this.is_str_in(dom, name, &["optgroup", "option"]) =>
+                            if is_atom_in(
+                                name,
+                                &[atoms::STYLE, atoms::SCRIPT, atoms::TEMPLATE],
+                            ) =>
                         {
-                            todo!()
+                            using_rules_for = Some(InsertionMode::InHead);
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::TEMPLATE => {
+                            using_rules_for = Some(InsertionMode::InHead);
+                        }
+                        Some(Token::StartTag { name, attrs, .. })
+                            if name == atoms::INPUT && this.is_hidden_input(dom, attrs) =>
+                        {
+                            this.error(ParseError::UnexpectedToken);
+                            this.insert_element(dom, name, attrs);
+                            this.stack.pop();
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::FORM => {
+                            this.error(ParseError::UnexpectedToken);
+                            if this.stack_contains(dom, &[atoms::TEMPLATE]) || this.form.is_some() {
+                                break;
+                            }
+                            let form = this.insert_element(dom, name, attrs);
+                            this.form = Some(form);
+                            this.stack.pop();
+                            break;
+                        }
+                        None => using_rules_for = Some(InsertionMode::InBody),
+                        _ => {
+                            this.error(ParseError::UnexpectedToken);
+                            this.foster_parenting = true;
+                            using_rules_for = Some(InsertionMode::InBody);
+                        }
+                    },
+                    InsertionMode::InTableText => match tok {
+                        Some(Token::Char('\0')) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::Char(c)) => {
+                            this.pending_table_chars.push(c);
+                            break;
+                        }
+                        _ => {
+                            let mut chars = std::mem::take(&mut this.pending_table_chars);
+                            if chars.contains(|c| !"\t\n\x0C ".contains(c)) {
+                                // same as the "anything else" rules in table
+                                this.error(ParseError::UnexpectedToken);
+                                this.foster_parenting = true;
+                                for c in chars.chars() {
+                                    this.reconstruct_active_formatting_elements(dom);
+                                    this.append_text(dom, c);
+                                }
+                                this.foster_parenting = false;
+                                this.frameset_ok = false;
+                            } else {
+                                for c in chars.chars() {
+                                    this.append_text(dom, c);
+                                }
+                            }
+                            chars.clear();
+                            this.pending_table_chars = chars;
+                            this.insertion_mode = this.original_insertion_mode;
+                        }
+                    },
+                    InsertionMode::InCaption => match tok {
+                        Some(Token::EndTag { name }) if name == atoms::CAPTION => {
+                            if !this.is_in_scope(dom, name, Scope::Table) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.generate_implied_end_tags(dom, None);
+                            if !this.is_current_node_in(dom, &[atoms::CAPTION]) {
+                                this.error(ParseError::UnexpectedToken);
+                            }
+                            this.close_until(dom, name);
+                            this.clear_active_formatting_elements_to_last_marker();
+                            this.insertion_mode = InsertionMode::InTable;
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. })
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::CAPTION,
+                                    atoms::COL,
+                                    atoms::COLGROUP,
+                                    atoms::TBODY,
+                                    atoms::TD,
+                                    atoms::TFOOT,
+                                    atoms::TH,
+                                    atoms::THEAD,
+                                    atoms::TR,
+                                ],
+                            ) =>
+                        {
+                            if !this.is_in_scope(dom, atoms::CAPTION, Scope::Table) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.generate_implied_end_tags(dom, None);
+                            if !this.is_current_node_in(dom, &[atoms::CAPTION]) {
+                                this.error(ParseError::UnexpectedToken);
+                            }
+                            this.close_until_one_of(dom, &[atoms::CAPTION]);
+                            this.clear_active_formatting_elements_to_last_marker();
+                            this.insertion_mode = InsertionMode::InTable;
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::TABLE => {
+                            if !this.is_in_scope(dom, atoms::CAPTION, Scope::Table) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.generate_implied_end_tags(dom, None);
+                            if !this.is_current_node_in(dom, &[atoms::CAPTION]) {
+                                this.error(ParseError::UnexpectedToken);
+                            }
+                            this.close_until_one_of(dom, &[atoms::CAPTION]);
+                            this.clear_active_formatting_elements_to_last_marker();
+                            this.insertion_mode = InsertionMode::InTable;
+                        }
+                        Some(Token::EndTag { name })
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::BODY,
+                                    atoms::COL,
+                                    atoms::COLGROUP,
+                                    atoms::HTML,
+                                    atoms::TBODY,
+                                    atoms::TD,
+                                    atoms::TFOOT,
+                                    atoms::TH,
+                                    atoms::THEAD,
+                                    atoms::TR,
+                                ],
+                            ) =>
+                        {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        _ => using_rules_for = Some(InsertionMode::InBody),
+                    },
+                    InsertionMode::InColumnGroup => match tok {
+                        Some(Token::Char(c)) if "\t\n\x0C ".contains(c) => {
+                            this.append_text(dom, c);
+                            break;
+                        }
+                        Some(Token::Comment(data)) => {
+                            this.insert_comment(dom, data);
+                            break;
+                        }
+                        Some(Token::DocType { .. }) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. }) if name == atoms::HTML => {
+                            using_rules_for = Some(InsertionMode::InBody);
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::COL => {
+                            this.insert_element(dom, name, attrs);
+                            this.stack.pop();
+                            break;
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::COLGROUP => {
+                            if !this.is_current_node_in(dom, &[atoms::COLGROUP]) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.stack.pop();
+                            this.insertion_mode = InsertionMode::InTable;
+                            break;
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::COL => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. } | Token::EndTag { name })
+                            if name == atoms::TEMPLATE =>
+                        {
+                            using_rules_for = Some(InsertionMode::InHead);
+                        }
+                        None => using_rules_for = Some(InsertionMode::InBody),
+                        _ => {
+                            if !this.is_current_node_in(dom, &[atoms::COLGROUP]) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.stack.pop();
+                            this.insertion_mode = InsertionMode::InTable;
+                        }
+                    },
+                    InsertionMode::InTableBody => match tok {
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::TR => {
+                            this.clear_stack_back_to(
+                                dom,
+                                &[
+                                    atoms::TBODY,
+                                    atoms::TFOOT,
+                                    atoms::THEAD,
+                                    atoms::TEMPLATE,
+                                    atoms::HTML,
+                                ],
+                            );
+                            this.insert_element(dom, name, attrs);
+                            this.insertion_mode = InsertionMode::InRow;
+                            break;
                         }
                         Some(Token::StartTag { name, .. })
-                            if this.is_str_in(
+                            if is_atom_in(name, &[atoms::TH, atoms::TD]) =>
+                        {
+                            this.error(ParseError::UnexpectedToken);
+                            this.clear_stack_back_to(
+                                dom,
+                                &[
+                                    atoms::TBODY,
+                                    atoms::TFOOT,
+                                    atoms::THEAD,
+                                    atoms::TEMPLATE,
+                                    atoms::HTML,
+                                ],
+                            );
+                            this.insert_synthetic_element(dom, atoms::TR);
+                            this.insertion_mode = InsertionMode::InRow;
+                        }
+                        Some(Token::EndTag { name })
+                            if is_atom_in(name, &[atoms::TBODY, atoms::TFOOT, atoms::THEAD]) =>
+                        {
+                            if !this.is_in_scope(dom, name, Scope::Table) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.clear_stack_back_to(
                                 dom,
+                                &[
+                                    atoms::TBODY,
+                                    atoms::TFOOT,
+                                    atoms::THEAD,
+                                    atoms::TEMPLATE,
+                                    atoms::HTML,
+                                ],
+                            );
+                            this.stack.pop();
+                            this.insertion_mode = InsertionMode::InTable;
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. })
+                            if is_atom_in(
                                 name,
                                 &[
-                                    "caption", "col", "colgroup", "frame", "head", "tbody", "td",
-                                    "tfoot", "th", "thead", "tr",
+                                    atoms::CAPTION,
+                                    atoms::COL,
+                                    atoms::COLGROUP,
+                                    atoms::TBODY,
+                                    atoms::TFOOT,
+                                    atoms::THEAD,
                                 ],
                             ) =>
                         {
-                            break
+                            if !this.is_in_scope(dom, atoms::TBODY, Scope::Table)
+                                && !this.is_in_scope(dom, atoms::THEAD, Scope::Table)
+                                && !this.is_in_scope(dom, atoms::TFOOT, Scope::Table)
+                            {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.clear_stack_back_to(
+                                dom,
+                                &[
+                                    atoms::TBODY,
+                                    atoms::TFOOT,
+                                    atoms::THEAD,
+                                    atoms::TEMPLATE,
+                                    atoms::HTML,
+                                ],
+                            );
+                            this.stack.pop();
+                            this.insertion_mode = InsertionMode::InTable;
                         }
-                        Some(Token::StartTag { name, attrs, .. }) => {
+                        Some(Token::EndTag { name }) if name == atoms::TABLE => {
+                            if !this.is_in_scope(dom, atoms::TBODY, Scope::Table)
+                                && !this.is_in_scope(dom, atoms::THEAD, Scope::Table)
+                                && !this.is_in_scope(dom, atoms::TFOOT, Scope::Table)
+                            {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.clear_stack_back_to(
+                                dom,
+                                &[
+                                    atoms::TBODY,
+                                    atoms::TFOOT,
+                                    atoms::THEAD,
+                                    atoms::TEMPLATE,
+                                    atoms::HTML,
+                                ],
+                            );
+                            this.stack.pop();
+                            this.insertion_mode = InsertionMode::InTable;
+                        }
+                        Some(Token::EndTag { name })
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::BODY,
+                                    atoms::CAPTION,
+                                    atoms::COL,
+                                    atoms::COLGROUP,
+                                    atoms::HTML,
+                                    atoms::TD,
+                                    atoms::TH,
+                                    atoms::TR,
+                                ],
+                            ) =>
+                        {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        _ => using_rules_for = Some(InsertionMode::InTable),
+                    },
+                    InsertionMode::InRow => match tok {
+                        Some(Token::StartTag { name, attrs, .. })
+                            if is_atom_in(name, &[atoms::TH, atoms::TD]) =>
+                        {
+                            this.clear_stack_back_to(
+                                dom,
+                                &[atoms::TR, atoms::TEMPLATE, atoms::HTML],
+                            );
+                            this.insert_element(dom, name, attrs);
+                            this.active_formatting_elements
+                                .push(FormattingElement::Marker);
+                            this.insertion_mode = InsertionMode::InCell;
+                            break;
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::TR => {
+                            if !this.is_in_scope(dom, name, Scope::Table) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.clear_stack_back_to(
+                                dom,
+                                &[atoms::TR, atoms::TEMPLATE, atoms::HTML],
+                            );
+                            this.stack.pop();
+                            this.insertion_mode = InsertionMode::InTableBody;
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. })
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::CAPTION,
+                                    atoms::COL,
+                                    atoms::COLGROUP,
+                                    atoms::TBODY,
+                                    atoms::TFOOT,
+                                    atoms::THEAD,
+                                    atoms::TR,
+                                ],
+                            ) =>
+                        {
+                            if !this.is_in_scope(dom, atoms::TR, Scope::Table) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.clear_stack_back_to(
+                                dom,
+                                &[atoms::TR, atoms::TEMPLATE, atoms::HTML],
+                            );
+                            this.stack.pop();
+                            this.insertion_mode = InsertionMode::InTableBody;
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::TABLE => {
+                            if !this.is_in_scope(dom, atoms::TR, Scope::Table) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.clear_stack_back_to(
+                                dom,
+                                &[atoms::TR, atoms::TEMPLATE, atoms::HTML],
+                            );
+                            this.stack.pop();
+                            this.insertion_mode = InsertionMode::InTableBody;
+                        }
+                        Some(Token::EndTag { name })
+                            if is_atom_in(name, &[atoms::TBODY, atoms::TFOOT, atoms::THEAD]) =>
+                        {
+                            if !this.is_in_scope(dom, name, Scope::Table) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            if !this.is_in_scope(dom, atoms::TR, Scope::Table) {
+                                break;
+                            }
+                            this.clear_stack_back_to(
+                                dom,
+                                &[atoms::TR, atoms::TEMPLATE, atoms::HTML],
+                            );
+                            this.stack.pop();
+                            this.insertion_mode = InsertionMode::InTableBody;
+                        }
+                        Some(Token::EndTag { name })
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::BODY,
+                                    atoms::CAPTION,
+                                    atoms::COL,
+                                    atoms::COLGROUP,
+                                    atoms::HTML,
+                                    atoms::TD,
+                                    atoms::TH,
+                                ],
+                            ) =>
+                        {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        _ => using_rules_for = Some(InsertionMode::InTable),
+                    },
+                    InsertionMode::InCell => match tok {
+                        Some(Token::EndTag { name })
+                            if is_atom_in(name, &[atoms::TD, atoms::TH]) =>
+                        {
+                            if !this.is_in_scope(dom, name, Scope::Table) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.generate_implied_end_tags(dom, None);
                             let top = *this.stack.last().unwrap();
-                            let mut top = dom.get_element_node_mut(top).unwrap();
-                            let element = top.append_child_element(name, attrs);
-                            this.stack.push(element);
+                            if !this.is_html_element_named(dom, top, name) {
+                                this.error(ParseError::UnexpectedToken);
+                            }
+                            this.close_until(dom, name);
+                            this.clear_active_formatting_elements_to_last_marker();
+                            this.insertion_mode = InsertionMode::InRow;
                             break;
                         }
+                        Some(Token::StartTag { name, .. })
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::CAPTION,
+                                    atoms::COL,
+                                    atoms::COLGROUP,
+                                    atoms::TBODY,
+                                    atoms::TD,
+                                    atoms::TFOOT,
+                                    atoms::TH,
+                                    atoms::THEAD,
+                                    atoms::TR,
+                                ],
+                            ) =>
+                        {
+                            if !this.is_in_scope(dom, atoms::TD, Scope::Table)
+                                && !this.is_in_scope(dom, atoms::TH, Scope::Table)
+                            {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.close_cell(dom);
+                        }
+                        Some(Token::EndTag { name })
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::BODY,
+                                    atoms::CAPTION,
+                                    atoms::COL,
+                                    atoms::COLGROUP,
+                                    atoms::HTML,
+                                ],
+                            ) =>
+                        {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::EndTag { name })
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::TABLE,
+                                    atoms::TBODY,
+                                    atoms::TFOOT,
+                                    atoms::THEAD,
+                                    atoms::TR,
+                                ],
+                            ) =>
+                        {
+                            if !this.is_in_scope(dom, name, Scope::Table) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.close_cell(dom);
+                        }
+                        _ => using_rules_for = Some(InsertionMode::InBody),
+                    },
+                    InsertionMode::InSelect => match tok {
+                        Some(Token::Char('\0')) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::Char(c)) => {
+                            this.append_text(dom, c);
+                            break;
+                        }
+                        Some(Token::Comment(data)) => {
+                            this.insert_comment(dom, data);
+                            break;
+                        }
+                        Some(Token::DocType { .. }) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. }) if name == atoms::HTML => {
+                            using_rules_for = Some(InsertionMode::InBody);
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::OPTION => {
+                            if this.is_current_node_in(dom, &[atoms::OPTION]) {
+                                this.stack.pop();
+                            }
+                            this.insert_element(dom, name, attrs);
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::OPTGROUP => {
+                            if this.is_current_node_in(dom, &[atoms::OPTION]) {
+                                this.stack.pop();
+                            }
+                            if this.is_current_node_in(dom, &[atoms::OPTGROUP]) {
+                                this.stack.pop();
+                            }
+                            this.insert_element(dom, name, attrs);
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::HR => {
+                            if this.is_current_node_in(dom, &[atoms::OPTION]) {
+                                this.stack.pop();
+                            }
+                            if this.is_current_node_in(dom, &[atoms::OPTGROUP]) {
+                                this.stack.pop();
+                            }
+                            this.insert_element(dom, name, attrs);
+                            this.stack.pop();
+                            break;
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::OPTGROUP => {
+                            if this.is_current_node_in(dom, &[atoms::OPTION]) {
+                                let parent = this.stack[this.stack.len() - 2];
+                                if this.is_html_element_in(dom, parent, &[atoms::OPTGROUP]) {
+                                    this.stack.pop();
+                                }
+                            }
+                            if !this.is_current_node_in(dom, &[atoms::OPTGROUP]) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.stack.pop();
+                            break;
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::OPTION => {
+                            if !this.is_current_node_in(dom, &[atoms::OPTION]) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.stack.pop();
+                            break;
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::SELECT => {
+                            if !this.is_in_scope(dom, atoms::SELECT, Scope::Select) {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.close_until(dom, name);
+                            this.reset_insertion_mode(dom);
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. }) if name == atoms::SELECT => {
+                            this.error(ParseError::UnexpectedToken);
+                            if !this.is_in_scope(dom, atoms::SELECT, Scope::Select) {
+                                break;
+                            }
+                            this.close_until(dom, name);
+                            this.reset_insertion_mode(dom);
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. })
+                            if is_atom_in(
+                                name,
+                                &[atoms::INPUT, atoms::KEYGEN, atoms::TEXTAREA],
+                            ) =>
+                        {
+                            this.error(ParseError::UnexpectedToken);
+                            if !this.is_in_scope(dom, atoms::SELECT, Scope::Select) {
+                                break;
+                            }
+                            this.close_until_one_of(dom, &[atoms::SELECT]);
+                            this.reset_insertion_mode(dom);
+                        }
+                        Some(Token::StartTag { name, .. })
+                            if is_atom_in(name, &[atoms::SCRIPT, atoms::TEMPLATE]) =>
+                        {
+                            using_rules_for = Some(InsertionMode::InHead);
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::TEMPLATE => {
+                            using_rules_for = Some(InsertionMode::InHead);
+                        }
+                        None => using_rules_for = Some(InsertionMode::InBody),
+                        _ => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                    },
+                    InsertionMode::InSelectInTable => match tok {
+                        Some(Token::StartTag { name, .. })
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::CAPTION,
+                                    atoms::TABLE,
+                                    atoms::TBODY,
+                                    atoms::TFOOT,
+                                    atoms::THEAD,
+                                    atoms::TR,
+                                    atoms::TD,
+                                    atoms::TH,
+                                ],
+                            ) =>
+                        {
+                            this.error(ParseError::UnexpectedToken);
+                            this.close_until_one_of(dom, &[atoms::SELECT]);
+                            this.reset_insertion_mode(dom);
+                        }
+                        Some(Token::EndTag { name })
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::CAPTION,
+                                    atoms::TABLE,
+                                    atoms::TBODY,
+                                    atoms::TFOOT,
+                                    atoms::THEAD,
+                                    atoms::TR,
+                                    atoms::TD,
+                                    atoms::TH,
+                                ],
+                            ) =>
+                        {
+                            this.error(ParseError::UnexpectedToken);
+                            if !this.is_in_scope(dom, name, Scope::Table) {
+                                break;
+                            }
+                            this.close_until_one_of(dom, &[atoms::SELECT]);
+                            this.reset_insertion_mode(dom);
+                        }
+                        _ => using_rules_for = Some(InsertionMode::InSelect),
+                    },
+                    InsertionMode::InTemplate => match tok {
+                        Some(Token::Char(_) | Token::Comment(_) | Token::DocType { .. }) => {
+                            using_rules_for = Some(InsertionMode::InBody);
+                        }
+                        Some(Token::StartTag { name, .. })
+                            if is_atom_in(
+                                name,
+                                &[
+                                    atoms::BASE,
+                                    atoms::BASEFONT,
+                                    atoms::BGSOUND,
+                                    atoms::LINK,
+                                    atoms::META,
+                                    atoms::NOFRAMES,
+                                    atoms::SCRIPT,
+                                    atoms::STYLE,
+                                    atoms::TEMPLATE,
+                                    atoms::TITLE,
+                                ],
+                            ) =>
+                        {
+                            using_rules_for = Some(InsertionMode::InHead);
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::TEMPLATE => {
+                            using_rules_for = Some(InsertionMode::InHead);
+                        }
+                        Some(Token::StartTag { name, .. }) => {
+                            // the first element decides what kind of content the template holds
+                            let mode = if is_atom_in(
+                                name,
+                                &[
+                                    atoms::CAPTION,
+                                    atoms::COLGROUP,
+                                    atoms::TBODY,
+                                    atoms::TFOOT,
+                                    atoms::THEAD,
+                                ],
+                            ) {
+                                InsertionMode::InTable
+                            } else if name == atoms::COL {
+                                InsertionMode::InColumnGroup
+                            } else if name == atoms::TR {
+                                InsertionMode::InTableBody
+                            } else if is_atom_in(name, &[atoms::TD, atoms::TH]) {
+                                InsertionMode::InRow
+                            } else {
+                                InsertionMode::InBody
+                            };
+                            this.template_insertion_modes.pop();
+                            this.template_insertion_modes.push(mode);
+                            this.insertion_mode = mode;
+                        }
                         Some(Token::EndTag { .. }) => {
-                            todo!()
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        None => {
+                            if !this.stack_contains(dom, &[atoms::TEMPLATE]) {
+                                return this.stop_parsing(dom);
+                            }
+                            this.error(ParseError::UnexpectedToken);
+                            this.close_until_one_of(dom, &[atoms::TEMPLATE]);
+                            this.clear_active_formatting_elements_to_last_marker();
+                            this.template_insertion_modes.pop();
+                            this.reset_insertion_mode(dom);
+                        }
+                    },
+                    InsertionMode::AfterBody => match tok {
+                        Some(Token::Char(c)) if "\t\n\x0C ".contains(c) => {
+                            this.append_text(dom, c);
+                            break;
+                        }
+                        Some(Token::DocType { .. }) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::Comment(data)) => {
+                            let mut html = dom.get_element_node_mut(this.stack[0]).unwrap();
+                            html.append_child_comment(data);
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::HTML => {
+                            this.error(ParseError::UnexpectedToken);
+                            if this.stack_contains(dom, &[atoms::TEMPLATE]) {
+                                break;
+                            }
+                            let html = this.stack[0];
+                            let mut html = dom.get_element_node_mut(html).unwrap();
+                            html.insert_missing_attrs(attrs);
+                            break;
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::HTML => {
+                            if this.context.is_some() {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.insertion_mode = InsertionMode::AfterAfterBody;
+                            break;
+                        }
+                        None => return this.stop_parsing(dom),
+                        _ => this.insertion_mode = InsertionMode::InBody,
+                    },
+                    InsertionMode::InFrameset => match tok {
+                        Some(Token::Char(c)) if "\t\n\x0C ".contains(c) => {
+                            this.append_text(dom, c);
+                            break;
+                        }
+                        Some(Token::Comment(data)) => {
+                            this.insert_comment(dom, data);
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. }) if name == atoms::HTML => {
+                            using_rules_for = Some(InsertionMode::InBody);
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::FRAMESET => {
+                            this.insert_element(dom, name, attrs);
+                            break;
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::FRAMESET => {
+                            if this.stack.len() == 1 {
+                                this.error(ParseError::UnexpectedToken);
+                                break;
+                            }
+                            this.stack.pop();
+                            if this.context.is_none()
+                                && !this.is_current_node_in(dom, &[atoms::FRAMESET])
+                            {
+                                this.insertion_mode = InsertionMode::AfterFrameset;
+                            }
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::FRAME => {
+                            let frame = this.insert_element(dom, name, attrs);
+                            this.stack.pop();
+                            if this.get_attr(dom, attrs, atoms::SRC).is_some() {
+                                return Poll::Ready(ParseEvent::Frame(frame));
+                            }
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. }) if name == atoms::NOFRAMES => {
+                            using_rules_for = Some(InsertionMode::InHead);
+                        }
+                        None => {
+                            if this.stack.len() != 1 {
+                                this.error(ParseError::UnexpectedToken);
+                            }
+                            return this.stop_parsing(dom);
+                        }
+                        _ => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
                         }
                     },
-                    InsertionMode::AfterBody => match tok {
+                    InsertionMode::AfterFrameset => match tok {
                         Some(Token::Char(c)) if "\t\n\x0C ".contains(c) => {
                             this.append_text(dom, c);
                             break;
                         }
-                        Some(Token::DocType | Token::Comment) => break,
-                        Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(dom, name, &["html"]) =>
-                        {
-                            if this.stack_contains(dom, &["template"]) {
-                                break;
-                            }
-                            // error: merge attrs
-                            let top = *this.stack.last().unwrap();
-                            let mut top = dom.get_element_node_mut(top).unwrap();
-                            top.insert_missing_attrs(attrs);
+                        Some(Token::Comment(data)) => {
+                            this.insert_comment(dom, data);
                             break;
                         }
-                        Some(Token::EndTag { name }) if this.is_str_in(dom, name, &["html"]) => {
-                            this.insertion_mode = InsertionMode::AfterAfterBody;
+                        Some(Token::StartTag { name, .. }) if name == atoms::HTML => {
+                            using_rules_for = Some(InsertionMode::InBody);
+                        }
+                        Some(Token::EndTag { name }) if name == atoms::HTML => {
+                            this.insertion_mode = InsertionMode::AfterAfterFrameset;
+                            break;
+                        }
+                        Some(Token::StartTag { name, .. }) if name == atoms::NOFRAMES => {
+                            using_rules_for = Some(InsertionMode::InHead);
+                        }
+                        None => return this.stop_parsing(dom),
+                        _ => {
+                            this.error(ParseError::UnexpectedToken);
                             break;
                         }
-                        None => return this.stop_parsing(),
-                        _ => this.insertion_mode = InsertionMode::InBody,
                     },
                     InsertionMode::AfterAfterBody => match tok {
                         Some(Token::Char(c)) if "\t\n\x0C ".contains(c) => {
                             this.append_text(dom, c);
                             break;
                         }
-                        Some(Token::DocType | Token::Comment) => break,
-                        Some(Token::StartTag { name, attrs, .. })
-                            if this.is_str_in(dom, name, &["html"]) =>
-                        {
-                            if this.stack_contains(dom, &["template"]) {
+                        Some(Token::DocType { .. }) => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                        Some(Token::Comment(data)) => {
+                            let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+                            root.append_child_comment(data);
+                            break;
+                        }
+                        Some(Token::StartTag { name, attrs, .. }) if name == atoms::HTML => {
+                            this.error(ParseError::UnexpectedToken);
+                            if this.stack_contains(dom, &[atoms::TEMPLATE]) {
                                 break;
                             }
-                            // error: merge attrs
-                            let top = *this.stack.last().unwrap();
-                            let mut top = dom.get_element_node_mut(top).unwrap();
-                            top.insert_missing_attrs(attrs);
+                            let html = this.stack[0];
+                            let mut html = dom.get_element_node_mut(html).unwrap();
+                            html.insert_missing_attrs(attrs);
                             break;
                         }
-                        None => return this.stop_parsing(),
+                        None => return this.stop_parsing(dom),
                         _ => this.insertion_mode = InsertionMode::InBody,
                     },
+                    InsertionMode::AfterAfterFrameset => match tok {
+                        Some(Token::Comment(data)) => {
+                            let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+                            root.append_child_comment(data);
+                            break;
+                        }
+                        Some(Token::DocType { .. } | Token::Char('\t' | '\n' | '\x0C' | ' ')) => {
+                            using_rules_for = Some(InsertionMode::InBody);
+                        }
+                        Some(Token::StartTag { name, .. }) if name == atoms::HTML => {
+                            using_rules_for = Some(InsertionMode::InBody);
+                        }
+                        Some(Token::StartTag { name, .. }) if name == atoms::NOFRAMES => {
+                            using_rules_for = Some(InsertionMode::InHead);
+                        }
+                        None => return this.stop_parsing(dom),
+                        _ => {
+                            this.error(ParseError::UnexpectedToken);
+                            break;
+                        }
+                    },
                     InsertionMode::Text => match tok {
                         Some(Token::Char(c)) => {
                             this.append_text(dom, c);
@@ -891,141 +3328,965 @@ impl<R: AsyncRead + Unpin> Parser<R> {
                             let top = this.stack.pop().unwrap();
                             this.insertion_mode = this.original_insertion_mode;
                             let element = dom.get_element_node(top).unwrap();
-                            if this.is_str_in(dom, element.name(), &["title"]) {
+                            if is_atom_in(element.name(), &[atoms::TITLE]) {
                                 return Poll::Ready(ParseEvent::Title(top));
                             }
-                            if this.is_str_in(dom, element.name(), &["style"]) {
+                            if is_atom_in(element.name(), &[atoms::STYLE]) {
                                 return Poll::Ready(ParseEvent::Style(top));
                             }
                         }
-                        Some(Token::EndTag { name }) if this.is_str_in(dom, name, &["script"]) => {
+                        Some(Token::EndTag { name }) if name == atoms::SCRIPT => {
                             this.stack.pop();
                             this.insertion_mode = this.original_insertion_mode;
+                            break;
                         }
                         Some(Token::EndTag { .. }) => {
                             let top = this.stack.pop().unwrap();
                             this.insertion_mode = this.original_insertion_mode;
                             let element = dom.get_element_node(top).unwrap();
-                            if this.is_str_in(dom, element.name(), &["title"]) {
+                            if is_atom_in(element.name(), &[atoms::TITLE]) {
                                 return Poll::Ready(ParseEvent::Title(top));
                             }
-                            if this.is_str_in(dom, element.name(), &["style"]) {
+                            if is_atom_in(element.name(), &[atoms::STYLE]) {
                                 return Poll::Ready(ParseEvent::Style(top));
                             }
+                            break;
                         }
                         _ => unreachable!(),
                     },
                 }
             }
+            this.foster_parenting = false;
+            if pop_head {
+                let head = this.head.unwrap();
+                this.stack.retain(|id| *id != head);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use smol::io::Cursor;
+
+    use super::*;
+    use crate::{asyncro, dom::NodeKind};
+
+    fn cx<'a>() -> Context<'a> {
+        Context::from_waker(asyncro::noop_waker_ref())
+    }
+
+    fn assert_done<R: AsyncRead + Unpin>(
+        cx: &mut Context<'_>,
+        parser: &mut Parser<R>,
+        dom: &mut Dom,
+    ) {
+        assert!(matches!(
+            Pin::new(parser).poll_next(cx, dom),
+            Poll::Ready(ParseEvent::Done)
+        ));
+    }
+
+    fn assert_error<R: AsyncRead + Unpin, L: Into<Location>>(
+        cx: &mut Context<'_>,
+        parser: &mut Parser<R>,
+        dom: &mut Dom,
+        loc: L,
+        err: ParseError,
+    ) {
+        let poll = Pin::new(parser).poll_next(cx, dom);
+        assert!(matches!(poll, Poll::Ready(ParseEvent::Error(_, _))));
+        if let Poll::Ready(ParseEvent::Error(location, error)) = poll {
+            assert_eq!(loc.into(), location);
+            assert_eq!(err, error);
+        }
+    }
+
+    fn assert_title<R: AsyncRead + Unpin>(
+        cx: &mut Context<'_>,
+        parser: &mut Parser<R>,
+        dom: &mut Dom,
+        title: &str,
+    ) {
+        let poll = Pin::new(parser).poll_next(cx, dom);
+        assert!(matches!(poll, Poll::Ready(ParseEvent::Title(_))));
+        if let Poll::Ready(ParseEvent::Title(id)) = poll {
+            let node = dom.get_element_node(id).unwrap();
+            let child = node.child_indices().start;
+            let text = dom.get_node_id_by_index(child).unwrap();
+            let text = dom.get_text_node(text).unwrap();
+            assert_eq!(title, text.text());
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::io;
+    fn child_ids(dom: &Dom, id: usize) -> Vec<usize> {
+        let element = dom.get_element_node(id).unwrap();
+        element
+            .child_indices()
+            .map(|index| dom.get_node_id_by_index(index).unwrap())
+            .collect()
+    }
+
+    fn assert_dom(dom: &Dom, repr: &str) {
+        let mut tree = io::Cursor::new(Vec::new());
+        dom.write_tree(&mut tree).unwrap();
+        let string = String::from_utf8(tree.into_inner()).unwrap();
+        assert_eq!(repr.trim_start(), string);
+    }
+
+    #[test]
+    fn empty() {
+        let reader = Cursor::new("");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+    <body>
+"#,
+        );
+    }
+
+    #[test]
+    fn text() {
+        let reader = Cursor::new("test");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+    <body>
+      <>test
+"#,
+        );
+    }
+
+    #[test]
+    fn title() {
+        let reader = Cursor::new("<title>test</title>");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_title(&mut cx, &mut parser, &mut dom, "test");
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+      <title>
+        <>test
+    <body>
+"#,
+        );
+    }
+
+    #[test]
+    fn script_and_style() {
+        let reader = Cursor::new(
+            "<script>a<b></script><style>p</style><body><xmp><i></xmp><plaintext></plaintext>",
+        );
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert!(matches!(
+            Pin::new(&mut parser).poll_next(&mut cx, &mut dom),
+            Poll::Ready(ParseEvent::Style(_))
+        ));
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+      <script>
+        <>a<b>
+      <style>
+        <>p
+    <body>
+      <xmp>
+        <><i>
+      <plaintext>
+        <></plaintext>
+"#,
+        );
+    }
+
+    #[test]
+    fn head_element_after_head() {
+        let reader = Cursor::new("<head></head><meta><noscript><link>");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 14],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+      <meta>
+    <body>
+      <noscript>
+        <link>
+"#,
+        );
+    }
+
+    #[test]
+    fn error_non_void_html_element_start_tag_with_trailing_solidus() {
+        let reader = Cursor::new("<link/><div/>");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 8],
+            ParseError::NonVoidHtmlElementStartTagWithTrailingSolidus,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+      <link>
+    <body>
+      <div>
+"#,
+        );
+    }
+    #[test]
+    fn table() {
+        let reader = Cursor::new(
+            "<table><caption>c</caption><col><tr><td>a<th>b</table><table><tbody><tr></table>",
+        );
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+    <body>
+      <table>
+        <caption>
+          <>c
+        <colgroup>
+          <col>
+        <tbody>
+          <tr>
+            <td>
+              <>a
+            <th>
+              <>b
+      <table>
+        <tbody>
+          <tr>
+"#,
+        );
+    }
+
+    #[test]
+    fn table_foster_parenting() {
+        let reader = Cursor::new("<table>a<tr> <div>b</table>");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 9],
+            ParseError::UnexpectedToken,
+        );
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 14],
+            ParseError::UnexpectedToken,
+        );
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 19],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+    <body>
+      <>a
+      <div>
+        <>b
+      <table>
+        <tbody>
+          <tr>
+            <> 
+"#,
+        );
+    }
+    #[test]
+    fn adoption_agency() {
+        let reader = Cursor::new("<b>1<p>2</b>3</p>");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 9],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+    <body>
+      <b>
+        <>1
+      <p>
+        <b>
+          <>2
+        <>3
+"#,
+        );
+    }
+
+    #[test]
+    fn reconstruct_active_formatting_elements() {
+        let reader = Cursor::new("<p><i><b>1</p>2<a>3<a>4");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 11],
+            ParseError::UnexpectedToken,
+        );
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 20],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+    <body>
+      <p>
+        <i>
+          <b>
+            <>1
+      <i>
+        <b>
+          <>2
+          <a>
+            <>3
+          <a>
+            <>4
+"#,
+        );
+    }
+    #[test]
+    fn textarea() {
+        let reader = Cursor::new("<textarea>\n<b>x\n</textarea>");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+    <body>
+      <textarea>
+        <><b>x
 
-    use smol::io::Cursor;
+"#,
+        );
+    }
 
-    use super::*;
-    use crate::asyncro;
+    #[test]
+    fn select_in_table() {
+        let reader = Cursor::new("<table><tr><td><select><option>a<b>b<td>c</table>");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 33],
+            ParseError::UnexpectedToken,
+        );
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 37],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+    <body>
+      <table>
+        <tbody>
+          <tr>
+            <td>
+              <select>
+                <option>
+                  <>ab
+            <td>
+              <>c
+"#,
+        );
+    }
 
-    fn cx<'a>() -> Context<'a> {
-        Context::from_waker(asyncro::noop_waker_ref())
+    #[test]
+    fn form_owner() {
+        let reader = Cursor::new("<form><input><select form=x></select></form><input>");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        let html = child_ids(&dom, ROOT_NODE_ID)[0];
+        let body = child_ids(&dom, html)[1];
+        let [form, input] = child_ids(&dom, body)[..] else {
+            panic!()
+        };
+        let [form_input, select] = child_ids(&dom, form)[..] else {
+            panic!()
+        };
+        assert_eq!(Some(form), dom.get_form_owner(form_input));
+        assert_eq!(None, dom.get_form_owner(select));
+        assert_eq!(None, dom.get_form_owner(input));
+    }
+    #[test]
+    fn template() {
+        let reader = Cursor::new("<template><td>a</template><p>b");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        // the contents aren't part of the tree
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+      <template>
+    <body>
+      <p>
+        <>b
+"#,
+        );
+        let html = child_ids(&dom, ROOT_NODE_ID)[0];
+        let head = child_ids(&dom, html)[0];
+        let template = child_ids(&dom, head)[0];
+        let contents = dom.get_template_contents(template).unwrap();
+        assert_eq!(
+            Some(NodeKind::DocumentFragment),
+            dom.get_node_kind(contents)
+        );
+        let [td] = child_ids(&dom, contents)[..] else {
+            panic!()
+        };
+        let [a] = child_ids(&dom, td)[..] else {
+            panic!()
+        };
+        let td = dom.get_element_node(td).unwrap();
+        assert_eq!("td", dom.get_str(td.name()).unwrap());
+        assert_eq!("a", dom.get_text_node(a).unwrap().text());
     }
 
-    fn assert_done<R: AsyncRead + Unpin>(
-        cx: &mut Context<'_>,
-        parser: &mut Parser<R>,
-        dom: &mut Dom,
-    ) {
-        assert!(matches!(
-            Pin::new(parser).poll_next(cx, dom),
-            Poll::Ready(ParseEvent::Done)
-        ));
+    #[test]
+    fn foreign_content() {
+        let reader = Cursor::new(
+            r#"<svg viewbox="0 0 1 1"><foreignobject><b>x</b></foreignobject><path d="M"/></svg><math><mi>y</mi></math>"#,
+        );
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+    <body>
+      <svg viewBox="0 0 1 1">
+        <foreignObject>
+          <b>
+            <>x
+        <path d="M">
+      <math>
+        <mi>
+          <>y
+"#,
+        );
+        let html = child_ids(&dom, ROOT_NODE_ID)[0];
+        let body = child_ids(&dom, html)[1];
+        let [svg, math] = child_ids(&dom, body)[..] else {
+            panic!()
+        };
+        let [foreign_object, path] = child_ids(&dom, svg)[..] else {
+            panic!()
+        };
+        let b = child_ids(&dom, foreign_object)[0];
+        let mi = child_ids(&dom, math)[0];
+        let namespace = |id| dom.get_element_node(id).unwrap().namespace();
+        assert_eq!(Namespace::Svg, namespace(svg));
+        assert_eq!(Namespace::Svg, namespace(foreign_object));
+        assert_eq!(Namespace::Html, namespace(b));
+        assert_eq!(Namespace::Svg, namespace(path));
+        assert_eq!(Namespace::MathMl, namespace(math));
+        assert_eq!(Namespace::MathMl, namespace(mi));
     }
 
-    fn assert_title<R: AsyncRead + Unpin>(
-        cx: &mut Context<'_>,
-        parser: &mut Parser<R>,
-        dom: &mut Dom,
-        title: &str,
-    ) {
-        let poll = Pin::new(parser).poll_next(cx, dom);
-        assert!(matches!(poll, Poll::Ready(ParseEvent::Title(_))));
-        if let Poll::Ready(ParseEvent::Title(id)) = poll {
-            let node = dom.get_element_node(id).unwrap();
-            let child = node.child_indices().start;
-            let text = dom.get_node_id_by_index(child).unwrap();
-            let text = dom.get_text_node(text).unwrap();
-            assert_eq!(title, text.text());
-        }
+    #[test]
+    fn foreign_content_breakout() {
+        let reader = Cursor::new("<svg><g><p>x</g>y");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 9],
+            ParseError::UnexpectedToken,
+        );
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 13],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+    <body>
+      <svg>
+        <g>
+      <p>
+        <>xy
+"#,
+        );
     }
 
-    fn assert_dom(dom: &Dom, repr: &str) {
-        let mut tree = io::Cursor::new(Vec::new());
-        dom.write_tree(&mut tree).unwrap();
-        let string = String::from_utf8(tree.into_inner()).unwrap();
-        assert_eq!(repr.trim_start(), string);
+    #[test]
+    fn frameset() {
+        let reader = Cursor::new(r#"<frameset><frame src="a.html"><frame></frameset>"#);
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
+        // only frames with a source are reported
+        let poll = Pin::new(&mut parser).poll_next(&mut cx, &mut dom);
+        let Poll::Ready(ParseEvent::Frame(frame)) = poll else {
+            panic!()
+        };
+        let frame = dom.get_element_node(frame).unwrap();
+        let [[name, value]] = frame.attrs() else {
+            panic!()
+        };
+        assert_eq!("src", dom.get_str(*name).unwrap());
+        assert_eq!("a.html", dom.get_str(*value).unwrap());
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <html>
+    <head>
+    <frameset>
+      <frame src="a.html">
+      <frame>
+"#,
+        );
     }
 
     #[test]
-    fn empty() {
-        let reader = Cursor::new("");
+    fn quirks_mode() {
+        let reader = Cursor::new(
+            r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN"><p><table>"#,
+        );
         let reader = AsyncStrReader::new(reader);
         let mut dom = Dom::new();
         let mut cx = cx();
         let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 1],
+            ParseError::UnexpectedToken,
+        );
         assert_done(&mut cx, &mut parser, &mut dom);
+        assert_eq!(QuirksMode::Quirks, dom.quirks_mode());
+        // the table doesn't close the paragraph
         assert_dom(
             &dom,
             r#"
 <>
+  <!DOCTYPE html>
   <html>
     <head>
     <body>
+      <p>
+        <table>
 "#,
         );
     }
 
     #[test]
-    fn text() {
-        let reader = Cursor::new("test");
+    fn no_quirks_mode() {
+        let reader = Cursor::new("<!DOCTYPE html><p><table>");
         let reader = AsyncStrReader::new(reader);
         let mut dom = Dom::new();
         let mut cx = cx();
         let mut parser = Parser::new(reader);
         assert_done(&mut cx, &mut parser, &mut dom);
+        assert_eq!(QuirksMode::NoQuirks, dom.quirks_mode());
         assert_dom(
             &dom,
             r#"
 <>
+  <!DOCTYPE html>
   <html>
     <head>
     <body>
-      <>test
+      <p>
+      <table>
 "#,
         );
     }
 
     #[test]
-    fn title() {
-        let reader = Cursor::new("<title>test</title>");
+    fn comments() {
+        let reader = Cursor::new("<!--a--><!DOCTYPE html><!--b--><p><!--c--></p></html><!--d-->");
         let reader = AsyncStrReader::new(reader);
         let mut dom = Dom::new();
         let mut cx = cx();
         let mut parser = Parser::new(reader);
-        assert_title(&mut cx, &mut parser, &mut dom, "test");
         assert_done(&mut cx, &mut parser, &mut dom);
         assert_dom(
             &dom,
             r#"
 <>
+  <!--a-->
+  <!DOCTYPE html>
+  <!--b-->
+  <html>
+    <head>
+    <body>
+      <p>
+        <!--c-->
+  <!--d-->
+"#,
+        );
+    }
+
+    #[test]
+    fn fragment() {
+        let mut dom = Dom::new();
+        let tr = dom.insert_str("tr");
+        let mut root = dom.get_element_node_mut(ROOT_NODE_ID).unwrap();
+        let tr = root.append_child_element(Namespace::Html, tr, EMPTY_RANGE_INDEX);
+        let reader = Cursor::new("<td>a<td>b</tr>");
+        let reader = AsyncStrReader::new(reader);
+        let mut cx = cx();
+        let mut parser = Parser::new_fragment(reader, &mut dom, Namespace::Html, "tr", tr);
+        // the context element is never closed by the fragment
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 11],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        // nothing of the made up context is left behind
+        assert!(dom.get_element_node(parser.context.unwrap()).is_none());
+        dom.compact();
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <tr>
+    <td>
+      <>a
+    <td>
+      <>b
+"#,
+        );
+    }
+
+    #[test]
+    fn fragment_fatal() {
+        let mut dom = Dom::new();
+        // invalid utf-8 fails before anything is parsed
+        let reader = Cursor::new(b"<b>\xFF".to_vec());
+        let reader = AsyncStrReader::new(reader);
+        let mut cx = cx();
+        let mut parser =
+            Parser::new_fragment(reader, &mut dom, Namespace::Html, "div", ROOT_NODE_ID);
+        assert!(matches!(
+            Pin::new(&mut parser).poll_next(&mut cx, &mut dom),
+            Poll::Ready(ParseEvent::Fatal(..))
+        ));
+        // the made up root and context are dropped on errors too
+        assert!(dom.get_element_node(parser.context.unwrap()).is_none());
+        assert!(dom.get_element_node(parser.stack[0]).is_none());
+        dom.compact();
+        assert_dom(
+            &dom,
+            r#"
+<>
+"#,
+        );
+    }
+
+    #[test]
+    fn fragment_rcdata() {
+        let mut dom = Dom::new();
+        let reader = Cursor::new("<b>a</b>");
+        let reader = AsyncStrReader::new(reader);
+        let mut cx = cx();
+        let mut parser =
+            Parser::new_fragment(reader, &mut dom, Namespace::Html, "textarea", ROOT_NODE_ID);
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <><b>a</b>
+"#,
+        );
+    }
+
+    #[test]
+    fn list_items() {
+        let reader = Cursor::new("<!DOCTYPE html><ul><li>a<li>b</ul><dl><dt>c<dd>d</dl><h1>e</h2>");
+        let reader = AsyncStrReader::new(reader);
+        let mut dom = Dom::new();
+        let mut cx = cx();
+        let mut parser = Parser::new(reader);
+        assert_error(
+            &mut cx,
+            &mut parser,
+            &mut dom,
+            [1, 59],
+            ParseError::UnexpectedToken,
+        );
+        assert_done(&mut cx, &mut parser, &mut dom);
+        assert_dom(
+            &dom,
+            r#"
+<>
+  <!DOCTYPE html>
   <html>
     <head>
-      <title>
-        <>test
     <body>
+      <ul>
+        <li>
+          <>a
+        <li>
+          <>b
+      <dl>
+        <dt>
+          <>c
+        <dd>
+          <>d
+      <h1>
+        <>e
 "#,
         );
     }
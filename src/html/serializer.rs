@@ -0,0 +1,180 @@
+// Writes the children of a node back out as HTML, the parser reads the
+// output back into the same tree for documents it built itself.
+// https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments
+use std::io::{self, Write};
+
+use crate::{
+    atoms,
+    dom::{Dom, Namespace, NodeKind},
+};
+
+// elements without an end tag or children
+static VOID: &[usize] = &[
+    atoms::AREA,
+    atoms::BASE,
+    atoms::BASEFONT,
+    atoms::BGSOUND,
+    atoms::BR,
+    atoms::COL,
+    atoms::EMBED,
+    atoms::FRAME,
+    atoms::HR,
+    atoms::IMG,
+    atoms::INPUT,
+    atoms::KEYGEN,
+    atoms::LINK,
+    atoms::META,
+    atoms::PARAM,
+    atoms::SOURCE,
+    atoms::TRACK,
+    atoms::WBR,
+];
+
+// elements whose text is written without escaping
+static RAW_TEXT: &[usize] = &[
+    atoms::IFRAME,
+    atoms::NOEMBED,
+    atoms::NOFRAMES,
+    atoms::PLAINTEXT,
+    atoms::SCRIPT,
+    atoms::STYLE,
+    atoms::XMP,
+];
+
+/// Writes the children of the node with id `id` as HTML, templates write
+/// their contents instead
+pub fn serialize(dom: &Dom, id: usize, writer: &mut dyn Write) -> io::Result<()> {
+    let id = dom.get_template_contents(id).unwrap_or(id);
+    let Some(parent) = dom.get_element_node(id) else {
+        return Ok(());
+    };
+    let raw = parent.namespace() == Namespace::Html && RAW_TEXT.contains(&parent.name());
+    let mut child = parent.first_child();
+    while let Some(id) = child {
+        match dom.get_node_kind(id).unwrap() {
+            NodeKind::Element => {
+                let element = dom.get_element_node(id).unwrap();
+                let name = dom.get_str(element.name()).unwrap();
+                write!(writer, "<{name}")?;
+                // namespaced attribute names are stored with their prefix
+                for &[name, value] in element.attrs() {
+                    write!(writer, " {}=\"", dom.get_str(name).unwrap())?;
+                    escape(dom.get_str(value).unwrap(), true, writer)?;
+                    write!(writer, "\"")?;
+                }
+                write!(writer, ">")?;
+                if element.namespace() != Namespace::Html || !VOID.contains(&element.name()) {
+                    serialize(dom, id, writer)?;
+                    write!(writer, "</{name}>")?;
+                }
+            }
+            NodeKind::Text => {
+                let text = dom.get_text_node(id).unwrap().text();
+                if raw {
+                    write!(writer, "{text}")?;
+                } else {
+                    escape(text, false, writer)?;
+                }
+            }
+            NodeKind::Comment => {
+                let comment = dom.get_comment_node(id).unwrap();
+                write!(writer, "<!--{}-->", comment.data())?;
+            }
+            NodeKind::ProcessingInstruction => {
+                let pi = dom.get_processing_instruction_node(id).unwrap();
+                write!(writer, "<?{} {}>", pi.target(), pi.data())?;
+            }
+            NodeKind::DocType => {
+                let doctype = dom.get_doctype_node(id).unwrap();
+                write!(writer, "<!DOCTYPE {}>", doctype.name())?;
+            }
+            NodeKind::Document | NodeKind::DocumentFragment => unreachable!(),
+        }
+        child = dom.get_next_sibling_id(id);
+    }
+    Ok(())
+}
+
+fn escape(text: &str, attribute: bool, writer: &mut dyn Write) -> io::Result<()> {
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        let escaped = match c {
+            '&' => "&amp;",
+            '\u{A0}' => "&nbsp;",
+            '<' => "&lt;",
+            '>' => "&gt;",
+            '"' if attribute => "&quot;",
+            _ => continue,
+        };
+        writer.write_all(&text.as_bytes()[start..i])?;
+        writer.write_all(escaped.as_bytes())?;
+        start = i + c.len_utf8();
+    }
+    writer.write_all(&text.as_bytes()[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use smol::io::Cursor;
+
+    use super::*;
+    use crate::{
+        asyncro,
+        dom::ROOT_NODE_ID,
+        html::{ParseEvent, Parser},
+        io::AsyncStrReader,
+    };
+
+    fn round_trip(html: &str) -> String {
+        let mut dom = Dom::new();
+        let mut cx = Context::from_waker(asyncro::noop_waker_ref());
+        let mut parser = Parser::new(AsyncStrReader::new(Cursor::new(html.to_string())));
+        while !matches!(
+            Pin::new(&mut parser).poll_next(&mut cx, &mut dom),
+            Poll::Ready(ParseEvent::Done)
+        ) {}
+        let mut out = Vec::new();
+        serialize(&dom, ROOT_NODE_ID, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn document() {
+        assert_eq!(
+            "<!DOCTYPE html><!--a--><html><head><title>a&amp;b</title></head>\
+             <body><p class=\"&quot;x&quot;\">1&lt;2<br>&nbsp;</p></body></html>",
+            round_trip(
+                "<!DOCTYPE html><!--a--><title>a&amp;b</title>\
+                 <p class='\"x\"'>1&lt;2<br>&nbsp;"
+            )
+        );
+    }
+
+    #[test]
+    fn raw_text() {
+        assert_eq!(
+            "<html><head><script>a<b && c</script><style>p > a {}</style></head>\
+             <body></body></html>",
+            round_trip("<script>a<b && c</script><style>p > a {}</style>")
+        );
+    }
+
+    #[test]
+    fn foreign_and_template() {
+        assert_eq!(
+            "<html><head><template><td>a</td></template></head><body>\
+             <svg viewBox=\"0 0 1 1\"><path></path><foreignObject><br></foreignObject></svg>\
+             <a xlink:href=\"x\"></a></body></html>",
+            round_trip(
+                "<template><td>a</template>\
+                 <svg viewbox='0 0 1 1'><path/><foreignobject><br></foreignobject></svg>\
+                 <a xlink:href=x></a>"
+            )
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::VecDeque,
     io,
     pin::Pin,
     str,
@@ -7,6 +8,10 @@ use std::{
 
 use smol::io::AsyncRead;
 
+use super::{
+    entities::{self, NAMED_CHAR_REFS},
+    ParseError,
+};
 use crate::io::{AsyncStrError, AsyncStrReader, Location, NewlineNormalizable};
 
 #[derive(thiserror::Error, Debug)]
@@ -38,10 +43,16 @@ pub enum Token {
     EndTag {
         name: usize,
     },
-    DocType,
-    Comment,
+    DocType {
+        name: Option<usize>,
+        public_id: Option<usize>,
+        system_id: Option<usize>,
+        force_quirks: bool,
+    },
+    Comment(usize),
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum State {
     Data,
 
@@ -58,11 +69,79 @@ pub enum State {
     AttributeValueNoQuote,
     AfterAttributeValueQuoted,
 
+    MarkupDeclarationOpen,
+    CommentStart,
+    CommentStartDash,
+    Comment,
+    CommentLessThan,
+    CommentLessThanBang,
+    CommentLessThanBangDash,
+    CommentLessThanBangDashDash,
+    CommentEndDash,
+    CommentEnd,
+    CommentEndBang,
     BogusComment,
+
+    CDataSection,
+    CDataSectionBracket,
+    CDataSectionEnd,
+
+    DocType,
+    BeforeDocTypeName,
+    DocTypeName,
+    AfterDocTypeName,
+    AfterDocTypePublicKeyword,
+    BeforeDocTypePublicIdentifier,
+    DocTypePublicIdentifierDoubleQuote,
+    DocTypePublicIdentifierSingleQuote,
+    AfterDocTypePublicIdentifier,
+    BetweenDocTypePublicAndSystemIdentifiers,
+    AfterDocTypeSystemKeyword,
+    BeforeDocTypeSystemIdentifier,
+    DocTypeSystemIdentifierDoubleQuote,
+    DocTypeSystemIdentifierSingleQuote,
+    AfterDocTypeSystemIdentifier,
+    BogusDocType,
+
     RcData,
     RcDataLessThan,
     RcDataEndTagOpen,
     RcDataEndTagName,
+
+    RawText,
+    RawTextLessThan,
+    RawTextEndTagOpen,
+    RawTextEndTagName,
+
+    ScriptData,
+    ScriptDataLessThan,
+    ScriptDataEndTagOpen,
+    ScriptDataEndTagName,
+    ScriptDataEscapeStart,
+    ScriptDataEscapeStartDash,
+    ScriptDataEscaped,
+    ScriptDataEscapedDash,
+    ScriptDataEscapedDashDash,
+    ScriptDataEscapedLessThan,
+    ScriptDataEscapedEndTagOpen,
+    ScriptDataEscapedEndTagName,
+    ScriptDataDoubleEscapeStart,
+    ScriptDataDoubleEscaped,
+    ScriptDataDoubleEscapedDash,
+    ScriptDataDoubleEscapedDashDash,
+    ScriptDataDoubleEscapedLessThan,
+    ScriptDataDoubleEscapeEnd,
+
+    PlainText,
+
+    CharacterReference,
+    NamedCharacterReference,
+    AmbiguousAmpersand,
+    NumericCharacterReference,
+    HexadecimalCharacterReferenceStart,
+    DecimalCharacterReferenceStart,
+    HexadecimalCharacterReference,
+    DecimalCharacterReference,
 }
 
 pub trait Interner {
@@ -74,12 +153,18 @@ pub trait Interner {
 struct TokenizerInner {
     consumed: usize,
     state: State,
+    return_state: State,
     str_buf: String,
     attr_buf: Vec<[usize; 2]>,
     temp_buffer: Vec<(Location, char)>,
     last_start_tag_emitted_name: Option<usize>,
+    char_ref_name: String,
+    char_ref_match: Option<(usize, usize)>,
+    char_ref_code: u32,
     synthetic_toks: Vec<(Location, Token)>,
+    errors: VecDeque<(Location, ParseError)>,
     force_eof: bool,
+    cdata_allowed: bool,
     tok: Token,
     start_loc: Location,
     loc: Location,
@@ -98,13 +183,19 @@ impl<R> Tokenizer<R> {
         let inner = TokenizerInner {
             consumed: 0,
             state: State::Data,
+            return_state: State::Data,
             str_buf: String::new(),
             attr_buf: Vec::new(),
             temp_buffer: Vec::new(),
             last_start_tag_emitted_name: None,
+            char_ref_name: String::new(),
+            char_ref_match: None,
+            char_ref_code: 0,
             synthetic_toks: Vec::new(),
+            errors: VecDeque::new(),
             force_eof: false,
-            tok: Token::Comment,
+            cdata_allowed: false,
+            tok: Token::Char('\x00'),
             start_loc: Location { line: 1, column: 1 },
             loc: Location { line: 1, column: 0 },
         };
@@ -114,6 +205,23 @@ impl<R> Tokenizer<R> {
     pub fn set_state(&mut self, state: State) {
         self.inner.state = state;
     }
+
+    #[cfg(test)]
+    pub fn set_last_start_tag(&mut self, name: Option<usize>) {
+        self.inner.last_start_tag_emitted_name = name;
+    }
+
+    /// Drains the parse errors found so far, they belong before the
+    /// last token returned by `poll_next`
+    pub fn take_errors(&mut self) -> impl Iterator<Item = (Location, ParseError)> + '_ {
+        self.inner.errors.drain(..)
+    }
+
+    /// CDATA sections are only recognized when the adjusted current node is
+    /// not an element in the HTML namespace, otherwise they are bogus comments
+    pub fn set_cdata_allowed(&mut self, allowed: bool) {
+        self.inner.cdata_allowed = allowed;
+    }
 }
 
 type TokenzizerItem = (Location, Result<Token, TokenizerError>);
@@ -127,6 +235,20 @@ impl TokenizerInner {
         self.token(self.loc, tok)
     }
 
+    fn error(&mut self, err: ParseError) {
+        self.errors.push_back((self.loc, err));
+    }
+
+    /// Checks the name of the attribute that was just finished against the ones before it
+    fn finish_attr_name<I: Interner>(&mut self, int: &mut I) {
+        let name = int.intern_str(&self.str_buf);
+        let previous = self.attr_buf.len() - 1;
+        if self.attr_buf[..previous].iter().any(|attr| attr[0] == name) {
+            self.error(ParseError::DuplicateAttribute);
+        }
+        self.attr_buf.last_mut().unwrap()[0] = name;
+    }
+
     fn set_tag_name_if_unset<I: Interner>(&mut self, int: &mut I) {
         self.tok = match self.tok {
             Token::StartTag {
@@ -150,6 +272,16 @@ impl TokenizerInner {
     }
 
     fn set_tag_attrs_if_unset<I: Interner>(&mut self, int: &mut I) {
+        // only the first of any duplicate attributes is kept
+        let mut i = 0;
+        while i < self.attr_buf.len() {
+            let name = self.attr_buf[i][0];
+            if self.attr_buf[..i].iter().any(|attr| attr[0] == name) {
+                self.attr_buf.remove(i);
+            } else {
+                i += 1;
+            }
+        }
         self.tok = match self.tok {
             Token::StartTag {
                 name,
@@ -163,10 +295,176 @@ impl TokenizerInner {
                     self_closing,
                 }
             }
+            Token::EndTag { .. } if !self.attr_buf.is_empty() => {
+                self.error(ParseError::EndTagWithAttributes);
+                self.tok
+            }
             tok => tok,
         }
     }
 
+    fn emit_comment<I: Interner>(&mut self, int: &mut I) -> Poll<Option<TokenzizerItem>> {
+        let data = int.intern_str(&self.str_buf);
+        self.token(self.start_loc, Token::Comment(data))
+    }
+
+    fn new_doctype(&mut self, force_quirks: bool) {
+        self.tok = Token::DocType {
+            name: None,
+            public_id: None,
+            system_id: None,
+            force_quirks,
+        };
+    }
+
+    fn set_doctype_force_quirks(&mut self) {
+        if let Token::DocType { force_quirks, .. } = &mut self.tok {
+            *force_quirks = true;
+        }
+    }
+
+    /// Interns `str_buf` into whichever part of the DOCTYPE the current state is building
+    fn set_doctype_part<I: Interner>(&mut self, int: &mut I) {
+        if let Token::DocType {
+            name,
+            public_id,
+            system_id,
+            ..
+        } = &mut self.tok
+        {
+            let part = match self.state {
+                State::DocTypeName => name,
+                State::DocTypePublicIdentifierDoubleQuote
+                | State::DocTypePublicIdentifierSingleQuote => public_id,
+                State::DocTypeSystemIdentifierDoubleQuote
+                | State::DocTypeSystemIdentifierSingleQuote => system_id,
+                _ => return,
+            };
+            *part = Some(int.intern_str(&self.str_buf));
+        }
+    }
+
+    /// Checks the chars matched so far in `temp_buffer` followed by `c` against
+    /// `keyword`. Returns `Some(true)` on a full match, `Some(false)` while still a prefix
+    fn match_keyword(&self, c: char, keyword: &str, ignore_case: bool) -> Option<bool> {
+        let len = self.temp_buffer.len() + 1;
+        if len > keyword.len() {
+            return None;
+        }
+        let matched = self.temp_buffer.iter().map(|(_, c)| *c).chain(Some(c));
+        for (lhs, rhs) in matched.zip(keyword.chars()) {
+            if !(lhs == rhs || (ignore_case && lhs.eq_ignore_ascii_case(&rhs))) {
+                return None;
+            }
+        }
+        Some(len == keyword.len())
+    }
+
+    fn is_appropriate_end_tag(&self) -> bool {
+        matches!(self.tok, Token::EndTag { name } if Some(name) == self.last_start_tag_emitted_name)
+    }
+
+    /// The text state to go back to when a would-be end tag turns out to just be text
+    fn end_tag_text_state(&self) -> State {
+        match self.state {
+            State::RawTextLessThan | State::RawTextEndTagOpen | State::RawTextEndTagName => {
+                State::RawText
+            }
+            State::ScriptDataLessThan
+            | State::ScriptDataEndTagOpen
+            | State::ScriptDataEndTagName => State::ScriptData,
+            State::ScriptDataEscapedLessThan
+            | State::ScriptDataEscapedEndTagOpen
+            | State::ScriptDataEscapedEndTagName => State::ScriptDataEscaped,
+            _ => State::RcData,
+        }
+    }
+
+    /// Emits the `</` and any buffered name chars of what was not an appropriate end tag
+    fn emit_end_tag_as_text(&mut self) -> Poll<Option<TokenzizerItem>> {
+        for (loc, c) in self.temp_buffer.drain(..).rev() {
+            self.synthetic_toks.push((loc, Token::Char(c)));
+        }
+        let solidus = Location {
+            column: self.start_loc.column + 1,
+            ..self.start_loc
+        };
+        self.synthetic_toks.push((solidus, Token::Char('/')));
+        self.state = self.end_tag_text_state();
+        self.token(self.start_loc, Token::Char('<'))
+    }
+
+    fn begin_char_ref(&mut self) {
+        self.return_state = self.state;
+        self.temp_buffer.clear();
+        self.temp_buffer.push((self.loc, '&'));
+        self.state = State::CharacterReference;
+    }
+
+    fn is_char_ref_in_attr(&self) -> bool {
+        matches!(
+            self.return_state,
+            State::AttributeValueDoubleQuote
+                | State::AttributeValueSingleQuote
+                | State::AttributeValueNoQuote
+        )
+    }
+
+    /// Flushes the code points in the temp buffer into the current attribute value,
+    /// or emits them as char tokens when the reference was not in an attribute
+    fn flush_char_ref(&mut self) -> Option<Poll<Option<TokenzizerItem>>> {
+        if self.is_char_ref_in_attr() {
+            for (_, c) in self.temp_buffer.drain(..) {
+                self.str_buf.push(c);
+            }
+            return None;
+        }
+        for (loc, c) in self.temp_buffer.drain(1..).rev() {
+            self.synthetic_toks.push((loc, Token::Char(c)));
+        }
+        self.temp_buffer
+            .pop()
+            .map(|(loc, c)| self.token(loc, Token::Char(c)))
+    }
+
+    fn end_numeric_char_ref(&mut self) -> Option<Poll<Option<TokenzizerItem>>> {
+        let value = self.char_ref_code_to_char();
+        let (loc, _) = self.temp_buffer[0];
+        self.temp_buffer.clear();
+        self.temp_buffer.push((loc, value));
+        self.state = self.return_state;
+        self.flush_char_ref()
+    }
+
+    fn char_ref_code_to_char(&mut self) -> char {
+        match self.char_ref_code {
+            0 => {
+                self.error(ParseError::NullCharacterReference);
+                char::REPLACEMENT_CHARACTER
+            }
+            0x110000.. => {
+                self.error(ParseError::CharacterReferenceOutsideUnicodeRange);
+                char::REPLACEMENT_CHARACTER
+            }
+            0xD800..=0xDFFF => {
+                self.error(ParseError::SurrogateCharacterReference);
+                char::REPLACEMENT_CHARACTER
+            }
+            code => {
+                if entities::is_noncharacter(code) {
+                    self.error(ParseError::NoncharacterCharacterReference);
+                }
+                if (code == 0x0D)
+                    || (entities::is_control(code) && !matches!(code, 0x09 | 0x0A | 0x0C))
+                {
+                    self.error(ParseError::ControlCharacterReference);
+                }
+                let code = entities::replace_c1_control(code);
+                char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+        }
+    }
+
     fn consume<C: Iterator<Item = (usize, char)>>(&mut self, chars: &mut C) {
         match chars.next() {
             Some((len, '\n')) => {
@@ -174,9 +472,17 @@ impl TokenizerInner {
                 self.loc.column = 1;
                 self.consumed += len;
             }
-            Some((len, _)) => {
+            Some((_, c)) => {
                 self.loc.column += 1;
-                self.consumed += len;
+                // the reader counts bytes, not chars
+                self.consumed += c.len_utf8();
+                let code = c as u32;
+                if entities::is_noncharacter(code) {
+                    self.error(ParseError::NoncharacterInInputStream);
+                } else if entities::is_control(code) && !matches!(c, '\x00' | '\t' | '\x0C' | '\r')
+                {
+                    self.error(ParseError::ControlCharacterInInputStream);
+                }
             }
             _ => {}
         }
@@ -193,15 +499,18 @@ impl TokenizerInner {
             }
             match self.state {
                 State::Data => match c {
-                    Some('&') => todo!("char reference state"),
+                    Some('&') => {
+                        self.consume(&mut chars);
+                        self.begin_char_ref();
+                    }
                     Some('<') => {
                         self.consume(&mut chars);
                         self.state = State::TagOpen;
                         self.start_loc = self.loc;
                     }
                     Some('\x00') => {
-                        // error: unexpected-null-character
                         self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
                         return self.token_here(Token::Char('\x00'));
                     }
                     Some(c) => {
@@ -211,7 +520,11 @@ impl TokenizerInner {
                     None => return Poll::Ready(None),
                 },
                 State::TagOpen => match c {
-                    Some('!') => todo!("markup decl state"),
+                    Some('!') => {
+                        self.consume(&mut chars);
+                        self.temp_buffer.clear();
+                        self.state = State::MarkupDeclarationOpen;
+                    }
                     Some('/') => {
                         self.consume(&mut chars);
                         self.state = State::EndTagOpen;
@@ -226,16 +539,17 @@ impl TokenizerInner {
                         self.state = State::TagName;
                     }
                     Some('?') => {
-                        // error: unexpected-question-mark-instead-of-tag-name
+                        self.error(ParseError::UnexpectedQuestionMarkInsteadOfTagName);
+                        self.str_buf.clear();
                         self.state = State::BogusComment;
                     }
                     None => {
-                        // error: eof-before-tag-name
+                        self.error(ParseError::EofBeforeTagName);
                         self.force_eof = true;
                         return self.token_here(Token::Char('<'));
                     }
                     Some(_) => {
-                        // error: invalid-first-character-of-tag-name
+                        self.error(ParseError::InvalidFirstCharacterOfTagName);
                         self.state = State::Data;
                         return self.token_here(Token::Char('<'));
                     }
@@ -249,18 +563,19 @@ impl TokenizerInner {
                         self.state = State::TagName;
                     }
                     Some('>') => {
-                        // error: missing-end-tag-name
                         self.consume(&mut chars);
+                        self.error(ParseError::MissingEndTagName);
                         self.state = State::Data;
                     }
                     None => {
-                        // error: eof-before-tag-name
+                        self.error(ParseError::EofBeforeTagName);
                         self.force_eof = true;
                         self.synthetic_toks.push((self.loc, Token::Char('/')));
                         return self.token(self.start_loc, Token::Char('<'));
                     }
                     Some(_) => {
-                        // error: invalid-first-character-of-tag-name
+                        self.error(ParseError::InvalidFirstCharacterOfTagName);
+                        self.str_buf.clear();
                         self.state = State::BogusComment;
                     }
                 },
@@ -273,6 +588,7 @@ impl TokenizerInner {
                     Some('/') => {
                         self.consume(&mut chars);
                         self.state = State::SelfClosingStartTag;
+                        self.set_tag_name_if_unset(int);
                     }
                     Some('>') => {
                         self.consume(&mut chars);
@@ -287,12 +603,12 @@ impl TokenizerInner {
                         self.str_buf.push(c.to_ascii_lowercase());
                     }
                     Some('\x00') => {
-                        // error: unexpected-null-character
                         self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
                         self.str_buf.push(char::REPLACEMENT_CHARACTER);
                     }
                     None => {
-                        // error: eof-in-tag
+                        self.error(ParseError::EofInTag);
                         return Poll::Ready(None);
                     }
                     Some(c) => {
@@ -306,8 +622,8 @@ impl TokenizerInner {
                     }
                     None | Some('/' | '>') => self.state = State::AfterAttributeName,
                     Some('=') => {
-                        // error: unexpected-equals-sign-before-attribute-name
                         self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedEqualsSignBeforeAttributeName);
                         self.str_buf.clear();
                         self.str_buf.push('=');
                         self.attr_buf
@@ -323,14 +639,12 @@ impl TokenizerInner {
                 },
                 State::AttributeName => match c {
                     None | Some('\t' | '\n' | '\x0C' | ' ' | '/' | '>') => {
-                        let name = int.intern_str(&self.str_buf);
-                        self.attr_buf.last_mut().unwrap()[0] = name;
+                        self.finish_attr_name(int);
                         self.state = State::AfterAttributeName;
                     }
                     Some('=') => {
                         self.consume(&mut chars);
-                        let name = int.intern_str(&self.str_buf);
-                        self.attr_buf.last_mut().unwrap()[0] = name;
+                        self.finish_attr_name(int);
                         self.state = State::BeforeAttributeValue;
                     }
                     Some(c) if c.is_ascii_uppercase() => {
@@ -338,11 +652,13 @@ impl TokenizerInner {
                         self.str_buf.push(c.to_ascii_lowercase());
                     }
                     Some('\x00') => {
-                        self.state = State::BeforeAttributeValue;
+                        self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
+                        self.str_buf.push(char::REPLACEMENT_CHARACTER);
                     }
                     Some(c @ '"' | c @ '\'' | c @ '<') => {
-                        // error: unexpected-character-in-attribute-name
                         self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedCharacterInAttributeName);
                         self.str_buf.push(c);
                     }
                     Some(c) => {
@@ -365,15 +681,19 @@ impl TokenizerInner {
                     Some('>') => {
                         self.consume(&mut chars);
                         self.state = State::Data;
+                        self.set_tag_attrs_if_unset(int);
+                        self.attr_buf.clear();
+                        return self.token(self.start_loc, self.tok);
                     }
                     None => {
-                        // error: eof-in-tag
+                        self.error(ParseError::EofInTag);
                         return Poll::Ready(None);
                     }
                     Some(_) => {
                         self.str_buf.clear();
                         self.attr_buf
                             .push([I::EMPTY_RANGE_INDEX, I::EMPTY_RANGE_INDEX]);
+                        self.state = State::AttributeName;
                     }
                 },
                 State::BeforeAttributeValue => match c {
@@ -391,8 +711,8 @@ impl TokenizerInner {
                         self.state = State::AttributeValueSingleQuote;
                     }
                     Some('>') => {
-                        // error: missing-attribute-value
                         self.consume(&mut chars);
+                        self.error(ParseError::MissingAttributeValue);
                         self.set_tag_attrs_if_unset(int);
                         self.attr_buf.clear();
                         self.state = State::Data;
@@ -410,14 +730,17 @@ impl TokenizerInner {
                         self.attr_buf.last_mut().unwrap()[1] = value;
                         self.state = State::AfterAttributeValueQuoted;
                     }
-                    Some('&') => todo!("char reference state"),
+                    Some('&') => {
+                        self.consume(&mut chars);
+                        self.begin_char_ref();
+                    }
                     Some('\x00') => {
-                        // error: unexpected-null-character
                         self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
                         self.str_buf.push(char::REPLACEMENT_CHARACTER);
                     }
                     None => {
-                        // error: eof-in-tag
+                        self.error(ParseError::EofInTag);
                         return Poll::Ready(None);
                     }
                     Some(c) => {
@@ -432,14 +755,17 @@ impl TokenizerInner {
                         self.attr_buf.last_mut().unwrap()[1] = value;
                         self.state = State::AfterAttributeValueQuoted;
                     }
-                    Some('&') => todo!("char reference state"),
+                    Some('&') => {
+                        self.consume(&mut chars);
+                        self.begin_char_ref();
+                    }
                     Some('\x00') => {
-                        // error: unexpected-null-character
                         self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
                         self.str_buf.push(char::REPLACEMENT_CHARACTER);
                     }
                     None => {
-                        // error: eof-in-tag
+                        self.error(ParseError::EofInTag);
                         return Poll::Ready(None);
                     }
                     Some(c) => {
@@ -454,7 +780,10 @@ impl TokenizerInner {
                         self.attr_buf.last_mut().unwrap()[1] = value;
                         self.state = State::BeforeAttributeName;
                     }
-                    Some('&') => todo!("char reference state"),
+                    Some('&') => {
+                        self.consume(&mut chars);
+                        self.begin_char_ref();
+                    }
                     Some('>') => {
                         self.consume(&mut chars);
                         let value = int.intern_str(&self.str_buf);
@@ -465,17 +794,17 @@ impl TokenizerInner {
                         return self.token(self.start_loc, self.tok);
                     }
                     Some('\x00') => {
-                        // error: unexpected-null-character
                         self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
                         self.str_buf.push(char::REPLACEMENT_CHARACTER);
                     }
                     Some(c @ '"' | c @ '\'' | c @ '<' | c @ '=' | c @ '`') => {
-                        // error: unexpected-character-in-unquoted-attribute-value
                         self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedCharacterInUnquotedAttributeValue);
                         self.str_buf.push(c);
                     }
                     None => {
-                        // error: eof-in-tag
+                        self.error(ParseError::EofInTag);
                         return Poll::Ready(None);
                     }
                     Some(c) => {
@@ -500,11 +829,11 @@ impl TokenizerInner {
                         return self.token(self.start_loc, self.tok);
                     }
                     None => {
-                        // error: eof-in-tag
+                        self.error(ParseError::EofInTag);
                         return Poll::Ready(None);
                     }
                     Some(_) => {
-                        // error: missing-whitespace-between-attributes
+                        self.error(ParseError::MissingWhitespaceBetweenAttributes);
                         self.state = State::BeforeAttributeName;
                     }
                 },
@@ -525,200 +854,1125 @@ impl TokenizerInner {
                                 },
                             );
                         }
-                        unreachable!()
+                        self.error(ParseError::EndTagWithTrailingSolidus);
+                        return self.token(self.start_loc, self.tok);
                     }
                     None => {
-                        // error: eof-in-tag
+                        self.error(ParseError::EofInTag);
                         return Poll::Ready(None);
                     }
                     Some(_) => {
-                        // error: unexpected-solidus-in-tag
+                        self.error(ParseError::UnexpectedSolidusInTag);
                         self.state = State::BeforeAttributeName;
                     }
                 },
-                State::BogusComment => todo!("bogus comment"),
-                State::RcData => match c {
-                    Some('&') => todo!("char reference state"),
-                    Some('<') => {
-                        self.consume(&mut chars);
-                        self.start_loc = self.loc;
-                        self.state = State::RcDataLessThan;
+                State::CharacterReference => match c {
+                    Some(c) if c.is_ascii_alphanumeric() => {
+                        self.char_ref_name.clear();
+                        self.char_ref_match = None;
+                        self.state = State::NamedCharacterReference;
                     }
-                    Some('\x00') => {
-                        // error: unexpected-null-character
+                    Some('#') => {
                         self.consume(&mut chars);
-                        return self.token_here(Token::Char(char::REPLACEMENT_CHARACTER));
+                        self.temp_buffer.push((self.loc, '#'));
+                        self.state = State::NumericCharacterReference;
                     }
-                    None => return Poll::Ready(None),
-                    Some(c) => {
-                        self.consume(&mut chars);
-                        return self.token_here(Token::Char(c));
+                    _ => {
+                        self.state = self.return_state;
+                        if let Some(tok) = self.flush_char_ref() {
+                            return tok;
+                        }
                     }
                 },
-                State::RcDataLessThan => match c {
-                    Some('/') => {
+                State::NamedCharacterReference => {
+                    // keep consuming for as long as we are still a prefix of some
+                    // reference, remembering the longest full match along the way
+                    if let Some(c) = c.filter(|c| c.is_ascii_alphanumeric() || *c == ';') {
+                        self.char_ref_name.push(c);
+                        if let Some(index) = entities::find_prefix(&self.char_ref_name) {
+                            self.consume(&mut chars);
+                            self.temp_buffer.push((self.loc, c));
+                            if NAMED_CHAR_REFS[index].0 == self.char_ref_name {
+                                self.char_ref_match = Some((self.temp_buffer.len(), index));
+                            }
+                            // nothing can follow a `;`, no need to wait for more input
+                            if c != ';' {
+                                continue;
+                            }
+                        } else {
+                            self.char_ref_name.pop();
+                        }
+                    }
+                    if let Some((len, index)) = self.char_ref_match {
+                        // whatever we consumed past the longest match is alphanumeric,
+                        // so its fine to flush it along with the reference itself
+                        let tail = self.temp_buffer.split_off(len);
+                        let next = tail.first().map(|(_, c)| *c).or(c);
+                        let (_, last) = self.temp_buffer[len - 1];
+                        let is_historical = self.is_char_ref_in_attr()
+                            && (last != ';')
+                            && matches!(next, Some(c) if (c == '=') || c.is_ascii_alphanumeric());
+                        if !is_historical {
+                            if last != ';' {
+                                self.error(ParseError::MissingSemicolonAfterCharacterReference);
+                            }
+                            let (loc, _) = self.temp_buffer[0];
+                            self.temp_buffer.clear();
+                            for c in NAMED_CHAR_REFS[index].1.chars() {
+                                self.temp_buffer.push((loc, c));
+                            }
+                        }
+                        self.temp_buffer.extend(tail);
+                        self.state = self.return_state;
+                    } else {
+                        self.state = State::AmbiguousAmpersand;
+                    }
+                    if let Some(tok) = self.flush_char_ref() {
+                        return tok;
+                    }
+                }
+                State::AmbiguousAmpersand => match c {
+                    Some(c) if c.is_ascii_alphanumeric() => {
                         self.consume(&mut chars);
-                        self.temp_buffer.clear();
-                        self.state = State::RcDataEndTagOpen;
+                        if !self.is_char_ref_in_attr() {
+                            return self.token_here(Token::Char(c));
+                        }
+                        self.str_buf.push(c);
+                    }
+                    Some(';') => {
+                        self.error(ParseError::UnknownNamedCharacterReference);
+                        self.state = self.return_state;
+                    }
+                    _ => self.state = self.return_state,
+                },
+                State::NumericCharacterReference => {
+                    self.char_ref_code = 0;
+                    match c {
+                        Some(c @ ('x' | 'X')) => {
+                            self.consume(&mut chars);
+                            self.temp_buffer.push((self.loc, c));
+                            self.state = State::HexadecimalCharacterReferenceStart;
+                        }
+                        _ => self.state = State::DecimalCharacterReferenceStart,
+                    }
+                }
+                State::HexadecimalCharacterReferenceStart => match c {
+                    Some(c) if c.is_ascii_hexdigit() => {
+                        self.state = State::HexadecimalCharacterReference;
                     }
                     _ => {
-                        self.state = State::RcData;
-                        return self.token(self.start_loc, Token::Char('<'));
+                        self.error(ParseError::AbsenceOfDigitsInNumericCharacterReference);
+                        self.state = self.return_state;
+                        if let Some(tok) = self.flush_char_ref() {
+                            return tok;
+                        }
                     }
                 },
-                State::RcDataEndTagOpen => match c {
-                    Some(c) if c.is_alphabetic() => {
-                        self.str_buf.clear();
-                        self.tok = Token::EndTag {
-                            name: I::EMPTY_RANGE_INDEX,
-                        };
-                        self.state = State::RcDataEndTagName;
+                State::DecimalCharacterReferenceStart => match c {
+                    Some(c) if c.is_ascii_digit() => {
+                        self.state = State::DecimalCharacterReference;
                     }
                     _ => {
-                        self.synthetic_toks.push((self.loc, Token::Char('/')));
-                        self.state = State::RcData;
-                        return self.token(self.start_loc, Token::Char('<'));
+                        self.error(ParseError::AbsenceOfDigitsInNumericCharacterReference);
+                        self.state = self.return_state;
+                        if let Some(tok) = self.flush_char_ref() {
+                            return tok;
+                        }
                     }
                 },
-                State::RcDataEndTagName => match c {
-                    Some('\t' | '\n' | '\x0C' | ' ') => {
-                        self.set_tag_name_if_unset(int);
-                        if let Token::EndTag { name } = self.tok {
-                            if matches!(self.last_start_tag_emitted_name, Some(n) if n == name) {
-                                self.consume(&mut chars);
-                                self.state = State::BeforeAttributeName;
-                                return Poll::Pending;
-                            }
+                State::HexadecimalCharacterReference => match c {
+                    Some(c) if c.is_ascii_hexdigit() => {
+                        self.consume(&mut chars);
+                        let digit = c.to_digit(16).unwrap();
+                        self.char_ref_code =
+                            self.char_ref_code.saturating_mul(16).saturating_add(digit);
+                    }
+                    Some(';') => {
+                        self.consume(&mut chars);
+                        if let Some(tok) = self.end_numeric_char_ref() {
+                            return tok;
                         }
-                        for (loc, c) in self.temp_buffer.drain(..).rev() {
-                            self.synthetic_toks.push((loc, Token::Char(c)));
+                    }
+                    _ => {
+                        self.error(ParseError::MissingSemicolonAfterCharacterReference);
+                        if let Some(tok) = self.end_numeric_char_ref() {
+                            return tok;
                         }
-                        self.synthetic_toks.push((self.loc, Token::Char('/')));
-                        self.state = State::RcData;
-                        return self.token(self.start_loc, Token::Char('<'));
                     }
-                    Some('/') => {
-                        self.set_tag_name_if_unset(int);
-                        if let Token::EndTag { name } = self.tok {
-                            if matches!(self.last_start_tag_emitted_name, Some(n) if n == name) {
-                                self.consume(&mut chars);
-                                self.state = State::SelfClosingStartTag;
-                                return Poll::Pending;
-                            }
+                },
+                State::DecimalCharacterReference => match c {
+                    Some(c) if c.is_ascii_digit() => {
+                        self.consume(&mut chars);
+                        let digit = c.to_digit(10).unwrap();
+                        self.char_ref_code =
+                            self.char_ref_code.saturating_mul(10).saturating_add(digit);
+                    }
+                    Some(';') => {
+                        self.consume(&mut chars);
+                        if let Some(tok) = self.end_numeric_char_ref() {
+                            return tok;
                         }
-                        for (loc, c) in self.temp_buffer.drain(..).rev() {
-                            self.synthetic_toks.push((loc, Token::Char(c)));
+                    }
+                    _ => {
+                        self.error(ParseError::MissingSemicolonAfterCharacterReference);
+                        if let Some(tok) = self.end_numeric_char_ref() {
+                            return tok;
                         }
-                        self.synthetic_toks.push((self.loc, Token::Char('/')));
-                        self.state = State::RcData;
-                        return self.token(self.start_loc, Token::Char('<'));
                     }
-                    Some('>') => {
-                        self.set_tag_name_if_unset(int);
-                        if let Token::EndTag { name } = self.tok {
-                            if matches!(self.last_start_tag_emitted_name, Some(n) if n == name) {
-                                self.consume(&mut chars);
-                                self.state = State::Data;
-                                return self.token(self.start_loc, self.tok);
-                            }
+                },
+                State::MarkupDeclarationOpen => {
+                    let dashes = c.and_then(|c| self.match_keyword(c, "--", false));
+                    let doctype = c.and_then(|c| self.match_keyword(c, "DOCTYPE", true));
+                    let cdata = c.and_then(|c| self.match_keyword(c, "[CDATA[", false));
+                    match (dashes, doctype, cdata) {
+                        (Some(true), _, _) => {
+                            self.consume(&mut chars);
+                            self.str_buf.clear();
+                            self.state = State::CommentStart;
                         }
-                        for (loc, c) in self.temp_buffer.drain(..).rev() {
-                            self.synthetic_toks.push((loc, Token::Char(c)));
+                        (_, Some(true), _) => {
+                            self.consume(&mut chars);
+                            self.state = State::DocType;
+                        }
+                        (_, _, Some(true)) if self.cdata_allowed => {
+                            self.consume(&mut chars);
+                            self.temp_buffer.clear();
+                            self.state = State::CDataSection;
+                        }
+                        (_, _, Some(true)) => {
+                            self.consume(&mut chars);
+                            self.error(ParseError::CdataInHtmlContent);
+                            self.str_buf.clear();
+                            self.str_buf.push_str("[CDATA[");
+                            self.state = State::BogusComment;
+                        }
+                        (Some(false), _, _) | (_, Some(false), _) | (_, _, Some(false)) => {
+                            self.consume(&mut chars);
+                            self.temp_buffer.push((self.loc, c.unwrap()));
+                        }
+                        _ => {
+                            self.error(ParseError::IncorrectlyOpenedComment);
+                            // the partial match is data for the bogus comment
+                            self.str_buf.clear();
+                            for (_, c) in self.temp_buffer.drain(..) {
+                                self.str_buf.push(c);
+                            }
+                            self.state = State::BogusComment;
                         }
-                        self.synthetic_toks.push((self.loc, Token::Char('/')));
-                        self.state = State::RcData;
-                        return self.token(self.start_loc, Token::Char('<'));
                     }
-                    Some(c) if c.is_ascii_uppercase() => {
+                }
+                State::CDataSection => match c {
+                    Some(']') => {
                         self.consume(&mut chars);
-                        self.str_buf.push(c.to_ascii_lowercase());
-                        self.temp_buffer.push((self.loc, c));
+                        self.temp_buffer.push((self.loc, ']'));
+                        self.state = State::CDataSectionBracket;
+                    }
+                    None => {
+                        self.error(ParseError::EofInCdata);
+                        return Poll::Ready(None);
                     }
-                    Some(c) if c.is_ascii_lowercase() => {
+                    Some(c) => {
                         self.consume(&mut chars);
-                        self.str_buf.push(c);
-                        self.temp_buffer.push((self.loc, c));
+                        return self.token_here(Token::Char(c));
+                    }
+                },
+                State::CDataSectionBracket => match c {
+                    Some(']') => {
+                        self.consume(&mut chars);
+                        self.temp_buffer.push((self.loc, ']'));
+                        self.state = State::CDataSectionEnd;
                     }
                     _ => {
-                        for (loc, c) in self.temp_buffer.drain(..).rev() {
-                            self.synthetic_toks.push((loc, Token::Char(c)));
-                        }
-                        self.synthetic_toks.push((self.loc, Token::Char('/')));
-                        self.state = State::RcData;
-                        return self.token(self.start_loc, Token::Char('<'));
+                        self.state = State::CDataSection;
+                        let (loc, c) = self.temp_buffer.pop().unwrap();
+                        return self.token(loc, Token::Char(c));
                     }
                 },
-            }
-        }
-    }
-}
-
-impl<R: AsyncRead + Unpin> Tokenizer<R> {
-    pub fn poll_next<I: Interner>(
-        self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        int: &mut I,
-    ) -> Poll<Option<TokenzizerItem>> {
-        let mut this = self.project();
-        if let Some((loc, tok)) = this.inner.synthetic_toks.pop() {
-            if let Token::StartTag { name, .. } = tok {
-                this.inner.last_start_tag_emitted_name = Some(name);
-            }
-            return Poll::Ready(Some((loc, Ok(tok))));
-        }
-        if this.inner.force_eof {
-            return Poll::Ready(None);
-        }
-        let input = {
-            match this.reader.as_mut().poll_fill_buf(cx) {
-                Poll::Ready(Ok(s)) => s,
-                Poll::Ready(Err(err)) => {
-                    // TODO: could handle some io errors as <EOF>
-                    return Poll::Ready(Some((this.inner.loc, Err(err.into()))));
-                }
-                Poll::Pending => {
-                    cx.waker().wake_by_ref();
-                    return Poll::Pending;
-                }
-            }
-        };
-        match this.inner.next(input, int) {
-            Poll::Ready(item) => {
-                if let Some((_, Ok(Token::StartTag { name, .. }))) = item {
-                    this.inner.last_start_tag_emitted_name = Some(name);
-                }
-                this.reader.consume(this.inner.consumed);
-                this.inner.consumed = 0;
-                Poll::Ready(item)
-            }
-            Poll::Pending => {
-                this.reader.consume(this.inner.consumed);
-                this.inner.consumed = 0;
-                cx.waker().wake_by_ref();
-                Poll::Pending
-            }
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use smol::io::Cursor;
-
-    use super::*;
-    use crate::asyncro;
-
-    fn cx<'a>() -> Context<'a> {
-        Context::from_waker(asyncro::noop_waker_ref())
-    }
-
-    struct MockInterner {
-        strings: Vec<String>,
-        attrs: Vec<Vec<[usize; 2]>>,
-    }
-
+                State::CDataSectionEnd => match c {
+                    Some(']') => {
+                        // the oldest bracket is data, the last two may still end the section
+                        self.consume(&mut chars);
+                        let (loc, c) = self.temp_buffer.remove(0);
+                        self.temp_buffer.push((self.loc, ']'));
+                        return self.token(loc, Token::Char(c));
+                    }
+                    Some('>') => {
+                        self.consume(&mut chars);
+                        self.temp_buffer.clear();
+                        self.state = State::Data;
+                    }
+                    _ => {
+                        self.state = State::CDataSection;
+                        let (second, c2) = self.temp_buffer.pop().unwrap();
+                        let (first, c1) = self.temp_buffer.pop().unwrap();
+                        self.synthetic_toks.push((second, Token::Char(c2)));
+                        return self.token(first, Token::Char(c1));
+                    }
+                },
+                State::CommentStart => match c {
+                    Some('-') => {
+                        self.consume(&mut chars);
+                        self.state = State::CommentStartDash;
+                    }
+                    Some('>') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::AbruptClosingOfEmptyComment);
+                        self.state = State::Data;
+                        return self.emit_comment(int);
+                    }
+                    _ => self.state = State::Comment,
+                },
+                State::CommentStartDash => match c {
+                    Some('-') => {
+                        self.consume(&mut chars);
+                        self.state = State::CommentEnd;
+                    }
+                    Some('>') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::AbruptClosingOfEmptyComment);
+                        self.state = State::Data;
+                        return self.emit_comment(int);
+                    }
+                    None => {
+                        self.error(ParseError::EofInComment);
+                        self.force_eof = true;
+                        return self.emit_comment(int);
+                    }
+                    Some(_) => {
+                        self.str_buf.push('-');
+                        self.state = State::Comment;
+                    }
+                },
+                State::Comment => match c {
+                    Some('<') => {
+                        self.consume(&mut chars);
+                        self.str_buf.push('<');
+                        self.state = State::CommentLessThan;
+                    }
+                    Some('-') => {
+                        self.consume(&mut chars);
+                        self.state = State::CommentEndDash;
+                    }
+                    Some('\x00') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
+                        self.str_buf.push(char::REPLACEMENT_CHARACTER);
+                    }
+                    None => {
+                        self.error(ParseError::EofInComment);
+                        self.force_eof = true;
+                        return self.emit_comment(int);
+                    }
+                    Some(c) => {
+                        self.consume(&mut chars);
+                        self.str_buf.push(c);
+                    }
+                },
+                State::CommentLessThan => match c {
+                    Some(c @ '!') => {
+                        self.consume(&mut chars);
+                        self.str_buf.push(c);
+                        self.state = State::CommentLessThanBang;
+                    }
+                    Some(c @ '<') => {
+                        self.consume(&mut chars);
+                        self.str_buf.push(c);
+                    }
+                    _ => self.state = State::Comment,
+                },
+                State::CommentLessThanBang => match c {
+                    Some('-') => {
+                        self.consume(&mut chars);
+                        self.state = State::CommentLessThanBangDash;
+                    }
+                    _ => self.state = State::Comment,
+                },
+                State::CommentLessThanBangDash => match c {
+                    Some('-') => {
+                        self.consume(&mut chars);
+                        self.state = State::CommentLessThanBangDashDash;
+                    }
+                    _ => self.state = State::CommentEndDash,
+                },
+                State::CommentLessThanBangDashDash => match c {
+                    None | Some('>') => self.state = State::CommentEnd,
+                    Some(_) => {
+                        self.error(ParseError::NestedComment);
+                        self.state = State::CommentEnd;
+                    }
+                },
+                State::CommentEndDash => match c {
+                    Some('-') => {
+                        self.consume(&mut chars);
+                        self.state = State::CommentEnd;
+                    }
+                    None => {
+                        self.error(ParseError::EofInComment);
+                        self.force_eof = true;
+                        return self.emit_comment(int);
+                    }
+                    Some(_) => {
+                        self.str_buf.push('-');
+                        self.state = State::Comment;
+                    }
+                },
+                State::CommentEnd => match c {
+                    Some('>') => {
+                        self.consume(&mut chars);
+                        self.state = State::Data;
+                        return self.emit_comment(int);
+                    }
+                    Some('!') => {
+                        self.consume(&mut chars);
+                        self.state = State::CommentEndBang;
+                    }
+                    Some('-') => {
+                        self.consume(&mut chars);
+                        self.str_buf.push('-');
+                    }
+                    None => {
+                        self.error(ParseError::EofInComment);
+                        self.force_eof = true;
+                        return self.emit_comment(int);
+                    }
+                    Some(_) => {
+                        self.str_buf.push_str("--");
+                        self.state = State::Comment;
+                    }
+                },
+                State::CommentEndBang => match c {
+                    Some('-') => {
+                        self.consume(&mut chars);
+                        self.str_buf.push_str("--!");
+                        self.state = State::CommentEndDash;
+                    }
+                    Some('>') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::IncorrectlyClosedComment);
+                        self.state = State::Data;
+                        return self.emit_comment(int);
+                    }
+                    None => {
+                        self.error(ParseError::EofInComment);
+                        self.force_eof = true;
+                        return self.emit_comment(int);
+                    }
+                    Some(_) => {
+                        self.str_buf.push_str("--!");
+                        self.state = State::Comment;
+                    }
+                },
+                State::BogusComment => match c {
+                    Some('>') => {
+                        self.consume(&mut chars);
+                        self.state = State::Data;
+                        return self.emit_comment(int);
+                    }
+                    Some('\x00') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
+                        self.str_buf.push(char::REPLACEMENT_CHARACTER);
+                    }
+                    None => {
+                        self.force_eof = true;
+                        return self.emit_comment(int);
+                    }
+                    Some(c) => {
+                        self.consume(&mut chars);
+                        self.str_buf.push(c);
+                    }
+                },
+                State::DocType => match c {
+                    Some('\t' | '\n' | '\x0C' | ' ') => {
+                        self.consume(&mut chars);
+                        self.state = State::BeforeDocTypeName;
+                    }
+                    Some('>') => self.state = State::BeforeDocTypeName,
+                    None => {
+                        self.error(ParseError::EofInDoctype);
+                        self.new_doctype(true);
+                        self.force_eof = true;
+                        return self.token(self.start_loc, self.tok);
+                    }
+                    Some(_) => {
+                        self.error(ParseError::MissingWhitespaceBeforeDoctypeName);
+                        self.state = State::BeforeDocTypeName;
+                    }
+                },
+                State::BeforeDocTypeName => match c {
+                    Some('\t' | '\n' | '\x0C' | ' ') => {
+                        self.consume(&mut chars);
+                    }
+                    Some('>') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::MissingDoctypeName);
+                        self.new_doctype(true);
+                        self.state = State::Data;
+                        return self.token(self.start_loc, self.tok);
+                    }
+                    None => {
+                        self.error(ParseError::EofInDoctype);
+                        self.new_doctype(true);
+                        self.force_eof = true;
+                        return self.token(self.start_loc, self.tok);
+                    }
+                    Some(c) => {
+                        self.consume(&mut chars);
+                        self.new_doctype(false);
+                        self.str_buf.clear();
+                        if c == '\x00' {
+                            self.error(ParseError::UnexpectedNullCharacter);
+                            self.str_buf.push(char::REPLACEMENT_CHARACTER);
+                        } else {
+                            self.str_buf.push(c.to_ascii_lowercase());
+                        }
+                        self.state = State::DocTypeName;
+                    }
+                },
+                State::DocTypeName => match c {
+                    Some('\t' | '\n' | '\x0C' | ' ') => {
+                        self.consume(&mut chars);
+                        self.set_doctype_part(int);
+                        self.temp_buffer.clear();
+                        self.state = State::AfterDocTypeName;
+                    }
+                    Some('>') => {
+                        self.consume(&mut chars);
+                        self.set_doctype_part(int);
+                        self.state = State::Data;
+                        return self.token(self.start_loc, self.tok);
+                    }
+                    Some(c) if c.is_ascii_uppercase() => {
+                        self.consume(&mut chars);
+                        self.str_buf.push(c.to_ascii_lowercase());
+                    }
+                    Some('\x00') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
+                        self.str_buf.push(char::REPLACEMENT_CHARACTER);
+                    }
+                    None => {
+                        self.error(ParseError::EofInDoctype);
+                        self.set_doctype_part(int);
+                        self.set_doctype_force_quirks();
+                        self.force_eof = true;
+                        return self.token(self.start_loc, self.tok);
+                    }
+                    Some(c) => {
+                        self.consume(&mut chars);
+                        self.str_buf.push(c);
+                    }
+                },
+                State::AfterDocTypeName => {
+                    // `temp_buffer` holds what we have matched of PUBLIC or SYSTEM
+                    let matching = !self.temp_buffer.is_empty();
+                    match c {
+                        Some('\t' | '\n' | '\x0C' | ' ') if !matching => {
+                            self.consume(&mut chars);
+                        }
+                        Some('>') if !matching => {
+                            self.consume(&mut chars);
+                            self.state = State::Data;
+                            return self.token(self.start_loc, self.tok);
+                        }
+                        None if !matching => {
+                            self.error(ParseError::EofInDoctype);
+                            self.set_doctype_force_quirks();
+                            self.force_eof = true;
+                            return self.token(self.start_loc, self.tok);
+                        }
+                        _ => {
+                            let public = c.and_then(|c| self.match_keyword(c, "PUBLIC", true));
+                            let system = c.and_then(|c| self.match_keyword(c, "SYSTEM", true));
+                            match (public, system) {
+                                (Some(true), _) => {
+                                    self.consume(&mut chars);
+                                    self.state = State::AfterDocTypePublicKeyword;
+                                }
+                                (_, Some(true)) => {
+                                    self.consume(&mut chars);
+                                    self.state = State::AfterDocTypeSystemKeyword;
+                                }
+                                (Some(false), _) | (_, Some(false)) => {
+                                    self.consume(&mut chars);
+                                    self.temp_buffer.push((self.loc, c.unwrap()));
+                                }
+                                _ => {
+                                    self.error(
+                                        ParseError::InvalidCharacterSequenceAfterDoctypeName,
+                                    );
+                                    // the partial match can't contain a `>`, so its fine
+                                    // to drop it rather than reconsume it
+                                    self.temp_buffer.clear();
+                                    self.set_doctype_force_quirks();
+                                    self.state = State::BogusDocType;
+                                }
+                            }
+                        }
+                    }
+                }
+                State::AfterDocTypePublicKeyword | State::BeforeDocTypePublicIdentifier => {
+                    let after_keyword = self.state == State::AfterDocTypePublicKeyword;
+                    match c {
+                        Some('\t' | '\n' | '\x0C' | ' ') => {
+                            self.consume(&mut chars);
+                            self.state = State::BeforeDocTypePublicIdentifier;
+                        }
+                        Some(c @ ('"' | '\'')) => {
+                            if after_keyword {
+                                self.error(ParseError::MissingWhitespaceAfterDoctypePublicKeyword);
+                            }
+                            self.consume(&mut chars);
+                            self.str_buf.clear();
+                            self.state = if c == '"' {
+                                State::DocTypePublicIdentifierDoubleQuote
+                            } else {
+                                State::DocTypePublicIdentifierSingleQuote
+                            };
+                        }
+                        Some('>') => {
+                            self.consume(&mut chars);
+                            self.error(ParseError::MissingDoctypePublicIdentifier);
+                            self.set_doctype_force_quirks();
+                            self.state = State::Data;
+                            return self.token(self.start_loc, self.tok);
+                        }
+                        None => {
+                            self.error(ParseError::EofInDoctype);
+                            self.set_doctype_force_quirks();
+                            self.force_eof = true;
+                            return self.token(self.start_loc, self.tok);
+                        }
+                        Some(_) => {
+                            self.error(ParseError::MissingQuoteBeforeDoctypePublicIdentifier);
+                            self.set_doctype_force_quirks();
+                            self.state = State::BogusDocType;
+                        }
+                    }
+                }
+                State::DocTypePublicIdentifierDoubleQuote
+                | State::DocTypePublicIdentifierSingleQuote
+                | State::DocTypeSystemIdentifierDoubleQuote
+                | State::DocTypeSystemIdentifierSingleQuote => {
+                    let quote = match self.state {
+                        State::DocTypePublicIdentifierDoubleQuote
+                        | State::DocTypeSystemIdentifierDoubleQuote => '"',
+                        _ => '\'',
+                    };
+                    let is_public = matches!(
+                        self.state,
+                        State::DocTypePublicIdentifierDoubleQuote
+                            | State::DocTypePublicIdentifierSingleQuote
+                    );
+                    match c {
+                        Some(c) if c == quote => {
+                            self.consume(&mut chars);
+                            self.set_doctype_part(int);
+                            self.state = if is_public {
+                                State::AfterDocTypePublicIdentifier
+                            } else {
+                                State::AfterDocTypeSystemIdentifier
+                            };
+                        }
+                        Some('\x00') => {
+                            self.consume(&mut chars);
+                            self.error(ParseError::UnexpectedNullCharacter);
+                            self.str_buf.push(char::REPLACEMENT_CHARACTER);
+                        }
+                        Some('>') => {
+                            self.error(ParseError::AbruptDoctypePublicIdentifier);
+                            self.consume(&mut chars);
+                            self.error(ParseError::AbruptDoctypeSystemIdentifier);
+                            self.set_doctype_part(int);
+                            self.set_doctype_force_quirks();
+                            self.state = State::Data;
+                            return self.token(self.start_loc, self.tok);
+                        }
+                        None => {
+                            self.error(ParseError::EofInDoctype);
+                            self.set_doctype_part(int);
+                            self.set_doctype_force_quirks();
+                            self.force_eof = true;
+                            return self.token(self.start_loc, self.tok);
+                        }
+                        Some(c) => {
+                            self.consume(&mut chars);
+                            self.str_buf.push(c);
+                        }
+                    }
+                }
+                State::AfterDocTypePublicIdentifier
+                | State::BetweenDocTypePublicAndSystemIdentifiers => {
+                    let after_identifier = self.state == State::AfterDocTypePublicIdentifier;
+                    match c {
+                        Some('\t' | '\n' | '\x0C' | ' ') => {
+                            self.consume(&mut chars);
+                            self.state = State::BetweenDocTypePublicAndSystemIdentifiers;
+                        }
+                        Some('>') => {
+                            self.consume(&mut chars);
+                            self.state = State::Data;
+                            return self.token(self.start_loc, self.tok);
+                        }
+                        Some(c @ ('"' | '\'')) => {
+                            if after_identifier {
+                                self.error(ParseError::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers);
+                            }
+                            self.consume(&mut chars);
+                            self.str_buf.clear();
+                            self.state = if c == '"' {
+                                State::DocTypeSystemIdentifierDoubleQuote
+                            } else {
+                                State::DocTypeSystemIdentifierSingleQuote
+                            };
+                        }
+                        None => {
+                            self.error(ParseError::EofInDoctype);
+                            self.set_doctype_force_quirks();
+                            self.force_eof = true;
+                            return self.token(self.start_loc, self.tok);
+                        }
+                        Some(_) => {
+                            self.error(ParseError::MissingQuoteBeforeDoctypeSystemIdentifier);
+                            self.set_doctype_force_quirks();
+                            self.state = State::BogusDocType;
+                        }
+                    }
+                }
+                State::AfterDocTypeSystemKeyword | State::BeforeDocTypeSystemIdentifier => {
+                    let after_keyword = self.state == State::AfterDocTypeSystemKeyword;
+                    match c {
+                        Some('\t' | '\n' | '\x0C' | ' ') => {
+                            self.consume(&mut chars);
+                            self.state = State::BeforeDocTypeSystemIdentifier;
+                        }
+                        Some(c @ ('"' | '\'')) => {
+                            if after_keyword {
+                                self.error(ParseError::MissingWhitespaceAfterDoctypeSystemKeyword);
+                            }
+                            self.consume(&mut chars);
+                            self.str_buf.clear();
+                            self.state = if c == '"' {
+                                State::DocTypeSystemIdentifierDoubleQuote
+                            } else {
+                                State::DocTypeSystemIdentifierSingleQuote
+                            };
+                        }
+                        Some('>') => {
+                            self.consume(&mut chars);
+                            self.error(ParseError::MissingDoctypeSystemIdentifier);
+                            self.set_doctype_force_quirks();
+                            self.state = State::Data;
+                            return self.token(self.start_loc, self.tok);
+                        }
+                        None => {
+                            self.error(ParseError::EofInDoctype);
+                            self.set_doctype_force_quirks();
+                            self.force_eof = true;
+                            return self.token(self.start_loc, self.tok);
+                        }
+                        Some(_) => {
+                            self.error(ParseError::MissingQuoteBeforeDoctypeSystemIdentifier);
+                            self.set_doctype_force_quirks();
+                            self.state = State::BogusDocType;
+                        }
+                    }
+                }
+                State::AfterDocTypeSystemIdentifier => match c {
+                    Some('\t' | '\n' | '\x0C' | ' ') => {
+                        self.consume(&mut chars);
+                    }
+                    Some('>') => {
+                        self.consume(&mut chars);
+                        self.state = State::Data;
+                        return self.token(self.start_loc, self.tok);
+                    }
+                    None => {
+                        self.error(ParseError::EofInDoctype);
+                        self.set_doctype_force_quirks();
+                        self.force_eof = true;
+                        return self.token(self.start_loc, self.tok);
+                    }
+                    Some(_) => {
+                        self.error(ParseError::UnexpectedCharacterAfterDoctypeSystemIdentifier);
+                        self.state = State::BogusDocType;
+                    }
+                },
+                State::BogusDocType => match c {
+                    Some('>') => {
+                        self.consume(&mut chars);
+                        self.state = State::Data;
+                        return self.token(self.start_loc, self.tok);
+                    }
+                    Some('\x00') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
+                    }
+                    None => {
+                        self.force_eof = true;
+                        return self.token(self.start_loc, self.tok);
+                    }
+                    Some(_) => {
+                        self.consume(&mut chars);
+                    }
+                },
+                State::RcData => match c {
+                    Some('&') => {
+                        self.consume(&mut chars);
+                        self.begin_char_ref();
+                    }
+                    Some('<') => {
+                        self.consume(&mut chars);
+                        self.start_loc = self.loc;
+                        self.state = State::RcDataLessThan;
+                    }
+                    Some('\x00') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
+                        return self.token_here(Token::Char(char::REPLACEMENT_CHARACTER));
+                    }
+                    None => return Poll::Ready(None),
+                    Some(c) => {
+                        self.consume(&mut chars);
+                        return self.token_here(Token::Char(c));
+                    }
+                },
+                State::RawText => match c {
+                    Some('<') => {
+                        self.consume(&mut chars);
+                        self.start_loc = self.loc;
+                        self.state = State::RawTextLessThan;
+                    }
+                    Some('\x00') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
+                        return self.token_here(Token::Char(char::REPLACEMENT_CHARACTER));
+                    }
+                    None => return Poll::Ready(None),
+                    Some(c) => {
+                        self.consume(&mut chars);
+                        return self.token_here(Token::Char(c));
+                    }
+                },
+                State::RcDataLessThan | State::RawTextLessThan => match c {
+                    Some('/') => {
+                        self.consume(&mut chars);
+                        self.temp_buffer.clear();
+                        self.state = match self.state {
+                            State::RcDataLessThan => State::RcDataEndTagOpen,
+                            _ => State::RawTextEndTagOpen,
+                        };
+                    }
+                    _ => {
+                        self.state = self.end_tag_text_state();
+                        return self.token(self.start_loc, Token::Char('<'));
+                    }
+                },
+                State::RcDataEndTagOpen
+                | State::RawTextEndTagOpen
+                | State::ScriptDataEndTagOpen
+                | State::ScriptDataEscapedEndTagOpen => match c {
+                    Some(c) if c.is_ascii_alphabetic() => {
+                        self.str_buf.clear();
+                        self.tok = Token::EndTag {
+                            name: I::EMPTY_RANGE_INDEX,
+                        };
+                        self.state = match self.state {
+                            State::RcDataEndTagOpen => State::RcDataEndTagName,
+                            State::RawTextEndTagOpen => State::RawTextEndTagName,
+                            State::ScriptDataEndTagOpen => State::ScriptDataEndTagName,
+                            _ => State::ScriptDataEscapedEndTagName,
+                        };
+                    }
+                    _ => return self.emit_end_tag_as_text(),
+                },
+                State::RcDataEndTagName
+                | State::RawTextEndTagName
+                | State::ScriptDataEndTagName
+                | State::ScriptDataEscapedEndTagName => match c {
+                    Some(c @ ('\t' | '\n' | '\x0C' | ' ' | '/' | '>')) => {
+                        self.set_tag_name_if_unset(int);
+                        if !self.is_appropriate_end_tag() {
+                            return self.emit_end_tag_as_text();
+                        }
+                        self.consume(&mut chars);
+                        match c {
+                            '/' => self.state = State::SelfClosingStartTag,
+                            '>' => {
+                                self.state = State::Data;
+                                return self.token(self.start_loc, self.tok);
+                            }
+                            _ => self.state = State::BeforeAttributeName,
+                        }
+                    }
+                    Some(c) if c.is_ascii_alphabetic() => {
+                        self.consume(&mut chars);
+                        self.str_buf.push(c.to_ascii_lowercase());
+                        self.temp_buffer.push((self.loc, c));
+                    }
+                    _ => return self.emit_end_tag_as_text(),
+                },
+                State::ScriptData => match c {
+                    Some('<') => {
+                        self.consume(&mut chars);
+                        self.start_loc = self.loc;
+                        self.state = State::ScriptDataLessThan;
+                    }
+                    Some('\x00') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
+                        return self.token_here(Token::Char(char::REPLACEMENT_CHARACTER));
+                    }
+                    None => return Poll::Ready(None),
+                    Some(c) => {
+                        self.consume(&mut chars);
+                        return self.token_here(Token::Char(c));
+                    }
+                },
+                State::ScriptDataLessThan => match c {
+                    Some('/') => {
+                        self.consume(&mut chars);
+                        self.temp_buffer.clear();
+                        self.state = State::ScriptDataEndTagOpen;
+                    }
+                    Some('!') => {
+                        self.consume(&mut chars);
+                        self.synthetic_toks.push((self.loc, Token::Char('!')));
+                        self.state = State::ScriptDataEscapeStart;
+                        return self.token(self.start_loc, Token::Char('<'));
+                    }
+                    _ => {
+                        self.state = State::ScriptData;
+                        return self.token(self.start_loc, Token::Char('<'));
+                    }
+                },
+                State::ScriptDataEscapeStart | State::ScriptDataEscapeStartDash => match c {
+                    Some('-') => {
+                        self.consume(&mut chars);
+                        self.state = match self.state {
+                            State::ScriptDataEscapeStart => State::ScriptDataEscapeStartDash,
+                            _ => State::ScriptDataEscapedDashDash,
+                        };
+                        return self.token_here(Token::Char('-'));
+                    }
+                    _ => self.state = State::ScriptData,
+                },
+                State::ScriptDataEscaped
+                | State::ScriptDataEscapedDash
+                | State::ScriptDataEscapedDashDash => match c {
+                    Some('-') => {
+                        self.consume(&mut chars);
+                        self.state = match self.state {
+                            State::ScriptDataEscaped => State::ScriptDataEscapedDash,
+                            _ => State::ScriptDataEscapedDashDash,
+                        };
+                        return self.token_here(Token::Char('-'));
+                    }
+                    Some('<') => {
+                        self.consume(&mut chars);
+                        self.start_loc = self.loc;
+                        self.state = State::ScriptDataEscapedLessThan;
+                    }
+                    Some('>') if self.state == State::ScriptDataEscapedDashDash => {
+                        self.consume(&mut chars);
+                        self.state = State::ScriptData;
+                        return self.token_here(Token::Char('>'));
+                    }
+                    Some('\x00') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
+                        self.state = State::ScriptDataEscaped;
+                        return self.token_here(Token::Char(char::REPLACEMENT_CHARACTER));
+                    }
+                    None => {
+                        self.error(ParseError::EofInScriptHtmlCommentLikeText);
+                        return Poll::Ready(None);
+                    }
+                    Some(c) => {
+                        self.consume(&mut chars);
+                        self.state = State::ScriptDataEscaped;
+                        return self.token_here(Token::Char(c));
+                    }
+                },
+                State::ScriptDataEscapedLessThan => match c {
+                    Some('/') => {
+                        self.consume(&mut chars);
+                        self.temp_buffer.clear();
+                        self.state = State::ScriptDataEscapedEndTagOpen;
+                    }
+                    Some(c) if c.is_ascii_alphabetic() => {
+                        self.temp_buffer.clear();
+                        self.state = State::ScriptDataDoubleEscapeStart;
+                        return self.token(self.start_loc, Token::Char('<'));
+                    }
+                    _ => {
+                        self.state = State::ScriptDataEscaped;
+                        return self.token(self.start_loc, Token::Char('<'));
+                    }
+                },
+                State::ScriptDataDoubleEscapeStart | State::ScriptDataDoubleEscapeEnd => match c {
+                    Some(c @ ('\t' | '\n' | '\x0C' | ' ' | '/' | '>')) => {
+                        self.consume(&mut chars);
+                        let is_script = self
+                            .temp_buffer
+                            .iter()
+                            .map(|(_, c)| *c)
+                            .eq("script".chars());
+                        self.state = match (self.state, is_script) {
+                            (State::ScriptDataDoubleEscapeStart, true) => {
+                                State::ScriptDataDoubleEscaped
+                            }
+                            (State::ScriptDataDoubleEscapeStart, false) => State::ScriptDataEscaped,
+                            (_, true) => State::ScriptDataEscaped,
+                            (_, false) => State::ScriptDataDoubleEscaped,
+                        };
+                        return self.token_here(Token::Char(c));
+                    }
+                    Some(c) if c.is_ascii_alphabetic() => {
+                        self.consume(&mut chars);
+                        self.temp_buffer.push((self.loc, c.to_ascii_lowercase()));
+                        return self.token_here(Token::Char(c));
+                    }
+                    _ => {
+                        self.state = match self.state {
+                            State::ScriptDataDoubleEscapeStart => State::ScriptDataEscaped,
+                            _ => State::ScriptDataDoubleEscaped,
+                        };
+                    }
+                },
+                State::ScriptDataDoubleEscaped
+                | State::ScriptDataDoubleEscapedDash
+                | State::ScriptDataDoubleEscapedDashDash => match c {
+                    Some('-') => {
+                        self.consume(&mut chars);
+                        self.state = match self.state {
+                            State::ScriptDataDoubleEscaped => State::ScriptDataDoubleEscapedDash,
+                            _ => State::ScriptDataDoubleEscapedDashDash,
+                        };
+                        return self.token_here(Token::Char('-'));
+                    }
+                    Some('<') => {
+                        self.consume(&mut chars);
+                        self.state = State::ScriptDataDoubleEscapedLessThan;
+                        return self.token_here(Token::Char('<'));
+                    }
+                    Some('>') if self.state == State::ScriptDataDoubleEscapedDashDash => {
+                        self.consume(&mut chars);
+                        self.state = State::ScriptData;
+                        return self.token_here(Token::Char('>'));
+                    }
+                    Some('\x00') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
+                        self.state = State::ScriptDataDoubleEscaped;
+                        return self.token_here(Token::Char(char::REPLACEMENT_CHARACTER));
+                    }
+                    None => {
+                        self.error(ParseError::EofInScriptHtmlCommentLikeText);
+                        return Poll::Ready(None);
+                    }
+                    Some(c) => {
+                        self.consume(&mut chars);
+                        self.state = State::ScriptDataDoubleEscaped;
+                        return self.token_here(Token::Char(c));
+                    }
+                },
+                State::ScriptDataDoubleEscapedLessThan => match c {
+                    Some('/') => {
+                        self.consume(&mut chars);
+                        self.temp_buffer.clear();
+                        self.state = State::ScriptDataDoubleEscapeEnd;
+                        return self.token_here(Token::Char('/'));
+                    }
+                    _ => self.state = State::ScriptDataDoubleEscaped,
+                },
+                State::PlainText => match c {
+                    Some('\x00') => {
+                        self.consume(&mut chars);
+                        self.error(ParseError::UnexpectedNullCharacter);
+                        return self.token_here(Token::Char(char::REPLACEMENT_CHARACTER));
+                    }
+                    None => return Poll::Ready(None),
+                    Some(c) => {
+                        self.consume(&mut chars);
+                        return self.token_here(Token::Char(c));
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Tokenizer<R> {
+    pub fn poll_next<I: Interner>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        int: &mut I,
+    ) -> Poll<Option<TokenzizerItem>> {
+        let mut this = self.project();
+        if let Some((loc, tok)) = this.inner.synthetic_toks.pop() {
+            if let Token::StartTag { name, .. } = tok {
+                this.inner.last_start_tag_emitted_name = Some(name);
+            }
+            return Poll::Ready(Some((loc, Ok(tok))));
+        }
+        if this.inner.force_eof {
+            return Poll::Ready(None);
+        }
+        let input = {
+            match this.reader.as_mut().poll_fill_buf(cx) {
+                Poll::Ready(Ok(s)) => s,
+                Poll::Ready(Err(err)) => {
+                    // TODO: could handle some io errors as <EOF>
+                    return Poll::Ready(Some((this.inner.loc, Err(err.into()))));
+                }
+                Poll::Pending => {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+            }
+        };
+        match this.inner.next(input, int) {
+            Poll::Ready(item) => {
+                if let Some((_, Ok(Token::StartTag { name, .. }))) = item {
+                    this.inner.last_start_tag_emitted_name = Some(name);
+                }
+                this.reader.consume(this.inner.consumed);
+                this.inner.consumed = 0;
+                if item.is_none() {
+                    // errors found at the end may still be waiting to be taken
+                    this.inner.force_eof = true;
+                }
+                Poll::Ready(item)
+            }
+            Poll::Pending => {
+                this.reader.consume(this.inner.consumed);
+                this.inner.consumed = 0;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use smol::io::Cursor;
+
+    use super::*;
+    use crate::asyncro;
+
+    fn cx<'a>() -> Context<'a> {
+        Context::from_waker(asyncro::noop_waker_ref())
+    }
+
+    pub(crate) struct MockInterner {
+        pub(crate) strings: Vec<String>,
+        pub(crate) attrs: Vec<Vec<[usize; 2]>>,
+    }
+
     impl MockInterner {
-        fn new() -> Self {
+        pub(crate) fn new() -> Self {
             Self {
                 strings: vec!["".into()],
                 attrs: vec![Vec::new()],
@@ -726,98 +1980,963 @@ mod tests {
         }
     }
 
-    impl Interner for MockInterner {
-        const EMPTY_RANGE_INDEX: usize = 0;
-        fn intern_str(&mut self, s: &str) -> usize {
-            if let Some(pos) = self.strings.iter().position(|string| s == string) {
-                return pos;
-            }
-            let len = self.strings.len();
-            self.strings.push(s.into());
-            len
-        }
-        fn intern_attrs(&mut self, attrs: &[[usize; 2]]) -> usize {
-            if let Some(pos) = self.attrs.iter().position(|attr| attr == attrs) {
-                return pos;
-            }
-            let len = self.attrs.len();
-            self.attrs.push(attrs.into());
-            len
-        }
+    impl Interner for MockInterner {
+        const EMPTY_RANGE_INDEX: usize = 0;
+        fn intern_str(&mut self, s: &str) -> usize {
+            if let Some(pos) = self.strings.iter().position(|string| s == string) {
+                return pos;
+            }
+            let len = self.strings.len();
+            self.strings.push(s.into());
+            len
+        }
+        fn intern_attrs(&mut self, attrs: &[[usize; 2]]) -> usize {
+            if let Some(pos) = self.attrs.iter().position(|attr| attr == attrs) {
+                return pos;
+            }
+            let len = self.attrs.len();
+            self.attrs.push(attrs.into());
+            len
+        }
+    }
+
+    fn assert_none<R: AsyncRead + Unpin, I: Interner>(
+        cx: &mut Context<'_>,
+        tokenizer: &mut Tokenizer<R>,
+        int: &mut I,
+    ) {
+        assert!(matches!(
+            Pin::new(tokenizer).poll_next(cx, int),
+            Poll::Ready(None)
+        ));
+    }
+
+    fn assert_pending<R: AsyncRead + Unpin, I: Interner>(
+        cx: &mut Context<'_>,
+        tokenizer: &mut Tokenizer<R>,
+        int: &mut I,
+    ) {
+        assert!(matches!(
+            Pin::new(tokenizer).poll_next(cx, int),
+            Poll::Pending
+        ));
+    }
+
+    fn assert_token<R: AsyncRead + Unpin, I: Interner, L: Into<Location>>(
+        cx: &mut Context<'_>,
+        tokenizer: &mut Tokenizer<R>,
+        int: &mut I,
+        loc: L,
+        tok: Token,
+    ) {
+        let result = Pin::new(tokenizer).poll_next(cx, int);
+        assert!(matches!(result, Poll::Ready(Some((_, Ok(_))))));
+        if let Poll::Ready(Some((location, Ok(token)))) = result {
+            assert_eq!(loc.into(), location);
+            assert_eq!(tok, token);
+        }
+    }
+
+    fn assert_error<R: AsyncRead + Unpin, I: Interner, L: Into<Location>>(
+        cx: &mut Context<'_>,
+        tokenizer: &mut Tokenizer<R>,
+        int: &mut I,
+        loc: L,
+        err: ParseError,
+    ) {
+        // errors are only known once the token they were found in is read,
+        // so read ahead and put the token back for the next assertion
+        if tokenizer.inner.errors.is_empty() {
+            if let Poll::Ready(Some((loc, Ok(tok)))) = Pin::new(&mut *tokenizer).poll_next(cx, int)
+            {
+                tokenizer.inner.synthetic_toks.push((loc, tok));
+            }
+        }
+        assert_eq!(Some((loc.into(), err)), tokenizer.inner.errors.pop_front());
+    }
+
+    fn assert_str(int: &MockInterner, s: &str, index: usize) {
+        assert_eq!(&int.strings[index], s);
+    }
+
+    fn assert_attrs(int: &MockInterner, attrs: &[[&str; 2]], index: usize) {
+        for (lhs, rhs) in int.attrs[index].iter().zip(attrs) {
+            assert_eq!(&int.strings[lhs[0]], rhs[0]);
+            assert_eq!(&int.strings[lhs[1]], rhs[1]);
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let buf = AsyncStrReader::new(Cursor::new(""));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn char() {
+        let buf = AsyncStrReader::new(Cursor::new("abc"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('a'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 2], Token::Char('b'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 3], Token::Char('c'));
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn start_tag() {
+        let buf = AsyncStrReader::new(Cursor::new("<hello>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::StartTag {
+                name: 1,
+                attrs: MockInterner::EMPTY_RANGE_INDEX,
+                self_closing: false,
+            },
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&mut int, "hello", 1);
+    }
+
+    #[test]
+    fn end_tag() {
+        let buf = AsyncStrReader::new(Cursor::new("</hello>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::EndTag { name: 1 },
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&mut int, "hello", 1);
+    }
+
+    #[test]
+    fn self_closing_tag() {
+        let buf = AsyncStrReader::new(Cursor::new("<hello/>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::StartTag {
+                name: 1,
+                attrs: MockInterner::EMPTY_RANGE_INDEX,
+                self_closing: true,
+            },
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&mut int, "hello", 1);
+    }
+
+    #[test]
+    fn self_closing_tag_attrs() {
+        let buf = AsyncStrReader::new(Cursor::new("<h/a='b'>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 3],
+            ParseError::UnexpectedSolidusInTag,
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::StartTag {
+                name: 1,
+                attrs: 1,
+                self_closing: false,
+            },
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&int, "h", 1);
+        assert_attrs(&int, &[["a", "b"]], 1);
+    }
+
+    #[test]
+    fn start_tag_attrs() {
+        let buf = AsyncStrReader::new(Cursor::new(
+            "<hello key='test'><hello key=\"test\"><hello key=test>",
+        ));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::StartTag {
+                name: 1,
+                attrs: 1,
+                self_closing: false,
+            },
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 19],
+            Token::StartTag {
+                name: 1,
+                attrs: 1,
+                self_closing: false,
+            },
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 37],
+            Token::StartTag {
+                name: 1,
+                attrs: 1,
+                self_closing: false,
+            },
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&mut int, "hello", 1);
+        assert_attrs(&mut int, &[["key", "test"]], 1);
+    }
+
+    #[test]
+    fn error_unexpected_null() {
+        let buf = AsyncStrReader::new(Cursor::new("\x00"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            ParseError::UnexpectedNullCharacter,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('\x00'));
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn error_eof_before_tag_name() {
+        let buf = AsyncStrReader::new(Cursor::new("<"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_pending(&mut cx, &mut tok, &mut int);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            ParseError::EofBeforeTagName,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('<'));
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn error_invalid_first_character_of_tag_name() {
+        let buf = AsyncStrReader::new(Cursor::new("<3>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            ParseError::InvalidFirstCharacterOfTagName,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('<'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 2], Token::Char('3'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 3], Token::Char('>'));
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn error_missing_end_tag_name() {
+        let buf = AsyncStrReader::new(Cursor::new("</>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 3],
+            ParseError::MissingEndTagName,
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn error_eof_before_tag_name_2() {
+        let buf = AsyncStrReader::new(Cursor::new("</"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_pending(&mut cx, &mut tok, &mut int);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 2],
+            ParseError::EofBeforeTagName,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('<'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 2], Token::Char('/'));
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn error_unexpected_null_2() {
+        let buf = AsyncStrReader::new(Cursor::new("<test\x00>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 6],
+            ParseError::UnexpectedNullCharacter,
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::StartTag {
+                name: 1,
+                attrs: MockInterner::EMPTY_RANGE_INDEX,
+                self_closing: false,
+            },
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&mut int, "test�", 1);
+    }
+
+    #[test]
+    fn error_eof_in_tag() {
+        let buf = AsyncStrReader::new(Cursor::new("<t"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_pending(&mut cx, &mut tok, &mut int);
+        assert_error(&mut cx, &mut tok, &mut int, [1, 2], ParseError::EofInTag);
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn error_unexpected_equals_sign_before_attribute_name() {
+        let buf = AsyncStrReader::new(Cursor::new("<test ==foo>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 7],
+            ParseError::UnexpectedEqualsSignBeforeAttributeName,
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::StartTag {
+                name: 1,
+                attrs: 1,
+                self_closing: false,
+            },
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&mut int, "test", 1);
+        assert_attrs(&mut int, &[["=", "foo"]], 1);
+    }
+
+    #[test]
+    fn error_unexpected_character_in_attribute_name() {
+        let buf = AsyncStrReader::new(Cursor::new("<test \"'<=foo>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 7],
+            ParseError::UnexpectedCharacterInAttributeName,
+        );
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 8],
+            ParseError::UnexpectedCharacterInAttributeName,
+        );
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 9],
+            ParseError::UnexpectedCharacterInAttributeName,
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::StartTag {
+                name: 1,
+                attrs: 1,
+                self_closing: false,
+            },
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&mut int, "test", 1);
+        assert_attrs(&mut int, &[["\"'<", "foo"]], 1);
+    }
+
+    #[test]
+    fn error_eof_in_tag_2() {
+        let buf = AsyncStrReader::new(Cursor::new("<test foo="));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_pending(&mut cx, &mut tok, &mut int);
+        assert_error(&mut cx, &mut tok, &mut int, [1, 10], ParseError::EofInTag);
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn error_missing_attribute_value() {
+        let buf = AsyncStrReader::new(Cursor::new("<test foo=>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 11],
+            ParseError::MissingAttributeValue,
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::StartTag {
+                name: 1,
+                attrs: 1,
+                self_closing: false,
+            },
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&mut int, "test", 1);
+        assert_attrs(&mut int, &[["foo", ""]], 1);
+    }
+
+    #[test]
+    fn error_unexpected_null_3() {
+        let buf = AsyncStrReader::new(Cursor::new("<test foo=\"\x00\">"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 12],
+            ParseError::UnexpectedNullCharacter,
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::StartTag {
+                name: 1,
+                attrs: 1,
+                self_closing: false,
+            },
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&mut int, "test", 1);
+        assert_attrs(&mut int, &[["foo", "�"]], 1);
+    }
+
+    #[test]
+    fn error_eof_in_tag_3() {
+        let buf = AsyncStrReader::new(Cursor::new("<test foo=\""));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_pending(&mut cx, &mut tok, &mut int);
+        assert_error(&mut cx, &mut tok, &mut int, [1, 11], ParseError::EofInTag);
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn error_missing_whitespace_between_attributes() {
+        let buf = AsyncStrReader::new(Cursor::new("<test foo=\"bar\"bar=\"baz\">"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 15],
+            ParseError::MissingWhitespaceBetweenAttributes,
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::StartTag {
+                name: 1,
+                attrs: 1,
+                self_closing: false,
+            },
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&mut int, "test", 1);
+        assert_attrs(&mut int, &[["foo", "bar"], ["bar", "baz"]], 1);
+    }
+
+    #[test]
+    fn error_eof_in_tag_4() {
+        let buf = AsyncStrReader::new(Cursor::new("</test"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_pending(&mut cx, &mut tok, &mut int);
+        assert_error(&mut cx, &mut tok, &mut int, [1, 6], ParseError::EofInTag);
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn error_unexpected_solidus_in_tag() {
+        let buf = AsyncStrReader::new(Cursor::new("<test//>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 6],
+            ParseError::UnexpectedSolidusInTag,
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::StartTag {
+                name: 1,
+                attrs: MockInterner::EMPTY_RANGE_INDEX,
+                self_closing: true,
+            },
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&mut int, "test", 1);
+    }
+
+    #[test]
+    fn char_ref_named() {
+        let buf = AsyncStrReader::new(Cursor::new("a&amp;b&lt"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('a'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 2], Token::Char('&'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 7], Token::Char('b'));
+        assert_pending(&mut cx, &mut tok, &mut int);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 10],
+            ParseError::MissingSemicolonAfterCharacterReference,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 8], Token::Char('<'));
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn char_ref_named_longest_match() {
+        let buf = AsyncStrReader::new(Cursor::new("&notit;"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 5],
+            ParseError::MissingSemicolonAfterCharacterReference,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('¬'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 5], Token::Char('i'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 6], Token::Char('t'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 7], Token::Char(';'));
+        assert_none(&mut cx, &mut tok, &mut int);
     }
 
-    fn assert_none<R: AsyncRead + Unpin, I: Interner>(
-        cx: &mut Context<'_>,
-        tokenizer: &mut Tokenizer<R>,
-        int: &mut I,
-    ) {
-        assert!(matches!(
-            Pin::new(tokenizer).poll_next(cx, int),
-            Poll::Ready(None)
-        ));
+    #[test]
+    fn char_ref_named_two_code_points() {
+        let buf = AsyncStrReader::new(Cursor::new("&NotEqualTilde;"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('\u{2242}'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('\u{338}'));
+        assert_none(&mut cx, &mut tok, &mut int);
     }
 
-    fn assert_pending<R: AsyncRead + Unpin, I: Interner>(
-        cx: &mut Context<'_>,
-        tokenizer: &mut Tokenizer<R>,
-        int: &mut I,
-    ) {
-        assert!(matches!(
-            Pin::new(tokenizer).poll_next(cx, int),
-            Poll::Pending
-        ));
+    #[test]
+    fn char_ref_numeric() {
+        let buf = AsyncStrReader::new(Cursor::new("&#65;&#x42;&#X63"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('A'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 6], Token::Char('B'));
+        assert_pending(&mut cx, &mut tok, &mut int);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 16],
+            ParseError::MissingSemicolonAfterCharacterReference,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 12], Token::Char('c'));
+        assert_none(&mut cx, &mut tok, &mut int);
     }
 
-    fn assert_token<R: AsyncRead + Unpin, I: Interner, L: Into<Location>>(
-        cx: &mut Context<'_>,
-        tokenizer: &mut Tokenizer<R>,
-        int: &mut I,
-        loc: L,
-        tok: Token,
-    ) {
-        let result = Pin::new(tokenizer).poll_next(cx, int);
-        assert!(matches!(result, Poll::Ready(Some((_, Ok(_))))));
-        if let Poll::Ready(Some((location, Ok(token)))) = result {
-            assert_eq!(loc.into(), location);
-            assert_eq!(tok, token);
-        }
+    #[test]
+    fn char_ref_attrs() {
+        let buf = AsyncStrReader::new(Cursor::new("<test a=\"&amp;&notit;\" b='&not=' c=&lt;&gt>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 42],
+            ParseError::MissingSemicolonAfterCharacterReference,
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::StartTag {
+                name: 1,
+                attrs: 1,
+                self_closing: false,
+            },
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_attrs(&int, &[["a", "&&notit;"], ["b", "&not="], ["c", "<>"]], 1);
     }
 
-    fn assert_str(int: &MockInterner, s: &str, index: usize) {
-        assert_eq!(&int.strings[index], s);
+    #[test]
+    fn char_ref_rcdata() {
+        let buf = AsyncStrReader::new(Cursor::new("&lt;/a&gt;"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        tok.set_state(State::RcData);
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('<'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 5], Token::Char('/'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 6], Token::Char('a'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 7], Token::Char('>'));
+        assert_none(&mut cx, &mut tok, &mut int);
     }
 
-    fn assert_attrs(int: &MockInterner, attrs: &[[&str; 2]], index: usize) {
-        for (lhs, rhs) in int.attrs[index].iter().zip(attrs) {
-            assert_eq!(&int.strings[lhs[0]], rhs[0]);
-            assert_eq!(&int.strings[lhs[1]], rhs[1]);
-        }
+    #[test]
+    fn error_unknown_named_character_reference() {
+        let buf = AsyncStrReader::new(Cursor::new("&xx;"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('&'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 2], Token::Char('x'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 3], Token::Char('x'));
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 3],
+            ParseError::UnknownNamedCharacterReference,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 4], Token::Char(';'));
+        assert_none(&mut cx, &mut tok, &mut int);
     }
 
     #[test]
-    fn empty() {
-        let buf = AsyncStrReader::new(Cursor::new(""));
+    fn error_absence_of_digits_in_numeric_character_reference() {
+        let buf = AsyncStrReader::new(Cursor::new("&#x;"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 3],
+            ParseError::AbsenceOfDigitsInNumericCharacterReference,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('&'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 2], Token::Char('#'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 3], Token::Char('x'));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 4], Token::Char(';'));
         assert_none(&mut cx, &mut tok, &mut int);
     }
 
     #[test]
-    fn char() {
-        let buf = AsyncStrReader::new(Cursor::new("abc"));
+    fn error_control_character_reference() {
+        let buf = AsyncStrReader::new(Cursor::new("&#x80;&#0;&#x110000;"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
-        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('a'));
-        assert_token(&mut cx, &mut tok, &mut int, [1, 2], Token::Char('b'));
-        assert_token(&mut cx, &mut tok, &mut int, [1, 3], Token::Char('c'));
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 6],
+            ParseError::ControlCharacterReference,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('€'));
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 10],
+            ParseError::NullCharacterReference,
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 7],
+            Token::Char(char::REPLACEMENT_CHARACTER),
+        );
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 20],
+            ParseError::CharacterReferenceOutsideUnicodeRange,
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 11],
+            Token::Char(char::REPLACEMENT_CHARACTER),
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn comment() {
+        let buf = AsyncStrReader::new(Cursor::new("<!-- hello -->a<!---->"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Comment(1));
+        assert_token(&mut cx, &mut tok, &mut int, [1, 15], Token::Char('a'));
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 16],
+            Token::Comment(MockInterner::EMPTY_RANGE_INDEX),
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&int, " hello ", 1);
+    }
+
+    #[test]
+    fn comment_dashes_and_bang() {
+        let buf = AsyncStrReader::new(Cursor::new("<!--a-b--c---!d--!>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 19],
+            ParseError::IncorrectlyClosedComment,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Comment(1));
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&int, "a-b--c---!d", 1);
+    }
+
+    #[test]
+    fn error_nested_comment() {
+        let buf = AsyncStrReader::new(Cursor::new("<!--<!--x-->"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 8],
+            ParseError::NestedComment,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Comment(1));
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&int, "<!--x", 1);
+    }
+
+    #[test]
+    fn error_abrupt_closing_of_empty_comment() {
+        let buf = AsyncStrReader::new(Cursor::new("<!-->"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 5],
+            ParseError::AbruptClosingOfEmptyComment,
+        );
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::Comment(MockInterner::EMPTY_RANGE_INDEX),
+        );
+        assert_none(&mut cx, &mut tok, &mut int);
+    }
+
+    #[test]
+    fn error_eof_in_comment() {
+        let buf = AsyncStrReader::new(Cursor::new("<!--abc"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_pending(&mut cx, &mut tok, &mut int);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 7],
+            ParseError::EofInComment,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Comment(1));
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&int, "abc", 1);
+    }
+
+    #[test]
+    fn error_incorrectly_opened_comment() {
+        let buf = AsyncStrReader::new(Cursor::new("<!-x><!DOC>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 3],
+            ParseError::IncorrectlyOpenedComment,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Comment(1));
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 10],
+            ParseError::IncorrectlyOpenedComment,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 6], Token::Comment(2));
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&int, "-x", 1);
+        assert_str(&int, "DOC", 2);
+    }
+
+    #[test]
+    fn error_unexpected_question_mark_instead_of_tag_name() {
+        let buf = AsyncStrReader::new(Cursor::new("<?xml?>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            ParseError::UnexpectedQuestionMarkInsteadOfTagName,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Comment(1));
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&int, "?xml?", 1);
+    }
+
+    #[test]
+    fn doctype() {
+        let buf = AsyncStrReader::new(Cursor::new("<!DOCTYPE HTML>"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 1],
+            Token::DocType {
+                name: Some(1),
+                public_id: None,
+                system_id: None,
+                force_quirks: false,
+            },
+        );
         assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&int, "html", 1);
     }
 
     #[test]
-    fn start_tag() {
-        let buf = AsyncStrReader::new(Cursor::new("<hello>"));
+    fn doctype_public_system() {
+        let buf = AsyncStrReader::new(Cursor::new(
+            "<!doctype html Public \"-//W3C//DTD HTML 4.01//EN\" 'http://www.w3.org/TR/html4/strict.dtd'>",
+        ));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
@@ -826,19 +2945,24 @@ mod tests {
             &mut tok,
             &mut int,
             [1, 1],
-            Token::StartTag {
-                name: 1,
-                attrs: MockInterner::EMPTY_RANGE_INDEX,
-                self_closing: false,
+            Token::DocType {
+                name: Some(1),
+                public_id: Some(2),
+                system_id: Some(3),
+                force_quirks: false,
             },
         );
         assert_none(&mut cx, &mut tok, &mut int);
-        assert_str(&mut int, "hello", 1);
+        assert_str(&int, "html", 1);
+        assert_str(&int, "-//W3C//DTD HTML 4.01//EN", 2);
+        assert_str(&int, "http://www.w3.org/TR/html4/strict.dtd", 3);
     }
 
     #[test]
-    fn end_tag() {
-        let buf = AsyncStrReader::new(Cursor::new("</hello>"));
+    fn doctype_system() {
+        let buf = AsyncStrReader::new(Cursor::new(
+            "<!DOCTYPE html SYSTEM \"about:legacy-compat\">",
+        ));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
@@ -847,323 +2971,361 @@ mod tests {
             &mut tok,
             &mut int,
             [1, 1],
-            Token::EndTag { name: 1 },
+            Token::DocType {
+                name: Some(1),
+                public_id: None,
+                system_id: Some(2),
+                force_quirks: false,
+            },
         );
         assert_none(&mut cx, &mut tok, &mut int);
-        assert_str(&mut int, "hello", 1);
+        assert_str(&int, "about:legacy-compat", 2);
     }
 
     #[test]
-    fn self_closing_tag() {
-        let buf = AsyncStrReader::new(Cursor::new("<hello/>"));
+    fn error_missing_doctype_name() {
+        let buf = AsyncStrReader::new(Cursor::new("<!DOCTYPE>"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 10],
+            ParseError::MissingDoctypeName,
+        );
         assert_token(
             &mut cx,
             &mut tok,
             &mut int,
             [1, 1],
-            Token::StartTag {
-                name: 1,
-                attrs: MockInterner::EMPTY_RANGE_INDEX,
-                self_closing: true,
+            Token::DocType {
+                name: None,
+                public_id: None,
+                system_id: None,
+                force_quirks: true,
             },
         );
         assert_none(&mut cx, &mut tok, &mut int);
-        assert_str(&mut int, "hello", 1);
     }
 
     #[test]
-    fn start_tag_attrs() {
-        let buf = AsyncStrReader::new(Cursor::new(
-            "<hello key='test'><hello key=\"test\"><hello key=test>",
-        ));
+    fn error_eof_in_doctype() {
+        let buf = AsyncStrReader::new(Cursor::new("<!DOCTYPE html PUBLIC \"foo"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
+        assert_pending(&mut cx, &mut tok, &mut int);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 26],
+            ParseError::EofInDoctype,
+        );
         assert_token(
             &mut cx,
             &mut tok,
             &mut int,
             [1, 1],
-            Token::StartTag {
-                name: 1,
-                attrs: 1,
-                self_closing: false,
+            Token::DocType {
+                name: Some(1),
+                public_id: Some(2),
+                system_id: None,
+                force_quirks: true,
             },
         );
-        assert_token(
+        assert_none(&mut cx, &mut tok, &mut int);
+        assert_str(&int, "foo", 2);
+    }
+
+    #[test]
+    fn error_invalid_character_sequence_after_doctype_name() {
+        let buf = AsyncStrReader::new(Cursor::new("<!DOCTYPE html PUBLISH>a"));
+        let mut int = MockInterner::new();
+        let mut cx = cx();
+        let mut tok = Tokenizer::new(buf);
+        assert_error(
             &mut cx,
             &mut tok,
             &mut int,
-            [1, 19],
-            Token::StartTag {
-                name: 1,
-                attrs: 1,
-                self_closing: false,
-            },
+            [1, 20],
+            ParseError::InvalidCharacterSequenceAfterDoctypeName,
         );
         assert_token(
             &mut cx,
             &mut tok,
             &mut int,
-            [1, 37],
-            Token::StartTag {
-                name: 1,
-                attrs: 1,
-                self_closing: false,
+            [1, 1],
+            Token::DocType {
+                name: Some(1),
+                public_id: None,
+                system_id: None,
+                force_quirks: true,
             },
         );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 24], Token::Char('a'));
         assert_none(&mut cx, &mut tok, &mut int);
-        assert_str(&mut int, "hello", 1);
-        assert_attrs(&mut int, &[["key", "test"]], 1);
     }
 
-    #[test]
-    fn error_unexpected_null() {
-        let buf = AsyncStrReader::new(Cursor::new("\x00"));
-        let mut int = MockInterner::new();
-        let mut cx = cx();
-        let mut tok = Tokenizer::new(buf);
-        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('\x00'));
-        assert_none(&mut cx, &mut tok, &mut int);
+    fn assert_chars<R: AsyncRead + Unpin, I: Interner>(
+        cx: &mut Context<'_>,
+        tokenizer: &mut Tokenizer<R>,
+        int: &mut I,
+        line: usize,
+        column: usize,
+        chars: &str,
+    ) {
+        for (i, c) in chars.chars().enumerate() {
+            assert_token(cx, tokenizer, int, [line, column + i], Token::Char(c));
+        }
     }
 
-    #[test]
-    fn error_eof_before_tag_name() {
-        let buf = AsyncStrReader::new(Cursor::new("<"));
-        let mut int = MockInterner::new();
-        let mut cx = cx();
-        let mut tok = Tokenizer::new(buf);
-        assert_pending(&mut cx, &mut tok, &mut int);
-        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('<'));
-        assert_none(&mut cx, &mut tok, &mut int);
+    fn assert_start_tag<R: AsyncRead + Unpin, I: Interner>(
+        cx: &mut Context<'_>,
+        tokenizer: &mut Tokenizer<R>,
+        int: &mut I,
+        name: usize,
+        state: State,
+    ) {
+        assert_token(
+            cx,
+            tokenizer,
+            int,
+            [1, 1],
+            Token::StartTag {
+                name,
+                attrs: MockInterner::EMPTY_RANGE_INDEX,
+                self_closing: false,
+            },
+        );
+        tokenizer.set_state(state);
     }
 
     #[test]
-    fn error_invalid_first_character_of_tag_name() {
-        let buf = AsyncStrReader::new(Cursor::new("<3>"));
+    fn rawtext() {
+        let buf = AsyncStrReader::new(Cursor::new("<style>a</b>&lt;</style>"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
-        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('<'));
-        assert_token(&mut cx, &mut tok, &mut int, [1, 2], Token::Char('3'));
-        assert_token(&mut cx, &mut tok, &mut int, [1, 3], Token::Char('>'));
+        assert_start_tag(&mut cx, &mut tok, &mut int, 1, State::RawText);
+        assert_chars(&mut cx, &mut tok, &mut int, 1, 8, "a</b>&lt;");
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 17],
+            Token::EndTag { name: 1 },
+        );
         assert_none(&mut cx, &mut tok, &mut int);
     }
 
     #[test]
-    fn error_missing_end_tag_name() {
-        let buf = AsyncStrReader::new(Cursor::new("</>"));
+    fn script_data_end_tag() {
+        let buf = AsyncStrReader::new(Cursor::new("<script></scrip></script >"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
-        assert_pending(&mut cx, &mut tok, &mut int);
+        assert_start_tag(&mut cx, &mut tok, &mut int, 1, State::ScriptData);
+        assert_chars(&mut cx, &mut tok, &mut int, 1, 9, "</scrip>");
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 17],
+            Token::EndTag { name: 1 },
+        );
         assert_none(&mut cx, &mut tok, &mut int);
     }
 
     #[test]
-    fn error_eof_before_tag_name_2() {
-        let buf = AsyncStrReader::new(Cursor::new("</"));
+    fn script_data_escaped() {
+        let buf = AsyncStrReader::new(Cursor::new("<script><!--<b>--></script>"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
-        assert_pending(&mut cx, &mut tok, &mut int);
-        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('<'));
-        assert_token(&mut cx, &mut tok, &mut int, [1, 2], Token::Char('/'));
+        assert_start_tag(&mut cx, &mut tok, &mut int, 1, State::ScriptData);
+        assert_chars(&mut cx, &mut tok, &mut int, 1, 9, "<!--<b>-->");
+        assert_token(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 19],
+            Token::EndTag { name: 1 },
+        );
         assert_none(&mut cx, &mut tok, &mut int);
     }
 
     #[test]
-    fn error_unexpected_null_2() {
-        let buf = AsyncStrReader::new(Cursor::new("<test\x00>"));
+    fn script_data_double_escaped() {
+        let buf = AsyncStrReader::new(Cursor::new("<script><!--<script></script>--></script>"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
+        assert_start_tag(&mut cx, &mut tok, &mut int, 1, State::ScriptData);
+        assert_chars(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            1,
+            9,
+            "<!--<script></script>-->",
+        );
         assert_token(
             &mut cx,
             &mut tok,
             &mut int,
-            [1, 1],
-            Token::StartTag {
-                name: 1,
-                attrs: MockInterner::EMPTY_RANGE_INDEX,
-                self_closing: false,
-            },
+            [1, 33],
+            Token::EndTag { name: 1 },
         );
         assert_none(&mut cx, &mut tok, &mut int);
-        assert_str(&mut int, "test�", 1);
     }
 
     #[test]
-    fn error_eof_in_tag() {
-        let buf = AsyncStrReader::new(Cursor::new("<t"));
+    fn plaintext() {
+        let buf = AsyncStrReader::new(Cursor::new("<plaintext></plaintext>"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
-        assert_pending(&mut cx, &mut tok, &mut int);
+        assert_start_tag(&mut cx, &mut tok, &mut int, 1, State::PlainText);
+        assert_chars(&mut cx, &mut tok, &mut int, 1, 12, "</plaintext>");
         assert_none(&mut cx, &mut tok, &mut int);
     }
 
     #[test]
-    fn error_unexpected_equals_sign_before_attribute_name() {
-        let buf = AsyncStrReader::new(Cursor::new("<test ==foo>"));
+    fn start_tag_attrs_after_whitespace() {
+        let buf = AsyncStrReader::new(Cursor::new("<a b c=d e>"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
-        assert_token(
-            &mut cx,
-            &mut tok,
-            &mut int,
-            [1, 1],
-            Token::StartTag {
-                name: 1,
-                attrs: 1,
-                self_closing: false,
-            },
-        );
+        let result = Pin::new(&mut tok).poll_next(&mut cx, &mut int);
+        assert!(matches!(
+            result,
+            Poll::Ready(Some((_, Ok(Token::StartTag { .. }))))
+        ));
+        if let Poll::Ready(Some((_, Ok(Token::StartTag { attrs, .. })))) = result {
+            assert_eq!(int.attrs[attrs].len(), 3);
+            assert_attrs(&int, &[["b", ""], ["c", "d"], ["e", ""]], attrs);
+        }
         assert_none(&mut cx, &mut tok, &mut int);
-        assert_str(&mut int, "test", 1);
-        assert_attrs(&mut int, &[["=", "foo"]], 1);
     }
 
     #[test]
-    fn error_unexpected_character_in_attribute_name() {
-        let buf = AsyncStrReader::new(Cursor::new("<test \"'<=foo>"));
+    fn cdata() {
+        let buf = AsyncStrReader::new(Cursor::new("<![CDATA[a]b]]]>c"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
-        assert_token(
-            &mut cx,
-            &mut tok,
-            &mut int,
-            [1, 1],
-            Token::StartTag {
-                name: 1,
-                attrs: 1,
-                self_closing: false,
-            },
-        );
+        tok.set_cdata_allowed(true);
+        assert_chars(&mut cx, &mut tok, &mut int, 1, 10, "a]b]");
+        assert_token(&mut cx, &mut tok, &mut int, [1, 17], Token::Char('c'));
         assert_none(&mut cx, &mut tok, &mut int);
-        assert_str(&mut int, "test", 1);
-        assert_attrs(&mut int, &[["\"'<", "foo"]], 1);
     }
 
     #[test]
-    fn error_eof_in_tag_2() {
-        let buf = AsyncStrReader::new(Cursor::new("<test foo="));
+    fn error_eof_in_cdata() {
+        let buf = AsyncStrReader::new(Cursor::new("<![CDATA[a]"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
+        tok.set_cdata_allowed(true);
+        assert_token(&mut cx, &mut tok, &mut int, [1, 10], Token::Char('a'));
         assert_pending(&mut cx, &mut tok, &mut int);
+        assert_token(&mut cx, &mut tok, &mut int, [1, 11], Token::Char(']'));
+        assert_error(&mut cx, &mut tok, &mut int, [1, 11], ParseError::EofInCdata);
         assert_none(&mut cx, &mut tok, &mut int);
     }
 
     #[test]
-    fn error_missing_attribute_value() {
-        let buf = AsyncStrReader::new(Cursor::new("<test foo=>"));
+    fn error_cdata_in_html_content() {
+        let buf = AsyncStrReader::new(Cursor::new("<![CDATA[a]]>"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
-        assert_token(
+        assert_error(
             &mut cx,
             &mut tok,
             &mut int,
-            [1, 1],
-            Token::StartTag {
-                name: 1,
-                attrs: 1,
-                self_closing: false,
-            },
+            [1, 9],
+            ParseError::CdataInHtmlContent,
         );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Comment(1));
         assert_none(&mut cx, &mut tok, &mut int);
-        assert_str(&mut int, "test", 1);
-        assert_attrs(&mut int, &[["foo", ""]], 1);
+        assert_str(&int, "[CDATA[a]]", 1);
     }
 
     #[test]
-    fn error_unexpected_null_3() {
-        let buf = AsyncStrReader::new(Cursor::new("<test foo=\"\x00\">"));
+    fn error_duplicate_attribute() {
+        let buf = AsyncStrReader::new(Cursor::new("<test a=1 b=2 a=3>"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
-        assert_token(
+        assert_error(
             &mut cx,
             &mut tok,
             &mut int,
-            [1, 1],
-            Token::StartTag {
-                name: 1,
-                attrs: 1,
-                self_closing: false,
-            },
+            [1, 16],
+            ParseError::DuplicateAttribute,
         );
-        assert_none(&mut cx, &mut tok, &mut int);
-        assert_str(&mut int, "test", 1);
-        assert_attrs(&mut int, &[["foo", "�"]], 1);
-    }
-
-    #[test]
-    fn error_eof_in_tag_3() {
-        let buf = AsyncStrReader::new(Cursor::new("<test foo=\""));
-        let mut int = MockInterner::new();
-        let mut cx = cx();
-        let mut tok = Tokenizer::new(buf);
-        assert_pending(&mut cx, &mut tok, &mut int);
+        let result = Pin::new(&mut tok).poll_next(&mut cx, &mut int);
+        assert!(matches!(
+            result,
+            Poll::Ready(Some((_, Ok(Token::StartTag { .. }))))
+        ));
+        if let Poll::Ready(Some((_, Ok(Token::StartTag { attrs, .. })))) = result {
+            assert_eq!(int.attrs[attrs].len(), 2);
+            assert_attrs(&int, &[["a", "1"], ["b", "2"]], attrs);
+        }
         assert_none(&mut cx, &mut tok, &mut int);
     }
 
     #[test]
-    fn error_missing_whitespace_between_attributes() {
-        let buf = AsyncStrReader::new(Cursor::new("<test foo=\"bar\"bar=\"baz\">"));
+    fn error_end_tag_with_attributes() {
+        let buf = AsyncStrReader::new(Cursor::new("</test a=1>"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 11],
+            ParseError::EndTagWithAttributes,
+        );
         assert_token(
             &mut cx,
             &mut tok,
             &mut int,
             [1, 1],
-            Token::StartTag {
-                name: 1,
-                attrs: 1,
-                self_closing: false,
-            },
+            Token::EndTag { name: 1 },
         );
         assert_none(&mut cx, &mut tok, &mut int);
-        assert_str(&mut int, "test", 1);
-        assert_attrs(&mut int, &[["foo", "bar"], ["bar", "baz"]], 1);
-    }
-
-    #[test]
-    fn error_eof_in_tag_4() {
-        let buf = AsyncStrReader::new(Cursor::new("</test"));
-        let mut int = MockInterner::new();
-        let mut cx = cx();
-        let mut tok = Tokenizer::new(buf);
-        assert_pending(&mut cx, &mut tok, &mut int);
-        assert_none(&mut cx, &mut tok, &mut int);
     }
 
     #[test]
-    fn error_unexpected_solidus_in_tag() {
-        let buf = AsyncStrReader::new(Cursor::new("<test//>"));
+    fn error_control_character_in_input_stream() {
+        let buf = AsyncStrReader::new(Cursor::new("a\x01\u{FFFF}"));
         let mut int = MockInterner::new();
         let mut cx = cx();
         let mut tok = Tokenizer::new(buf);
-        assert_token(
+        assert_token(&mut cx, &mut tok, &mut int, [1, 1], Token::Char('a'));
+        assert_error(
             &mut cx,
             &mut tok,
             &mut int,
-            [1, 1],
-            Token::StartTag {
-                name: 1,
-                attrs: MockInterner::EMPTY_RANGE_INDEX,
-                self_closing: true,
-            },
+            [1, 2],
+            ParseError::ControlCharacterInInputStream,
+        );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 2], Token::Char('\x01'));
+        assert_error(
+            &mut cx,
+            &mut tok,
+            &mut int,
+            [1, 3],
+            ParseError::NoncharacterInInputStream,
         );
+        assert_token(&mut cx, &mut tok, &mut int, [1, 3], Token::Char('\u{FFFF}'));
         assert_none(&mut cx, &mut tok, &mut int);
-        assert_str(&mut int, "test", 1);
     }
 }
@@ -8,12 +8,13 @@
 use std::{future, path::PathBuf, pin::Pin};
 
 use clap::Parser;
-use dom::Dom;
+use dom::{Dom, ROOT_NODE_ID};
 use html::ParseEvent;
 use io::AsyncStrReader;
 use smol::fs::File;
 
 mod asyncro;
+mod atoms;
 mod dom;
 mod html;
 mod io;
@@ -25,6 +26,14 @@ mod uri;
 struct Args {
     /// An optional document location
     location: Option<PathBuf>, // TODO: Uri
+
+    /// Print the document as HTML instead of the debug tree
+    #[arg(long, conflicts_with = "html5lib")]
+    html: bool,
+
+    /// Print the tree in the html5lib test format instead of the debug tree
+    #[arg(long)]
+    html5lib: bool,
 }
 
 fn main() -> std::io::Result<()> {
@@ -40,10 +49,17 @@ fn main() -> std::io::Result<()> {
                 ParseEvent::Fatal(_, err) => {
                     return Err(std::io::Error::new(std::io::ErrorKind::Other, err))
                 }
+                ParseEvent::Error(loc, err) => eprintln!("{}:{}: {err}", loc.line, loc.column),
                 _ => {}
             }
         }
-        dom.write_tree(&mut std::io::stdout())?;
+        if args.html {
+            html::serialize(&dom, ROOT_NODE_ID, &mut std::io::stdout())?;
+        } else if args.html5lib {
+            dom.write_html5lib_tree(&mut std::io::stdout())?;
+        } else {
+            dom.write_tree(&mut std::io::stdout())?;
+        }
         Ok(())
     })
     //    let mut win = Window::new(